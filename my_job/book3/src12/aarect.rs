@@ -1,9 +1,11 @@
 use super::aabb::AABB;
+use crate::material::{emitted_power, is_emissive};
 use crate::vec3::{Point3, Vec3};
 use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
 use rand::Rng;
 use std::f64::INFINITY;
 use std::sync::Arc;
+#[derive(Clone)]
 pub struct xy_rect {
     pub x0: f64,
     pub x1: f64,
@@ -25,6 +27,41 @@ impl xy_rect {
     }
 }
 impl Hittable for xy_rect {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (Vec3::new(0., 0., 1.), 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
+            let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+            let dis_sqr = rec.t * rec.t * v.length_squared();
+            let cos = (Vec3::dot(v, rec.normal) / v.length()).abs();
+            dis_sqr / (cos * area)
+        } else {
+            0.
+        }
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let random_point = Point3::new(
+            rng.gen_range(self.x0..self.x1),
+            rng.gen_range(self.y0..self.y1),
+            self.k,
+        );
+        return random_point - o;
+    }
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let t = (self.k - r.orig.z) / r.dir.z;
         if t < t_min || t > t_max {
@@ -49,6 +86,13 @@ impl Hittable for xy_rect {
         );
 
         rec.set_face_normal(r, outward_normal);
+        let zero = Vec3::new(0., 0., 0.);
+        rec.set_derivatives(
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., self.y1 - self.y0, 0.),
+            zero,
+            zero,
+        );
 
         Some(rec)
     }
@@ -61,6 +105,7 @@ impl Hittable for xy_rect {
     }
 }
 
+#[derive(Clone)]
 pub struct xz_rect {
     pub x0: f64,
     pub x1: f64,
@@ -82,6 +127,22 @@ impl xz_rect {
     }
 }
 impl Hittable for xz_rect {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (Vec3::new(0., 1., 0.), 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
     fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
         if let Some(rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
             let area = (self.x1 - self.x0) * (self.z1 - self.z0);
@@ -125,6 +186,13 @@ impl Hittable for xz_rect {
         );
 
         rec.set_face_normal(r, outward_normal);
+        let zero = Vec3::new(0., 0., 0.);
+        rec.set_derivatives(
+            Vec3::new(self.x1 - self.x0, 0., 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+            zero,
+            zero,
+        );
 
         Some(rec)
     }
@@ -137,6 +205,7 @@ impl Hittable for xz_rect {
     }
 }
 
+#[derive(Clone)]
 pub struct yz_rect {
     pub y0: f64,
     pub y1: f64,
@@ -158,6 +227,41 @@ impl yz_rect {
     }
 }
 impl Hittable for yz_rect {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (Vec3::new(1., 0., 0.), 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        (self.y1 - self.y0) * (self.z1 - self.z0)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        if let Some(rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
+            let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+            let dis_sqr = rec.t * rec.t * v.length_squared();
+            let cos = (Vec3::dot(v, rec.normal) / v.length()).abs();
+            dis_sqr / (cos * area)
+        } else {
+            0.
+        }
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let random_point = Point3::new(
+            self.k,
+            rng.gen_range(self.y0..self.y1),
+            rng.gen_range(self.z0..self.z1),
+        );
+        return random_point - o;
+    }
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let t = (self.k - r.orig.x) / r.dir.x;
         if t < t_min || t > t_max {
@@ -182,6 +286,13 @@ impl Hittable for yz_rect {
         );
 
         rec.set_face_normal(r, outward_normal);
+        let zero = Vec3::new(0., 0., 0.);
+        rec.set_derivatives(
+            Vec3::new(0., self.y1 - self.y0, 0.),
+            Vec3::new(0., 0., self.z1 - self.z0),
+            zero,
+            zero,
+        );
 
         Some(rec)
     }
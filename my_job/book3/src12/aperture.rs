@@ -0,0 +1,233 @@
+use crate::vec3::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+//光圈形状，焦外的光斑就是这个形状
+//采样在形状里按面积均匀，半径为1，相机再乘上光圈半径
+
+//灰度图做的光圈，亮的地方透光，按亮度采样
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+    row_cdf: Vec<f64>,        //每一行的累计亮度
+    pixel_cdf: Vec<Vec<f64>>, //每一行里每个像素的累计亮度
+}
+
+impl ApertureMask {
+    pub fn new(filename: &str) -> Self {
+        let img = image::open(filename).expect("failed").to_luma8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut pixel_cdf = Vec::with_capacity(height);
+        let mut values = Vec::with_capacity(width * height);
+        let mut total = 0.;
+        for y in 0..height {
+            let mut row = Vec::with_capacity(width);
+            let mut sum = 0.;
+            for x in 0..width {
+                let value = img.get_pixel(x as u32, y as u32)[0] as f64 / 255.;
+                values.push(value);
+                sum += value;
+                row.push(sum);
+            }
+            total += sum;
+            row_cdf.push(total);
+            pixel_cdf.push(row);
+        }
+        if total <= 0. {
+            panic!("aperture mask {} is completely black", filename);
+        }
+        Self {
+            width,
+            height,
+            values,
+            row_cdf,
+            pixel_cdf,
+        }
+    }
+    //长边对到[-1,1]，图片上方是光圈的上方
+    fn sample(&self) -> (f64, f64) {
+        let mut rng = rand::thread_rng();
+        let total = self.row_cdf[self.height - 1];
+        let pick = rng.gen_range(0.0..total);
+        let y = self
+            .row_cdf
+            .partition_point(|c| *c <= pick)
+            .min(self.height - 1);
+        let row = &self.pixel_cdf[y];
+        let pick = rng.gen_range(0.0..row[self.width - 1]);
+        let x = row.partition_point(|c| *c <= pick).min(self.width - 1);
+        let scale = 2. / self.width.max(self.height) as f64;
+        let px = (x as f64 + rng.gen::<f64>() - self.width as f64 / 2.) * scale;
+        let py = (self.height as f64 / 2. - y as f64 - rng.gen::<f64>()) * scale;
+        (px, py)
+    }
+    fn value(&self, px: f64, py: f64) -> f64 {
+        let scale = self.width.max(self.height) as f64 / 2.;
+        let x = (px * scale + self.width as f64 / 2.).floor();
+        let y = (self.height as f64 / 2. - py * scale).floor();
+        if x < 0. || y < 0. || x >= self.width as f64 || y >= self.height as f64 {
+            return 0.;
+        }
+        self.values[y as usize * self.width + x as usize]
+    }
+}
+
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circle,
+    Polygon { blades: u32, rotation: f64 }, //rotation是度数
+    Mask(Arc<ApertureMask>),
+}
+
+#[derive(Clone)]
+pub struct Aperture {
+    pub shape: ApertureShape,
+    pub squeeze: f64, //变形宽银幕镜头的压缩比，1是不压缩，2的时候光斑是竖着的椭圆
+}
+
+impl Aperture {
+    pub fn circle() -> Self {
+        Self {
+            shape: ApertureShape::Circle,
+            squeeze: 1.,
+        }
+    }
+    pub fn polygon(blades: u32, rotation: f64) -> Self {
+        if blades < 3 {
+            panic!("a polygonal aperture needs at least 3 blades");
+        }
+        Self {
+            shape: ApertureShape::Polygon { blades, rotation },
+            squeeze: 1.,
+        }
+    }
+    pub fn mask(filename: &str) -> Self {
+        Self {
+            shape: ApertureShape::Mask(Arc::new(ApertureMask::new(filename))),
+            squeeze: 1.,
+        }
+    }
+    pub fn sample(&self) -> Vec3 {
+        let (x, y) = match &self.shape {
+            ApertureShape::Circle => {
+                let p = Vec3::random_in_unit_disk();
+                (p.x, p.y)
+            }
+            ApertureShape::Polygon { blades, rotation } => {
+                //正多边形切成blades个一样大的三角形，挑一个再在里面均匀采样
+                let mut rng = rand::thread_rng();
+                let k = rng.gen_range(0..*blades) as f64;
+                let step = 2. * PI / *blades as f64;
+                let a0 = rotation.to_radians() + k * step;
+                let a1 = a0 + step;
+                let r1: f64 = rng.gen();
+                let r2: f64 = rng.gen();
+                let su = r1.sqrt();
+                let b1 = su * (1. - r2);
+                let b2 = su * r2;
+                (b1 * a0.cos() + b2 * a1.cos(), b1 * a0.sin() + b2 * a1.sin())
+            }
+            ApertureShape::Mask(mask) => mask.sample(),
+        };
+        //x方向压扁、y方向拉长，面积不变
+        let s = self.squeeze.sqrt();
+        Vec3::new(x / s, y * s, 0.)
+    }
+    //单位半径的光圈上这一点透过多少光，真实镜头在光圈那一面用
+    pub fn transmittance(&self, x: f64, y: f64) -> f64 {
+        let s = self.squeeze.sqrt();
+        let (x, y) = (x * s, y / s);
+        match &self.shape {
+            ApertureShape::Circle => {
+                if x * x + y * y <= 1. {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            ApertureShape::Polygon { blades, rotation } => {
+                //点所在的那个三角形，看它在边的哪一侧
+                let step = 2. * PI / *blades as f64;
+                let angle = (y.atan2(x) - rotation.to_radians()).rem_euclid(2. * PI);
+                let mid = rotation.to_radians() + ((angle / step).floor() + 0.5) * step;
+                if x * mid.cos() + y * mid.sin() <= (step / 2.).cos() {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            ApertureShape::Mask(mask) => mask.value(x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_samples() {
+        let n = 200000;
+        for &(blades, rotation, squeeze) in [(3, 0., 1.), (6, 15., 1.), (5, 90., 2.)].iter() {
+            let mut aperture = Aperture::polygon(blades, rotation);
+            aperture.squeeze = squeeze;
+            let s = f64::sqrt(squeeze);
+            let mut inner = 0;
+            for _ in 0..n {
+                let p = aperture.sample();
+                assert_eq!(aperture.transmittance(p.x, p.y), 1.);
+                let (x, y) = (p.x * s, p.y / s);
+                if x * x + y * y < 0.25 {
+                    inner += 1;
+                }
+            }
+            //按面积均匀：落在半径0.5的圆里的比例是两个面积之比
+            let step = 2. * PI / blades as f64;
+            let area = blades as f64 / 2. * step.sin();
+            let expected = PI * 0.25 / area;
+            let got = inner as f64 / n as f64;
+            assert!((got - expected).abs() < 0.01, "{} != {}", got, expected);
+            //顶点在光圈上，边的中点往外一点就挡住了
+            let vertex = rotation.to_radians();
+            let (x, y) = (0.999 * vertex.cos() / s, 0.999 * vertex.sin() * s);
+            assert_eq!(aperture.transmittance(x, y), 1.);
+            let mid = vertex + step / 2.;
+            let r = (step / 2.).cos() + 0.01;
+            let (x, y) = (r * mid.cos() / s, r * mid.sin() * s);
+            assert_eq!(aperture.transmittance(x, y), 0.);
+        }
+    }
+
+    #[test]
+    fn mask_samples() {
+        //4x2的图：左上角全亮，右下角三分之一亮，别的都是黑的
+        let path = std::env::temp_dir().join(format!("aperture_mask_{}.png", std::process::id()));
+        image::GrayImage::from_fn(4, 2, |x, y| match (x, y) {
+            (0, 0) => image::Luma([255]),
+            (3, 1) => image::Luma([85]),
+            _ => image::Luma([0]),
+        })
+        .save(&path)
+        .unwrap();
+        let aperture = Aperture::mask(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let n = 100000;
+        let mut bright = 0;
+        for _ in 0..n {
+            let p = aperture.sample();
+            //长边对到[-1,1]，一个像素宽0.5
+            let top_left = p.x < -0.5 && p.y > 0.;
+            let bottom_right = p.x > 0.5 && p.y < 0.;
+            assert!(top_left || bottom_right, "{} {}", p.x, p.y);
+            assert!(aperture.transmittance(p.x, p.y) > 0.);
+            if top_left {
+                bright += 1;
+            }
+        }
+        let got = bright as f64 / n as f64;
+        assert!((got - 0.75).abs() < 0.01, "{}", got);
+        assert_eq!(aperture.transmittance(0., 0.25), 0.);
+    }
+}
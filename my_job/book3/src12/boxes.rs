@@ -1,15 +1,16 @@
 use super::aabb::AABB;
 use crate::aarect::{xy_rect, xz_rect, yz_rect};
-use crate::hittable::HittableList;
-use crate::vec3::Point3;
+use crate::hittable::{area_pdf_value, flip_face, HittableList};
+use crate::vec3::{Point3, Vec3};
 use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
-
+use rand::Rng;
 use std::sync::Arc;
 
 pub struct Box {
     min: Point3,
     max: Point3,
     sides: HittableList,
+    area: f64,
 }
 
 impl Box {
@@ -23,14 +24,15 @@ impl Box {
             p1.z,
             ptr.clone(),
         )));
-        sides.add(Arc::new(xy_rect::new(
+        //小的那三个面翻过来，让法向量都朝外，这样front_face才能分清进出
+        sides.add(Arc::new(flip_face::new(Arc::new(xy_rect::new(
             p0.x,
             p1.x,
             p0.y,
             p1.y,
             p0.z,
             ptr.clone(),
-        )));
+        )))));
 
         sides.add(Arc::new(xz_rect::new(
             p0.x,
@@ -40,14 +42,14 @@ impl Box {
             p1.y,
             ptr.clone(),
         )));
-        sides.add(Arc::new(xz_rect::new(
+        sides.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
             p0.x,
             p1.x,
             p0.z,
             p1.z,
             p0.y,
             ptr.clone(),
-        )));
+        )))));
 
         sides.add(Arc::new(yz_rect::new(
             p0.y,
@@ -57,12 +59,16 @@ impl Box {
             p1.x,
             ptr.clone(),
         )));
-        sides.add(Arc::new(yz_rect::new(p0.y, p1.y, p0.z, p1.z, p0.x, ptr)));
+        sides.add(Arc::new(flip_face::new(Arc::new(yz_rect::new(
+            p0.y, p1.y, p0.z, p1.z, p0.x, ptr,
+        )))));
 
+        let d = p1 - p0;
         Self {
             min: p0,
             max: p1,
             sides,
+            area: 2. * (d.x * d.y + d.x * d.z + d.y * d.z),
         }
     }
 }
@@ -74,4 +80,36 @@ impl Hittable for Box {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         self.sides.hit(r, t_min, t_max)
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        self.sides.lights()
+    }
+    fn power(&self) -> f64 {
+        self.sides.power()
+    }
+    fn area(&self) -> f64 {
+        self.area
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        //按面积选一个面，sides里是前后、上下、左右两两一对
+        let d = self.max - self.min;
+        let areas = [
+            d.x * d.y,
+            d.x * d.y,
+            d.x * d.z,
+            d.x * d.z,
+            d.y * d.z,
+            d.y * d.z,
+        ];
+        let mut pick = rand::thread_rng().gen_range(0.0..self.area);
+        for (i, a) in areas.iter().enumerate() {
+            if pick < *a {
+                return self.sides.objects[i].random(o);
+            }
+            pick -= a;
+        }
+        self.sides.objects[5].random(o)
+    }
 }
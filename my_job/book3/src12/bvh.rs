@@ -35,6 +35,16 @@ impl Hittable for BvhNode {
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(self.Box)
     }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        let left = self.left.as_ref().unwrap();
+        let right = self.right.as_ref().unwrap();
+        let mut lights = left.lights();
+        //只有一个物体的时候左右是同一个
+        if !Arc::ptr_eq(left, right) {
+            lights.append(&mut right.lights());
+        }
+        lights
+    }
 }
 #[warn(unused_assignments)]
 impl BvhNode {
@@ -1,8 +1,47 @@
-use super::ray::Ray;
+use super::ray::{Ray, RayDifferential};
 use super::vec3::{Point3, Vec3};
+use crate::aperture::Aperture;
+use crate::exposure::{Exposure, PhysicalCamera};
+use crate::realistic_camera::RealisticCamera;
 use rand::Rng;
-#[derive(Copy, Clone)]
-pub struct Camera {
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+//s、t是胶片上的坐标，从左下角(0,0)到右上角(1,1)
+//返回光线和它的权重，这个像素拍不到东西（鱼眼圆外面、被镜头挡住）就是None
+pub trait Camera: Send + Sync {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)>;
+}
+
+//没有解析式的相机用有限差分求微分，ray(s, t)是镜头上取同一个点时这个胶片坐标打出去的光线，
+//结果按s、t各挪1放大，和别的相机一样
+pub fn finite_differential(
+    s: f64,
+    t: f64,
+    ray: impl Fn(f64, f64) -> Option<(Point3, Vec3)>,
+) -> Option<RayDifferential> {
+    let eps = 1e-4;
+    let (o, d) = ray(s, t)?;
+    let (ox, dx) = ray(s + eps, t)?;
+    let (oy, dy) = ray(s, t + eps)?;
+    Some(RayDifferential {
+        rx_orig: o + (ox - o) / eps,
+        rx_dir: d + (dx - d) / eps,
+        ry_orig: o + (oy - o) / eps,
+        ry_dir: d + (dy - d) / eps,
+    })
+}
+
+pub fn random_time(time0: f64, time1: f64) -> f64 {
+    if time1 > time0 {
+        rand::thread_rng().gen_range(time0..time1)
+    } else {
+        time0
+    }
+}
+
+#[derive(Clone)]
+pub struct PerspectiveCamera {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
@@ -11,12 +50,13 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    aperture_shape: Aperture,
     time0: f64,
     time1: f64,
 }
 
 #[allow(clippy::too_many_arguments)]
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -29,12 +69,11 @@ impl Camera {
         time1: f64,
     ) -> Self {
         let theta = vfov.to_radians();
-        let mut h = (theta / 2.0).tan();
+        let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
-        let focal_length = 1.0;
         let w = Vec3::unit_vector(lookfrom - lookat);
-        let mut u = Vec3::unit_vector(Vec3::cross(vup, w));
+        let u = Vec3::unit_vector(Vec3::cross(vup, w));
         let v = Vec3::cross(w, u);
         let origin = lookfrom;
         let horizontal = u * viewport_width * focus_dist;
@@ -50,20 +89,619 @@ impl Camera {
             v,
             w,
             lens_radius,
+            aperture_shape: Aperture::circle(),
             time0,
             time1,
         }
     }
+    pub fn set_aperture_shape(&mut self, aperture_shape: Aperture) {
+        self.aperture_shape = aperture_shape;
+    }
+}
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let mut rng = rand::thread_rng();
-        let rd = Vec3::random_in_unit_disk() * self.lens_radius;
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let rd = self.aperture_shape.sample() * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
 
-        return Ray::new(
+        let mut r = Ray::new(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
-            rng.gen_range(self.time0..self.time1),
+            random_time(self.time0, self.time1),
         );
+        //镜头上还是同一个点，对焦平面上的点挪一下
+        r.diff = Some(RayDifferential {
+            rx_orig: r.orig,
+            rx_dir: r.dir + self.horizontal,
+            ry_orig: r.orig,
+            ry_dir: r.dir + self.vertical,
+        });
+        Some((r, 1.))
+    }
+}
+
+//正交相机，光线都平行于视线，view_height是画面的高度（场景单位）
+#[derive(Clone)]
+pub struct OrthographicCamera {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    lens_radius: f64,
+    aperture_shape: Aperture,
+    focus_dist: f64,
+    time0: f64,
+    time1: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl OrthographicCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        view_height: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let w = Vec3::unit_vector(lookfrom - lookat);
+        let u = Vec3::unit_vector(Vec3::cross(vup, w));
+        let v = Vec3::cross(w, u);
+        let horizontal = u * view_height * aspect_ratio;
+        let vertical = v * view_height;
+        Self {
+            lower_left_corner: lookfrom - horizontal / 2. - vertical / 2.,
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            lens_radius: aperture / 2.,
+            aperture_shape: Aperture::circle(),
+            focus_dist,
+            time0,
+            time1,
+        }
+    }
+    pub fn set_aperture_shape(&mut self, aperture_shape: Aperture) {
+        self.aperture_shape = aperture_shape;
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let pixel = self.lower_left_corner + self.horizontal * s + self.vertical * t;
+        let rd = self.aperture_shape.sample() * self.lens_radius;
+        let offset = self.u * rd.x + self.v * rd.y;
+        //对焦平面上的点不受光圈影响
+        let focus_point = pixel - self.w * self.focus_dist;
+        let mut r = Ray::new(
+            pixel + offset,
+            focus_point - pixel - offset,
+            random_time(self.time0, self.time1),
+        );
+        r.diff = Some(RayDifferential {
+            rx_orig: r.orig + self.horizontal,
+            rx_dir: r.dir,
+            ry_orig: r.orig + self.vertical,
+            ry_dir: r.dir,
+        });
+        Some((r, 1.))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum FisheyeMapping {
+    Equidistant, //r和入射角成正比
+    Equisolid,   //r和sin(θ/2)成正比，面积和立体角成正比
+}
+
+//圆形鱼眼，fov是画面短边对应的视角（度），可以超过180
+#[derive(Copy, Clone)]
+pub struct FisheyeCamera {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    mapping: FisheyeMapping,
+    theta_max: f64,
+    aspect_ratio: f64,
+    time0: f64,
+    time1: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl FisheyeCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        fov: f64,
+        mapping: FisheyeMapping,
+        aspect_ratio: f64,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let w = Vec3::unit_vector(lookfrom - lookat);
+        let u = Vec3::unit_vector(Vec3::cross(vup, w));
+        let v = Vec3::cross(w, u);
+        Self {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            mapping,
+            theta_max: fov.to_radians() / 2.,
+            aspect_ratio,
+            time0,
+            time1,
+        }
+    }
+}
+
+impl FisheyeCamera {
+    fn direction(&self, s: f64, t: f64) -> Option<Vec3> {
+        //短边上半径是1
+        let (mut x, mut y) = ((s - 0.5) * 2., (t - 0.5) * 2.);
+        if self.aspect_ratio >= 1. {
+            x *= self.aspect_ratio;
+        } else {
+            y /= self.aspect_ratio;
+        }
+        let r = (x * x + y * y).sqrt();
+        if r > 1. {
+            return None;
+        }
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.theta_max,
+            FisheyeMapping::Equisolid => {
+                2. * (r * (self.theta_max / 2.).sin()).clamp(-1., 1.).asin()
+            }
+        };
+        let (cos_phi, sin_phi) = if r > 0. { (x / r, y / r) } else { (1., 0.) };
+        Some(-self.w * theta.cos() + (self.u * cos_phi + self.v * sin_phi) * theta.sin())
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let dir = self.direction(s, t)?;
+        let mut r = Ray::new(self.origin, dir, random_time(self.time0, self.time1));
+        //圆的边上挪出去就没有微分了
+        r.diff = finite_differential(s, t, |s, t| Some((self.origin, self.direction(s, t)?)));
+        Some((r, 1.))
+    }
+}
+
+//360°全景，s对应经度，t对应纬度，画面中心是lookat的方向
+#[derive(Copy, Clone)]
+pub struct EquirectangularCamera {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    time0: f64,
+    time1: f64,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, time0: f64, time1: f64) -> Self {
+        let up = Vec3::unit_vector(vup);
+        let forward = lookat - lookfrom;
+        //视线投影到水平面上，全景图的地平线是水平的
+        let w = -Vec3::unit_vector(forward - up * Vec3::dot(forward, up));
+        let u = Vec3::cross(up, w);
+        Self {
+            origin: lookfrom,
+            u,
+            v: up,
+            w,
+            time0,
+            time1,
+        }
+    }
+}
+
+impl EquirectangularCamera {
+    fn direction(&self, s: f64, t: f64) -> Vec3 {
+        let phi = (s - 0.5) * 2. * PI;
+        let theta = (t - 0.5) * PI;
+        (-self.w * phi.cos() + self.u * phi.sin()) * theta.cos() + self.v * theta.sin()
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let mut r = Ray::new(
+            self.origin,
+            self.direction(s, t),
+            random_time(self.time0, self.time1),
+        );
+        r.diff = finite_differential(s, t, |s, t| Some((self.origin, self.direction(s, t))));
+        Some((r, 1.))
+    }
+}
+
+//移轴相机：shift平移镜头（画面在胶片平面上挪，透视不变，拍楼不会歪），
+//tilt让对焦平面绕着相机的水平轴转（度），正的时候对焦平面下半部分离相机更近
+#[derive(Clone)]
+pub struct TiltShiftCamera {
+    origin: Point3,
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    focus_point: Point3,
+    focus_normal: Vec3,
+    lens_radius: f64,
+    aperture_shape: Aperture,
+    time0: f64,
+    time1: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl TiltShiftCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+        tilt: f64,
+        shift: (f64, f64),
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let h = (vfov.to_radians() / 2.).tan();
+        let viewport_height = 2. * h;
+        let viewport_width = aspect_ratio * viewport_height;
+        let w = Vec3::unit_vector(lookfrom - lookat);
+        let u = Vec3::unit_vector(Vec3::cross(vup, w));
+        let v = Vec3::cross(w, u);
+        let horizontal = u * viewport_width;
+        let vertical = v * viewport_height;
+        //shift以画面宽高为单位
+        let lower_left_corner = lookfrom - horizontal / 2. - vertical / 2. - w
+            + horizontal * shift.0
+            + vertical * shift.1;
+        let tilt = tilt.to_radians();
+        Self {
+            origin: lookfrom,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            focus_point: lookfrom - w * focus_dist,
+            focus_normal: w * tilt.cos() + v * tilt.sin(),
+            lens_radius: aperture / 2.,
+            aperture_shape: Aperture::circle(),
+            time0,
+            time1,
+        }
+    }
+    pub fn set_aperture_shape(&mut self, aperture_shape: Aperture) {
+        self.aperture_shape = aperture_shape;
+    }
+}
+
+impl TiltShiftCamera {
+    //镜头上offset那一点打向(s,t)对焦的地方的光线
+    fn ray_through(&self, s: f64, t: f64, offset: Vec3) -> (Point3, Vec3) {
+        //主光线（过镜头中心）和斜的对焦平面的交点就是这个像素对焦的地方
+        let d = self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin;
+        let denom = Vec3::dot(self.focus_normal, d);
+        let k = Vec3::dot(self.focus_normal, self.focus_point - self.origin) / denom;
+        if !(k > 0. && k.is_finite()) {
+            //这个方向和对焦平面不相交，焦点在无穷远
+            return (self.origin + offset, d);
+        }
+        let target = self.origin + d * k;
+        (self.origin + offset, target - self.origin - offset)
+    }
+}
+
+impl Camera for TiltShiftCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let rd = self.aperture_shape.sample() * self.lens_radius;
+        let offset = self.u * rd.x + self.v * rd.y;
+        let (orig, dir) = self.ray_through(s, t, offset);
+        let mut r = Ray::new(orig, dir, random_time(self.time0, self.time1));
+        r.diff = finite_differential(s, t, |s, t| Some(self.ray_through(s, t, offset)));
+        Some((r, 1.))
+    }
+}
+
+#[derive(Clone)]
+pub enum Projection {
+    Perspective,
+    Orthographic {
+        view_height: f64,
+    },
+    Fisheye {
+        fov: f64,
+        mapping: FisheyeMapping,
+    },
+    Equirectangular,
+    TiltShift {
+        tilt: f64,
+        shift: (f64, f64),
+    },
+    //真实镜头，lookfrom是胶片的位置，见RealisticCamera::new
+    Realistic {
+        lens_file: String,
+        aperture_diameter: f64,
+        film_diagonal: f64,
+        mm_per_unit: f64,
+    },
+}
+
+//场景里描述相机用的，main里按画面比例生成真正的相机
+#[derive(Clone)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub aperture_shape: Aperture,
+    pub focus_dist: f64,
+    pub time0: f64,
+    pub time1: f64,
+    pub projection: Projection,
+    pub exposure: Option<Exposure>, //设了的话光圈由f值算，亮度和快门时间也由它管
+}
+
+impl CameraSettings {
+    pub fn new(lookfrom: Point3, lookat: Point3, vfov: f64) -> Self {
+        Self {
+            lookfrom,
+            lookat,
+            vup: Vec3::new(0., 1., 0.),
+            vfov,
+            aperture: 0.,
+            aperture_shape: Aperture::circle(),
+            focus_dist: (lookfrom - lookat).length(),
+            time0: 0.,
+            time1: 1.,
+            projection: Projection::Perspective,
+            exposure: None,
+        }
+    }
+    pub fn build(&self, aspect_ratio: f64) -> Arc<dyn Camera> {
+        let aperture = match &self.exposure {
+            Some(exposure) => exposure.aperture(),
+            None => self.aperture,
+        };
+        let camera: Arc<dyn Camera> = match &self.projection {
+            Projection::Perspective => {
+                let mut cam = PerspectiveCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    self.vfov,
+                    aspect_ratio,
+                    aperture,
+                    self.focus_dist,
+                    self.time0,
+                    self.time1,
+                );
+                cam.set_aperture_shape(self.aperture_shape.clone());
+                Arc::new(cam)
+            }
+            Projection::Orthographic { view_height } => {
+                let mut cam = OrthographicCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    *view_height,
+                    aspect_ratio,
+                    aperture,
+                    self.focus_dist,
+                    self.time0,
+                    self.time1,
+                );
+                cam.set_aperture_shape(self.aperture_shape.clone());
+                Arc::new(cam)
+            }
+            Projection::Fisheye { fov, mapping } => Arc::new(FisheyeCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+                *fov,
+                *mapping,
+                aspect_ratio,
+                self.time0,
+                self.time1,
+            )),
+            Projection::Equirectangular => Arc::new(EquirectangularCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+                self.time0,
+                self.time1,
+            )),
+            Projection::TiltShift { tilt, shift } => {
+                let mut cam = TiltShiftCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    self.vfov,
+                    aspect_ratio,
+                    aperture,
+                    self.focus_dist,
+                    *tilt,
+                    *shift,
+                    self.time0,
+                    self.time1,
+                );
+                cam.set_aperture_shape(self.aperture_shape.clone());
+                Arc::new(cam)
+            }
+            Projection::Realistic {
+                lens_file,
+                aperture_diameter,
+                film_diagonal,
+                mm_per_unit,
+            } => {
+                let mut cam = RealisticCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    lens_file,
+                    if self.exposure.is_some() {
+                        f64::INFINITY
+                    } else {
+                        *aperture_diameter
+                    },
+                    self.focus_dist,
+                    *film_diagonal,
+                    aspect_ratio,
+                    *mm_per_unit,
+                    self.time0,
+                    self.time1,
+                );
+                if let Some(exposure) = &self.exposure {
+                    cam.set_f_number(exposure.f_number);
+                }
+                cam.set_aperture_shape(self.aperture_shape.clone());
+                Arc::new(cam)
+            }
+        };
+        match &self.exposure {
+            Some(exposure) => Arc::new(PhysicalCamera::new(
+                camera, *exposure, self.time0, self.time1,
+            )),
+            None => camera,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::assert_close;
+
+    //相机在原点看-z，u是+x，v是+y
+    fn eye() -> (Point3, Point3, Vec3) {
+        (
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+        )
+    }
+
+    fn angle(a: Vec3, b: Vec3) -> f64 {
+        Vec3::dot(Vec3::unit_vector(a), Vec3::unit_vector(b))
+            .clamp(-1., 1.)
+            .acos()
+            .to_degrees()
+    }
+
+    #[test]
+    fn fisheye_angles() {
+        let (from, at, up) = eye();
+        let forward = at - from;
+        for &(fov, mapping) in [
+            (180., FisheyeMapping::Equidistant),
+            (220., FisheyeMapping::Equidistant),
+            (180., FisheyeMapping::Equisolid),
+        ]
+        .iter()
+        {
+            let cam = FisheyeCamera::new(from, at, up, fov, mapping, 1.5, 0., 0.);
+            let (r, _) = cam.get_ray(0.5, 0.5).unwrap();
+            assert!(angle(r.dir, forward) < 1e-6);
+            //短边是竖的，上下边正好在圆上，是视角的一半
+            let (r, _) = cam.get_ray(0.5, 1.).unwrap();
+            assert!((angle(r.dir, forward) - fov / 2.).abs() < 1e-6);
+            assert!(Vec3::dot(r.dir, up) > 0.);
+            //左右圆的边在s = 0.5 ± 1/3
+            let (r, _) = cam.get_ray(0.5 + 1. / 3., 0.5).unwrap();
+            assert!((angle(r.dir, forward) - fov / 2.).abs() < 1e-6);
+            assert!(r.dir.x > 0.);
+            //半径一半的地方
+            let half = match mapping {
+                FisheyeMapping::Equidistant => fov / 4.,
+                FisheyeMapping::Equisolid => {
+                    (2. * (0.5 * (fov.to_radians() / 4.).sin()).asin()).to_degrees()
+                }
+            };
+            let (r, _) = cam.get_ray(0.5, 0.75).unwrap();
+            assert!((angle(r.dir, forward) - half).abs() < 1e-6);
+            //圆外面和四个角拍不到东西
+            assert!(cam.get_ray(1., 0.5).is_none());
+            assert!(cam.get_ray(0., 0.).is_none());
+        }
+    }
+
+    #[test]
+    fn equirectangular_angles() {
+        let (from, _, up) = eye();
+        //往上看也不影响地平线
+        let cam = EquirectangularCamera::new(from, Point3::new(0., 1., -1.), up, 0., 0.);
+        let dir = |s, t| Vec3::unit_vector(cam.get_ray(s, t).unwrap().0.dir);
+        assert_close(dir(0.5, 0.5), Vec3::new(0., 0., -1.));
+        assert_close(dir(0.75, 0.5), Vec3::new(1., 0., 0.));
+        assert_close(dir(0.25, 0.5), Vec3::new(-1., 0., 0.));
+        //左右两边接在正后方
+        assert_close(dir(0., 0.5), Vec3::new(0., 0., 1.));
+        assert_close(dir(1., 0.5), Vec3::new(0., 0., 1.));
+        assert_close(dir(0.3, 1.), Vec3::new(0., 1., 0.));
+        assert_close(dir(0.3, 0.), Vec3::new(0., -1., 0.));
+        assert!((angle(dir(0.5, 0.75), Vec3::new(0., 0., -1.)) - 45.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn orthographic_rays() {
+        let (from, at, up) = eye();
+        let cam = OrthographicCamera::new(from, at, up, 2., 1.5, 0., 3., 0., 0.);
+        let (r, _) = cam.get_ray(0.5, 0.5).unwrap();
+        assert_close(r.orig, from);
+        let (r, _) = cam.get_ray(1., 1.).unwrap();
+        assert_close(r.orig, Point3::new(1.5, 1., 0.));
+        assert!(angle(r.dir, at - from) < 1e-6);
+        //有光圈的时候同一个像素的光线在对焦平面上汇到一点
+        let cam = OrthographicCamera::new(from, at, up, 2., 1.5, 0.5, 3., 0., 0.);
+        for _ in 0..16 {
+            let (r, _) = cam.get_ray(0.25, 0.75).unwrap();
+            assert_close(r.at(1.), Point3::new(-0.75, 0.5, -3.));
+        }
+    }
+
+    #[test]
+    fn tilt_shift_focus() {
+        let (from, at, up) = eye();
+        let cam = TiltShiftCamera::new(from, at, up, 90., 1., 0., 2., 0., (0., 0.), 0., 0.);
+        let (r, _) = cam.get_ray(0.5, 0.5).unwrap();
+        assert!(angle(r.dir, at - from) < 1e-6);
+        //shift只是把画面挪了，透视不变
+        let shifted = TiltShiftCamera::new(from, at, up, 90., 1., 0., 2., 0., (0.25, 0.), 0., 0.);
+        let (a, _) = shifted.get_ray(0.5, 0.5).unwrap();
+        let (b, _) = cam.get_ray(0.75, 0.5).unwrap();
+        assert!(angle(a.dir, b.dir) < 1e-6);
+        assert!(angle(a.dir, Vec3::new(0.5, 0., -1.)) < 1e-6);
+        //tilt以后中间还是对焦在focus_dist，下边更近，上边更远
+        let tilted = TiltShiftCamera::new(from, at, up, 90., 1., 0.5, 2., 10., (0., 0.), 0., 0.);
+        let focus = |t| {
+            let (o, d) = tilted.ray_through(0.5, t, Vec3::new(0., 0., 0.));
+            //镜头上别的点打过来也汇到同一个地方
+            let (o2, d2) = tilted.ray_through(0.5, t, Vec3::new(0.2, -0.1, 0.));
+            assert_close(o + d, o2 + d2);
+            -(o + d).z
+        };
+        assert!((focus(0.5) - 2.).abs() < 1e-6);
+        let tilt = 10f64.to_radians();
+        let near = 2. * tilt.cos() / (tilt.sin() + tilt.cos());
+        assert!((focus(0.) - near).abs() < 1e-6);
+        assert!(focus(1.) > 2.);
     }
 }
@@ -0,0 +1,194 @@
+use super::aabb::AABB;
+use crate::vec3::Point3;
+use crate::{hittable::HitRecord, hittable::Hittable, ray::Ray};
+use std::f64::INFINITY;
+use std::sync::Arc;
+//构造实体几何：两个封闭物体的并、交、差
+//沿着光线依次看两个物体的进出点，组合起来的里外状态变了的地方才是交点
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference, //a减去b
+}
+impl CsgOp {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+//一条光线最多看这么多个进出点，防止不封闭的物体一直找下去
+const MAX_EVENTS: usize = 64;
+
+pub struct Csg {
+    a: Arc<dyn Hittable>,
+    b: Arc<dyn Hittable>,
+    op: CsgOp,
+}
+impl Csg {
+    pub fn new(op: CsgOp, a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Self { a, b, op }
+    }
+    pub fn union(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Self::new(CsgOp::Union, a, b)
+    }
+    pub fn intersection(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Self::new(CsgOp::Intersection, a, b)
+    }
+    pub fn difference(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Self {
+        Self::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        //往后一直找到无穷远，不然t_max外面的进出点会让起点的里外判断错
+        let mut ha = self.a.hit(r, t_min, INFINITY);
+        let mut hb = self.b.hit(r, t_min, INFINITY);
+        //第一个交点是从里面出去的，说明起点就在物体里面
+        let mut in_a = ha.as_ref().map_or(false, |h| !h.front_face);
+        let mut in_b = hb.as_ref().map_or(false, |h| !h.front_face);
+        let mut inside = self.op.inside(in_a, in_b);
+        for _ in 0..MAX_EVENTS {
+            let from_a = match (&ha, &hb) {
+                (Some(x), Some(y)) => x.t <= y.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let mut rec = if from_a {
+                ha.take().unwrap()
+            } else {
+                hb.take().unwrap()
+            };
+            if rec.t > t_max {
+                return None;
+            }
+            if from_a {
+                in_a = rec.front_face;
+                ha = self.a.hit(r, rec.t + 0.0001, INFINITY);
+            } else {
+                in_b = rec.front_face;
+                hb = self.b.hit(r, rec.t + 0.0001, INFINITY);
+            }
+            let now = self.op.inside(in_a, in_b);
+            if now != inside {
+                //差集里b的表面是反着用的，进入结果的时候正好是离开b，和flip_face一样翻过来
+                if rec.front_face != now {
+                    rec.front_face = now;
+                    rec.dndu = -rec.dndu;
+                    rec.dndv = -rec.dndv;
+                }
+                return Some(rec);
+            }
+            inside = now;
+        }
+        None
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        let box_a = self.a.bounding_box(time0, time1);
+        let box_b = self.b.bounding_box(time0, time1);
+        match self.op {
+            CsgOp::Union => Some(AABB::surrounding_box(box_a?, box_b?)),
+            //交集只会在两个盒子重叠的地方
+            CsgOp::Intersection => match (box_a, box_b) {
+                (Some(x), Some(y)) => Some(AABB::new(
+                    Point3::new(
+                        x.minimum.x.max(y.minimum.x),
+                        x.minimum.y.max(y.minimum.y),
+                        x.minimum.z.max(y.minimum.z),
+                    ),
+                    Point3::new(
+                        x.maximum.x.min(y.maximum.x),
+                        x.maximum.y.min(y.maximum.y),
+                        x.maximum.z.min(y.maximum.z),
+                    ),
+                )),
+                (x, None) => x,
+                (None, y) => y,
+            },
+            CsgOp::Difference => box_a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_close, gray};
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    //a在原点，b往x挪1，半径都是1
+    fn csg(op: CsgOp) -> Csg {
+        Csg::new(
+            op,
+            Arc::new(Sphere::new(Point3::new(0., 0., 0.), 1., gray())),
+            Arc::new(Sphere::new(Point3::new(1., 0., 0.), 1., gray())),
+        )
+    }
+
+    //沿着+x走一遍，所有交点的(x, 是不是进去)
+    fn events(shape: &Csg, x0: f64) -> Vec<(f64, bool)> {
+        let r = Ray::new(Point3::new(x0, 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        let mut out = Vec::new();
+        let mut t_min = 0.001;
+        while let Some(rec) = shape.hit(r, t_min, INFINITY) {
+            out.push((((x0 + rec.t) * 1e6).round() / 1e6, rec.front_face));
+            t_min = rec.t + 0.001;
+        }
+        out
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(
+            events(&csg(CsgOp::Union), -5.),
+            vec![(-1., true), (2., false)]
+        );
+        assert_eq!(
+            events(&csg(CsgOp::Intersection), -5.),
+            vec![(0., true), (1., false)]
+        );
+        assert_eq!(
+            events(&csg(CsgOp::Difference), -5.),
+            vec![(-1., true), (0., false)]
+        );
+    }
+
+    #[test]
+    fn starting_inside() {
+        //起点在两个球里面
+        assert_eq!(events(&csg(CsgOp::Union), 0.5), vec![(2., false)]);
+        assert_eq!(events(&csg(CsgOp::Intersection), 0.5), vec![(1., false)]);
+        assert_eq!(events(&csg(CsgOp::Difference), 0.5), vec![]);
+        //只在a里面
+        assert_eq!(events(&csg(CsgOp::Difference), -0.5), vec![(0., false)]);
+        assert_eq!(
+            events(&csg(CsgOp::Intersection), -0.5),
+            vec![(0., true), (1., false)]
+        );
+    }
+
+    #[test]
+    fn difference_normal_and_t_max() {
+        //差集在x=0用的是b的表面，朝外的法向量要反过来朝+x
+        let d = csg(CsgOp::Difference);
+        let r = Ray::new(Point3::new(-5., 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        let rec = d.hit(r, 4.5, INFINITY).unwrap();
+        assert!((rec.t - 5.).abs() < 1e-9);
+        assert!(!rec.front_face);
+        assert_close(rec.normal, Vec3::new(-1., 0., 0.));
+        //t_max之前没有交点，后面的进出点不能漏出来
+        assert!(csg(CsgOp::Intersection).hit(r, 0.001, 4.5).is_none());
+        assert!(csg(CsgOp::Union).hit(r, 0.001, 4.5).is_some());
+        //从旁边擦过去
+        let miss = Ray::new(Point3::new(-5., 1.5, 0.), Vec3::new(1., 0., 0.), 0.);
+        assert!(csg(CsgOp::Union).hit(miss, 0.001, INFINITY).is_none());
+    }
+}
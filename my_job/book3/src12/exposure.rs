@@ -0,0 +1,185 @@
+use crate::camera::Camera;
+use crate::ray::Ray;
+use rand::Rng;
+use std::sync::Arc;
+//物理相机的曝光：f值、快门速度、ISO决定画面亮度，快门曲线决定光线的时间
+
+#[derive(Copy, Clone)]
+pub enum ShutterCurve {
+    Box,                      //瞬间打开瞬间关上
+    Trapezoid { ramp: f64 },  //打开和关上各用掉快门时间的ramp（不超过0.5），中间全开
+    Rolling { readout: f64 }, //卷帘快门，从最上面一行扫到最下面一行用readout秒，每一行都曝光快门时间那么久
+}
+
+#[derive(Copy, Clone)]
+pub struct Exposure {
+    pub f_number: f64,
+    pub shutter_speed: f64, //秒
+    pub iso: f64,
+    pub curve: ShutterCurve,
+    pub focal_length: f64, //场景单位，用来从f值算光圈直径，真实镜头用镜头自己的焦距
+    pub seconds_per_unit: f64, //场景里一个时间单位是多少秒
+}
+
+impl Exposure {
+    pub fn new(f_number: f64, shutter_speed: f64, iso: f64, focal_length: f64) -> Self {
+        Self {
+            f_number,
+            shutter_speed,
+            iso,
+            curve: ShutterCurve::Box,
+            focal_length,
+            seconds_per_unit: 1.,
+        }
+    }
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number
+    }
+    //快门曲线下面的面积，梯形快门实际进的光少一些
+    fn effective_shutter_time(&self) -> f64 {
+        match self.curve {
+            ShutterCurve::Trapezoid { ramp } => self.shutter_speed * (1. - ramp.clamp(0., 0.5)),
+            _ => self.shutter_speed,
+        }
+    }
+    //按EV100算的曝光，f/1、1秒、ISO 100 大约是0.83
+    pub fn scale(&self) -> f64 {
+        let ev100 = (self.f_number * self.f_number / self.effective_shutter_time()).log2()
+            - (self.iso / 100.).log2();
+        1. / (1.2 * 2f64.powf(ev100))
+    }
+    //t是胶片上的纵坐标，卷帘快门要用；返回[time0, time1]里的时间。
+    //快门开着的整段（卷帘还要加上readout）比这个区间长的话整段按比例压进去，
+    //BVH和运动物体的包围盒都只管到time1
+    pub fn sample_time(&self, time0: f64, time1: f64, t: f64) -> f64 {
+        let mut rng = rand::thread_rng();
+        let x: f64 = match self.curve {
+            ShutterCurve::Box => rng.gen(),
+            ShutterCurve::Trapezoid { ramp } => {
+                //按梯形的面积反着求
+                let r = ramp.clamp(0., 0.5);
+                let area = 1. - r;
+                let y = rng.gen::<f64>() * area;
+                if y < r / 2. {
+                    (2. * r * y).sqrt()
+                } else if y < area - r / 2. {
+                    y + r / 2.
+                } else {
+                    1. - (2. * r * (area - y)).max(0.).sqrt()
+                }
+            }
+            ShutterCurve::Rolling { readout } => {
+                //最上面一行(t=1)最先开始
+                rng.gen::<f64>() + (1. - t) * readout / self.shutter_speed
+            }
+        };
+        let x_max = match self.curve {
+            ShutterCurve::Rolling { readout } => 1. + readout / self.shutter_speed,
+            _ => 1.,
+        };
+        let span = x_max * self.shutter_speed / self.seconds_per_unit;
+        let squeeze = if span > time1 - time0 {
+            (time1 - time0).max(0.) / span
+        } else {
+            1.
+        };
+        time0 + x * self.shutter_speed / self.seconds_per_unit * squeeze
+    }
+}
+
+//给别的相机套上快门和曝光，曝光乘在光线的权重上
+pub struct PhysicalCamera {
+    camera: Arc<dyn Camera>,
+    exposure: Exposure,
+    time0: f64, //快门打开的时间
+    time1: f64,
+    scale: f64,
+}
+
+impl PhysicalCamera {
+    pub fn new(camera: Arc<dyn Camera>, exposure: Exposure, time0: f64, time1: f64) -> Self {
+        Self {
+            camera,
+            exposure,
+            time0,
+            time1,
+            scale: exposure.scale(),
+        }
+    }
+}
+
+impl Camera for PhysicalCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        let (mut r, weight) = self.camera.get_ray(s, t)?;
+        r.tm = self.exposure.sample_time(self.time0, self.time1, t);
+        Some((r, weight * self.scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs()
+    }
+
+    #[test]
+    fn ev100_scale() {
+        //EV100 = 0
+        assert!(close(Exposure::new(1., 1., 100., 0.05).scale(), 1. / 1.2));
+        //f/16、1/100秒：EV100 = log2(25600)
+        let sunny = Exposure::new(16., 0.01, 100., 0.05);
+        assert!(close(sunny.scale(), 1. / (1.2 * 25600.)));
+        //ISO翻倍亮一档，梯形快门按曲线下面的面积少进光
+        let mut e = Exposure::new(16., 0.01, 200., 0.05);
+        assert!(close(e.scale(), 2. * sunny.scale()));
+        e.curve = ShutterCurve::Trapezoid { ramp: 0.25 };
+        assert!(close(e.scale(), 1.5 * sunny.scale()));
+    }
+
+    //n个样本的时间，检查都在[time0, time1]里，返回平均值和落在[time0, edge)里的比例
+    fn sample(e: &Exposure, time0: f64, time1: f64, t: f64, edge: f64) -> (f64, f64) {
+        let n = 200000;
+        let (mut sum, mut below) = (0., 0);
+        for _ in 0..n {
+            let x = e.sample_time(time0, time1, t);
+            assert!(
+                x >= time0 && x <= time1,
+                "{} not in [{}, {}]",
+                x,
+                time0,
+                time1
+            );
+            sum += x;
+            if x < edge {
+                below += 1;
+            }
+        }
+        (sum / n as f64, below as f64 / n as f64)
+    }
+
+    #[test]
+    fn shutter_times() {
+        let mut e = Exposure::new(2., 0.5, 100., 0.05);
+        let (mean, _) = sample(&e, 0., 1., 0.5, 0.);
+        assert!((mean - 0.25).abs() < 0.005, "{}", mean);
+        //2秒的快门压进[1, 2]，梯形是对称的，一头的斜坡占面积的(r/2)/(1-r)
+        e.shutter_speed = 2.;
+        e.curve = ShutterCurve::Trapezoid { ramp: 0.3 };
+        let (mean, ramp) = sample(&e, 1., 2., 0.5, 1.3);
+        assert!((mean - 1.5).abs() < 0.005, "{}", mean);
+        assert!((ramp - 0.15 / 0.7).abs() < 0.005, "{}", ramp);
+        //卷帘：最上面一行先开，最下面一行晚readout
+        e.shutter_speed = 1.;
+        e.curve = ShutterCurve::Rolling { readout: 0.5 };
+        let (top, _) = sample(&e, 0., 3., 1., 0.);
+        let (bottom, early) = sample(&e, 0., 3., 0., 0.5);
+        assert!((top - 0.5).abs() < 0.005, "{}", top);
+        assert!((bottom - 1.).abs() < 0.005, "{}", bottom);
+        assert_eq!(early, 0.);
+        //区间不够长的时候整段按比例压进去
+        let (bottom, _) = sample(&e, 0., 0.75, 0., 0.);
+        assert!((bottom - 0.5).abs() < 0.005, "{}", bottom);
+    }
+}
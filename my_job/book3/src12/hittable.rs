@@ -1,18 +1,38 @@
 use crate::aabb::AABB;
 use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::RayDifferential;
 use crate::{ray::Ray, vec3::Point3, vec3::Vec3};
 use rand::Rng;
 use std::sync::Arc;
 #[derive(Clone)]
 pub struct HitRecord {
-    pub p: Point3,    //碰撞点
-    pub normal: Vec3, //法向量
+    pub p: Point3,        //碰撞点
+    pub p_object: Point3, //物体自己坐标系里的碰撞点，Translate、RotateY不改它，纹理用这个
+    pub n_object: Vec3,   //物体坐标系里朝外的几何法向量，三平面投影要用
+    pub normal: Vec3,     //着色用的法向量，法线贴图、凹凸贴图会改它
+    pub geo_normal: Vec3, //几何法向量，和normal一样朝着光线那一面，判断在不在面的下面用它
     pub u: f64,
     pub v: f64,
     pub t: f64,
+    pub tm: f64,          //光线的时间，着色的时候填，随时间变的纹理要用
     pub front_face: bool, //光线朝里还是朝外
     pub mat_ptr: Arc<dyn Material>,
+    pub dpdu: Vec3, //p对uv的偏导，形状自己填，没填就是0
+    pub dpdv: Vec3,
+    pub dpdu_object: Vec3, //物体坐标系里的dpdu、dpdv，和p_object一样变换不改它，凹凸贴图沿它挪
+    pub dpdv_object: Vec3,
+    pub dndu: Vec3, //朝外的法向量对uv的偏导，曲面上反射、折射的光线微分要用
+    pub dndv: Vec3,
+    pub dpdx: Vec3, //下面这些由compute_differentials按光线微分算
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+    pub extra_uv: [(f64, f64); EXTRA_UV_SETS], //网格上除了(u,v)以外的几套uv
 }
+pub const EXTRA_UV_SETS: usize = 3;
 
 impl HitRecord {
     pub fn new(
@@ -26,14 +46,147 @@ impl HitRecord {
     ) -> HitRecord {
         HitRecord {
             p: _p,
+            p_object: _p,
+            n_object: _normal,
             normal: _normal,
+            geo_normal: _normal,
             t: _t,
+            tm: 0.,
             u: _u,
             v: _v,
             front_face: _front_face,
             mat_ptr: _mat_ptr,
+            dpdu: Vec3::new(0., 0., 0.),
+            dpdv: Vec3::new(0., 0., 0.),
+            dpdu_object: Vec3::new(0., 0., 0.),
+            dpdv_object: Vec3::new(0., 0., 0.),
+            dndu: Vec3::new(0., 0., 0.),
+            dndv: Vec3::new(0., 0., 0.),
+            dpdx: Vec3::new(0., 0., 0.),
+            dpdy: Vec3::new(0., 0., 0.),
+            dudx: 0.,
+            dvdx: 0.,
+            dudy: 0.,
+            dvdy: 0.,
+            extra_uv: [(0., 0.); EXTRA_UV_SETS],
         }
     }
+    pub fn set_derivatives(&mut self, dpdu: Vec3, dpdv: Vec3, dndu: Vec3, dndv: Vec3) {
+        self.dpdu_object = dpdu;
+        self.dpdv_object = dpdv;
+        self.set_world_derivatives(dpdu, dpdv, dndu, dndv);
+    }
+    //RotateY、Instance把偏导换到世界坐标系的时候用，物体坐标系里的留着
+    pub fn set_world_derivatives(&mut self, dpdu: Vec3, dpdv: Vec3, dndu: Vec3, dndv: Vec3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.dndu = dndu;
+        self.dndv = dndv;
+    }
+    //偏移的两条光线和这一点的切平面求交，再把p上的差换成uv上的差
+    pub fn compute_differentials(&mut self, r: &Ray) {
+        self.dpdx = Vec3::new(0., 0., 0.);
+        self.dpdy = Vec3::new(0., 0., 0.);
+        self.dudx = 0.;
+        self.dvdx = 0.;
+        self.dudy = 0.;
+        self.dvdy = 0.;
+        let diff = match r.diff {
+            Some(diff) => diff,
+            None => return,
+        };
+        let n = self.geo_normal;
+        let d = Vec3::dot(n, self.p);
+        let tx = (d - Vec3::dot(n, diff.rx_orig)) / Vec3::dot(n, diff.rx_dir);
+        let ty = (d - Vec3::dot(n, diff.ry_orig)) / Vec3::dot(n, diff.ry_dir);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
+        }
+        self.dpdx = diff.rx_orig + diff.rx_dir * tx - self.p;
+        self.dpdy = diff.ry_orig + diff.ry_dir * ty - self.p;
+        //丢掉法向量最大的那一维，剩下两维解2x2的方程
+        let (a, b) = if n.x.abs() > n.y.abs() && n.x.abs() > n.z.abs() {
+            (1, 2)
+        } else if n.y.abs() > n.z.abs() {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let det = self.dpdu[a] * self.dpdv[b] - self.dpdv[a] * self.dpdu[b];
+        if det.abs() < 1e-12 {
+            return;
+        }
+        let solve = |dp: Vec3| {
+            (
+                (self.dpdv[b] * dp[a] - self.dpdv[a] * dp[b]) / det,
+                (self.dpdu[a] * dp[b] - self.dpdu[b] * dp[a]) / det,
+            )
+        };
+        let (dudx, dvdx) = solve(self.dpdx);
+        let (dudy, dvdy) = solve(self.dpdy);
+        self.dudx = dudx;
+        self.dvdx = dvdx;
+        self.dudy = dudy;
+        self.dvdy = dvdy;
+    }
+    //纹理的滤波宽度（uv单位）
+    pub fn uv_width(&self) -> f64 {
+        2. * self
+            .dudx
+            .abs()
+            .max(self.dudy.abs())
+            .max(self.dvdx.abs())
+            .max(self.dvdy.abs())
+    }
+    //朝着光线那一面的法向量对屏幕x、y的偏导
+    fn dndxy(&self) -> (Vec3, Vec3) {
+        let sign = if self.front_face { 1. } else { -1. };
+        (
+            (self.dndu * self.dudx + self.dndv * self.dvdx) * sign,
+            (self.dndu * self.dudy + self.dndv * self.dvdy) * sign,
+        )
+    }
+    //镜面反射之后的光线微分，wi是反射方向
+    pub fn reflect_differential(&self, r_in: &Ray, wi: Vec3) -> Option<RayDifferential> {
+        let diff = r_in.diff?;
+        let n = self.normal;
+        let wo = -Vec3::unit_vector(r_in.dir);
+        let wi = Vec3::unit_vector(wi);
+        let (dndx, dndy) = self.dndxy();
+        let dwodx = -Vec3::unit_vector(diff.rx_dir) - wo;
+        let dwody = -Vec3::unit_vector(diff.ry_dir) - wo;
+        let ddndx = Vec3::dot(dwodx, n) + Vec3::dot(wo, dndx);
+        let ddndy = Vec3::dot(dwody, n) + Vec3::dot(wo, dndy);
+        let cos = Vec3::dot(wo, n);
+        Some(RayDifferential {
+            rx_orig: self.p + self.dpdx,
+            rx_dir: wi - dwodx + (dndx * cos + n * ddndx) * 2.,
+            ry_orig: self.p + self.dpdy,
+            ry_dir: wi - dwody + (dndy * cos + n * ddndy) * 2.,
+        })
+    }
+    //折射之后的光线微分，eta是入射一侧比折射一侧的折射率
+    pub fn refract_differential(&self, r_in: &Ray, wi: Vec3, eta: f64) -> Option<RayDifferential> {
+        let diff = r_in.diff?;
+        let n = self.normal;
+        let wo = -Vec3::unit_vector(r_in.dir);
+        let wi = Vec3::unit_vector(wi);
+        let (dndx, dndy) = self.dndxy();
+        let dwodx = -Vec3::unit_vector(diff.rx_dir) - wo;
+        let dwody = -Vec3::unit_vector(diff.ry_dir) - wo;
+        let ddndx = Vec3::dot(dwodx, n) + Vec3::dot(wo, dndx);
+        let ddndy = Vec3::dot(dwody, n) + Vec3::dot(wo, dndy);
+        let cos_o = Vec3::dot(wo, n);
+        let cos_i = Vec3::dot(wi, n).abs();
+        let mu = eta * cos_o - cos_i;
+        let dmu = eta - eta * eta * cos_o / cos_i;
+        Some(RayDifferential {
+            rx_orig: self.p + self.dpdx,
+            rx_dir: wi - dwodx * eta + dndx * mu + n * (dmu * ddndx),
+            ry_orig: self.p + self.dpdy,
+            ry_dir: wi - dwody * eta + dndy * mu + n * (dmu * ddndy),
+        })
+    }
     pub fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_face = Vec3::dot(r.dir, outward_normal) < 0.;
         if self.front_face {
@@ -41,6 +194,35 @@ impl HitRecord {
         } else {
             self.normal = Vec3::new(0., 0., 0.) - outward_normal;
         }
+        self.geo_normal = self.normal;
+        self.n_object = outward_normal;
+    }
+    //朝外的着色法向量，贴图都是按朝外定义的
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
+    //n是朝外的，换成朝着光线那一面再存
+    pub fn set_shading_normal(&mut self, n: Vec3) {
+        let n = Vec3::unit_vector(n);
+        self.normal = if self.front_face { n } else { -n };
+    }
+    //着色法向量加上dpdu正交化得到的切向量，uv退化的时候随便取
+    pub fn tangent_frame(&self) -> Onb {
+        let n = self.outward_normal();
+        let t = self.dpdu - n * Vec3::dot(self.dpdu, n);
+        if t.length_squared() < 1e-16 {
+            return Onb::build_from_w(n);
+        }
+        let mut frame = Onb::build_from_wu(n, t);
+        //uv镜像的时候副切向量要跟着dpdv翻过来
+        if Vec3::dot(frame.v(), self.dpdv) < 0. {
+            frame.axis[1] = -frame.axis[1];
+        }
+        frame
     }
 }
 pub trait Hittable: Send + Sync {
@@ -52,6 +234,37 @@ pub trait Hittable: Send + Sync {
     fn random(&self, o: Vec3) -> Vec3 {
         Vec3::new(1., 0., 0.)
     }
+    //场景里会发光的图元，包装器要把自己的变换也套上
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        Vec::new()
+    }
+    fn power(&self) -> f64 {
+        0.
+    }
+    //能按面积采样的图元的表面积，实例把材质换成发光的时候按它估功率
+    fn area(&self) -> f64 {
+        0.
+    }
+    //法向量的范围，(轴, cos半角)，默认是所有方向
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (Vec3::new(0., 0., 1.), -1.)
+    }
+}
+
+//按面积均匀采样的形状，把面积pdf换成立体角pdf
+//一条方向可能穿过形状好几次，每个交点都可能被采到，所以要加起来
+pub fn area_pdf_value(object: &dyn Hittable, o: Point3, v: Vec3, area: f64) -> f64 {
+    let mut sum = 0.;
+    let mut t_min = 0.001;
+    while let Some(rec) = object.hit(Ray::new(o, v, 0.), t_min, f64::INFINITY) {
+        let dis_sqr = rec.t * rec.t * v.length_squared();
+        let cos = (Vec3::dot(v, rec.geo_normal) / v.length()).abs();
+        if cos > 0. {
+            sum += dis_sqr / (cos * area);
+        }
+        t_min = rec.t + 0.0001;
+    }
+    sum
 }
 
 pub struct HittableList {
@@ -115,6 +328,19 @@ impl Hittable for HittableList {
         let target = rand::thread_rng().gen_range(0..self.objects.len());
         self.objects[target].random(o)
     }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        let mut lights = Vec::new();
+        for object in &self.objects {
+            lights.append(&mut object.lights());
+        }
+        lights
+    }
+    fn power(&self) -> f64 {
+        self.objects.iter().map(|object| object.power()).sum()
+    }
+    fn area(&self) -> f64 {
+        self.objects.iter().map(|object| object.area()).sum()
+    }
 }
 #[derive(Clone)]
 pub struct Translate {
@@ -136,7 +362,6 @@ impl Hittable for Translate {
         let moved_r = Ray::new(r.orig - self.offset, r.dir, r.tm);
         if let Some(mut rec) = self.ptr.hit(moved_r, t_min, t_max) {
             rec.p += self.offset;
-            rec.set_face_normal(moved_r, rec.normal);
             Some(rec)
         } else {
             None
@@ -152,10 +377,33 @@ impl Hittable for Translate {
             None
         }
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        self.ptr.pdf_value(o - self.offset, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.ptr.random(o - self.offset)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        self.ptr
+            .lights()
+            .into_iter()
+            .map(|l| Arc::new(Translate::new(l, self.offset)) as Arc<dyn Hittable>)
+            .collect()
+    }
+    fn power(&self) -> f64 {
+        self.ptr.power()
+    }
+    fn area(&self) -> f64 {
+        self.ptr.area()
+    }
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        self.ptr.normal_bounds()
+    }
 }
 
 pub struct RotateY {
     ptr: Arc<dyn Hittable>,
+    angle: f64,
     sin_theta: f64,
     cos_theta: f64,
     bbox: Option<AABB>,
@@ -193,6 +441,7 @@ impl RotateY {
             }
             Self {
                 ptr: p,
+                angle,
                 sin_theta,
                 cos_theta,
                 bbox: Some(AABB::new(minimum, maximum)),
@@ -200,6 +449,7 @@ impl RotateY {
         } else {
             Self {
                 ptr: p,
+                angle,
                 sin_theta,
                 cos_theta,
                 bbox: None,
@@ -208,10 +458,50 @@ impl RotateY {
     }
 }
 
+impl RotateY {
+    fn to_object(&self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * a.x - self.sin_theta * a.z,
+            a.y,
+            self.sin_theta * a.x + self.cos_theta * a.z,
+        )
+    }
+    fn to_world(&self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * a.x + self.sin_theta * a.z,
+            a.y,
+            -self.sin_theta * a.x + self.cos_theta * a.z,
+        )
+    }
+}
+
 impl Hittable for RotateY {
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         self.bbox
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        self.ptr.pdf_value(self.to_object(o), self.to_object(v))
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.to_world(self.ptr.random(self.to_object(o)))
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        self.ptr
+            .lights()
+            .into_iter()
+            .map(|l| Arc::new(RotateY::new(l, self.angle)) as Arc<dyn Hittable>)
+            .collect()
+    }
+    fn power(&self) -> f64 {
+        self.ptr.power()
+    }
+    fn area(&self) -> f64 {
+        self.ptr.area()
+    }
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        let (w, cos_theta) = self.ptr.normal_bounds();
+        (self.to_world(w), cos_theta)
+    }
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut origin = r.orig;
         let mut direction = r.dir;
@@ -226,16 +516,27 @@ impl Hittable for RotateY {
 
         if let Some(mut rec) = self.ptr.hit(rotated_r, t_min, t_max) {
             let mut p = rec.p;
-            let mut normal = rec.normal;
 
             p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
             p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
 
-            normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-            normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-
             rec.p = p;
-            rec.set_face_normal(rotated_r, normal);
+            let rotate = |a: Vec3| {
+                Vec3::new(
+                    self.cos_theta * a.x + self.sin_theta * a.z,
+                    a.y,
+                    -self.sin_theta * a.x + self.cos_theta * a.z,
+                )
+            };
+            //旋转不改变朝哪一面，两个法向量直接转过来
+            rec.normal = rotate(rec.normal);
+            rec.geo_normal = rotate(rec.geo_normal);
+            rec.set_world_derivatives(
+                rotate(rec.dpdu),
+                rotate(rec.dpdv),
+                rotate(rec.dndu),
+                rotate(rec.dndv),
+            );
 
             Some(rec)
         } else {
@@ -258,6 +559,9 @@ impl Hittable for flip_face {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         if let Some(mut rec) = self.ptr.hit(r, t_min, t_max) {
             rec.front_face = !rec.front_face;
+            //朝外的方向反过来了，法向量的偏导也跟着反
+            rec.dndu = -rec.dndu;
+            rec.dndv = -rec.dndv;
             Some(rec)
         } else {
             None
@@ -266,4 +570,183 @@ impl Hittable for flip_face {
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         return self.ptr.bounding_box(time0, time1);
     }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        self.ptr.pdf_value(o, v)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.ptr.random(o)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        self.ptr
+            .lights()
+            .into_iter()
+            .map(|l| Arc::new(flip_face::new(l)) as Arc<dyn Hittable>)
+            .collect()
+    }
+    fn power(&self) -> f64 {
+        self.ptr.power()
+    }
+    fn area(&self) -> f64 {
+        self.ptr.area()
+    }
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        self.ptr.normal_bounds()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::aarect::{xy_rect, xz_rect, yz_rect};
+    use crate::boxes::Box;
+    use crate::material::Lambertian;
+    use crate::onb::Onb;
+    use crate::sphere::{MovingSphere, Sphere};
+    use crate::triangle::{Mesh, Triangle};
+    use crate::vec3::RGBColor;
+    use std::f64::consts::PI;
+
+    pub fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5)))
+    }
+
+    //两个向量每一维都差不到1e-6
+    pub fn assert_close(a: Vec3, b: Vec3) {
+        for i in 0..3 {
+            assert!(
+                (a[i] - b[i]).abs() < 1e-6,
+                "({}, {}, {}) != ({}, {}, {})",
+                a.x,
+                a.y,
+                a.z,
+                b.x,
+                b.y,
+                b.z
+            );
+        }
+    }
+
+    //从o看过去：均匀方向上pdf_value的平均乘立体角是pdf在球面上的积分，应该是1；
+    //按random采的方向1/pdf的平均是形状张的立体角，和均匀方向里打中的比例乘立体角对上。
+    //方向只在包围球张的那个锥里均匀取，曲面轮廓边上pdf按1/cos涨，全球面取的话噪声太大
+    pub fn check_sampling(shape: &dyn Hittable, o: Point3) {
+        let n = 200000;
+        let bbox = shape.bounding_box(0., 1.).unwrap();
+        let center = (bbox.minimum + bbox.maximum) / 2.;
+        let radius = (bbox.maximum - bbox.minimum).length() / 2.;
+        let d2 = (center - o).length_squared();
+        let uvw = Onb::build_from_w(center - o);
+        let (cone, omega) = if d2 > radius * radius {
+            let cos_max = (1. - radius * radius / d2).sqrt();
+            (true, 2. * PI * (1. - cos_max))
+        } else {
+            (false, 4. * PI)
+        };
+        let (mut integral, mut covered) = (0., 0.);
+        for _ in 0..n {
+            let v = if cone {
+                uvw.local_vec(Vec3::random_to_sphere(radius, d2))
+            } else {
+                Vec3::random_unit_vector()
+            };
+            integral += shape.pdf_value(o, v);
+            if shape
+                .hit(Ray::new(o, v, 0.), 0.001, f64::INFINITY)
+                .is_some()
+            {
+                covered += 1.;
+            }
+        }
+        let integral = integral * omega / n as f64;
+        let solid_angle = covered * omega / n as f64;
+        let mut inv_pdf = 0.;
+        for _ in 0..n {
+            let v = shape.random(o);
+            let pdf = shape.pdf_value(o, v);
+            assert!(pdf > 0., "random() gave a direction with zero pdf");
+            inv_pdf += 1. / pdf;
+        }
+        let inv_pdf = inv_pdf / n as f64;
+        assert!(
+            (integral - 1.).abs() < 0.05,
+            "pdf integrates to {}",
+            integral
+        );
+        assert!(
+            (inv_pdf - solid_angle).abs() < 0.05 * solid_angle,
+            "E[1/pdf] = {}, solid angle = {}",
+            inv_pdf,
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn sphere_pdf() {
+        check_sampling(
+            &Sphere::new(Point3::new(0., 0., 2.), 1., gray()),
+            Point3::new(0., 0., 0.),
+        );
+    }
+
+    #[test]
+    fn moving_sphere_pdf() {
+        let s = MovingSphere::new(
+            Point3::new(0., 0., 2.),
+            Point3::new(0., 0., 2.),
+            0.,
+            1.,
+            1.,
+            gray(),
+        );
+        check_sampling(&s, Point3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn rect_pdfs() {
+        let o = Point3::new(0.2, 0.3, 0.1);
+        check_sampling(&xy_rect::new(-1., 1., -1., 2., 1., gray()), o);
+        check_sampling(&xz_rect::new(-1., 1., -1., 2., -1., gray()), o);
+        check_sampling(&yz_rect::new(-1., 1., -1., 2., 1.5, gray()), o);
+    }
+
+    #[test]
+    fn box_pdf() {
+        let b = Box::new(Point3::new(-1., -1., 1.), Point3::new(1., 0.5, 2.), gray());
+        check_sampling(&b, Point3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn triangle_and_mesh_pdfs() {
+        let o = Point3::new(0., 0., 0.);
+        let t = Triangle::new(
+            Point3::new(-1., -1., 1.),
+            Point3::new(1., -1., 1.5),
+            Point3::new(0., 1., 1.),
+            gray(),
+        );
+        check_sampling(&t, o);
+        //两个大小差很多的三角形，按面积挑
+        let mesh = Mesh::new(
+            vec![
+                Point3::new(-1., -1., 1.),
+                Point3::new(1., -1., 1.),
+                Point3::new(1., 1., 1.),
+                Point3::new(-0.5, 0.5, 1.),
+            ],
+            Vec::new(),
+            Vec::new(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            gray(),
+        );
+        check_sampling(&mesh, o);
+    }
+
+    #[test]
+    fn transformed_pdfs() {
+        let o = Point3::new(0., 0., 0.);
+        let rect: Arc<dyn Hittable> = Arc::new(xy_rect::new(-1., 1., -1., 1., 0., gray()));
+        check_sampling(&Translate::new(rect.clone(), Vec3::new(0., 0., 1.5)), o);
+        let turned = Arc::new(RotateY::new(rect, 30.));
+        check_sampling(&Translate::new(turned, Vec3::new(0., 0.5, 1.5)), o);
+    }
 }
@@ -0,0 +1,420 @@
+use super::aabb::AABB;
+use crate::material::{emitted_power, is_emissive};
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use console::style;
+use std::sync::Arc;
+//实例化：很多个实例共用同一份原型（一般是自己带BVH的网格），每个实例只存一个变换和可选的材质，
+//场景的BVH里放的是实例，原型内部的BVH只建一次，就是两层BVH
+
+//仿射变换，m的前三列是线性部分，最后一列是平移
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub m: [[f64; 4]; 3],
+}
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            m: [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.]],
+        }
+    }
+    pub fn translate(offset: Vec3) -> Self {
+        let mut t = Self::identity();
+        for i in 0..3 {
+            t.m[i][3] = offset[i];
+        }
+        t
+    }
+    pub fn scale(s: Vec3) -> Self {
+        let mut t = Self::identity();
+        for i in 0..3 {
+            t.m[i][i] = s[i];
+        }
+        t
+    }
+    //绕axis转angle度（Rodrigues公式）
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = Vec3::unit_vector(axis);
+        let (s, c) = angle.to_radians().sin_cos();
+        let k = 1. - c;
+        Self {
+            m: [
+                [
+                    a.x * a.x * k + c,
+                    a.x * a.y * k - a.z * s,
+                    a.x * a.z * k + a.y * s,
+                    0.,
+                ],
+                [
+                    a.y * a.x * k + a.z * s,
+                    a.y * a.y * k + c,
+                    a.y * a.z * k - a.x * s,
+                    0.,
+                ],
+                [
+                    a.z * a.x * k - a.y * s,
+                    a.z * a.y * k + a.x * s,
+                    a.z * a.z * k + c,
+                    0.,
+                ],
+            ],
+        }
+    }
+    //先做self再做next
+    pub fn then(&self, next: &Transform) -> Self {
+        let mut m = [[0.; 4]; 3];
+        for i in 0..3 {
+            for j in 0..4 {
+                for k in 0..3 {
+                    m[i][j] += next.m[i][k] * self.m[k][j];
+                }
+            }
+            m[i][3] += next.m[i][3];
+        }
+        Self { m }
+    }
+    //压扁了的变换（某个方向缩放成0）没有逆，返回None
+    pub fn inverse(&self) -> Option<Self> {
+        let a = &self.m;
+        let cof = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            a[r0][c0] * a[r1][c1] - a[r0][c1] * a[r1][c0]
+        };
+        let det = a[0][0] * cof(1, 2, 1, 2) - a[0][1] * cof(1, 2, 0, 2) + a[0][2] * cof(1, 2, 0, 1);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        //伴随矩阵除以行列式
+        let l = [
+            [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+            [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+            [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+        ];
+        let mut m = [[0.; 4]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = l[i][j] * inv_det;
+            }
+            m[i][3] = -(0..3).map(|k| m[i][k] * a[k][3]).sum::<f64>();
+        }
+        Some(Self { m })
+    }
+    //线性部分是正交矩阵（只有旋转、镜像），长度和角度都不变，立体角的pdf也不变
+    pub fn is_rigid(&self) -> bool {
+        for i in 0..3 {
+            for j in 0..3 {
+                let dot: f64 = (0..3).map(|k| self.m[k][i] * self.m[k][j]).sum();
+                let expected = if i == j { 1. } else { 0. };
+                if (dot - expected).abs() > 1e-6 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    pub fn point(&self, p: Point3) -> Point3 {
+        let mut q = self.vector(p);
+        for i in 0..3 {
+            q[i] += self.m[i][3];
+        }
+        q
+    }
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let mut w = Vec3::new(0., 0., 0.);
+        for i in 0..3 {
+            w[i] = self.m[i][0] * v.x + self.m[i][1] * v.y + self.m[i][2] * v.z;
+        }
+        w
+    }
+    //法向量要乘逆矩阵的转置，self是逆变换的时候直接按列乘
+    pub fn normal_from_inverse(&self, n: Vec3) -> Vec3 {
+        let mut w = Vec3::new(0., 0., 0.);
+        for i in 0..3 {
+            w[i] = self.m[0][i] * n.x + self.m[1][i] * n.y + self.m[2][i] * n.z;
+        }
+        w
+    }
+}
+
+pub struct Instance {
+    prototype: Arc<dyn Hittable>,
+    to_world: Transform,
+    to_object: Transform,
+    material: Option<Arc<dyn Material>>, //有的话替换原型里的材质
+}
+impl Instance {
+    //光线要换回原型的坐标系，变换必须可逆；想做贴花这种扁的东西就给一个很小但不是0的缩放
+    pub fn new(prototype: Arc<dyn Hittable>, transform: Transform) -> Self {
+        let to_object = transform.inverse().unwrap_or_else(|| {
+            panic!(
+                "Instance::new: the instance transform {:?} flattens its prototype to zero volume and cannot be inverted; scale every axis by a nonzero amount",
+                transform.m
+            )
+        });
+        Self {
+            prototype,
+            to_world: transform,
+            to_object,
+            material: None,
+        }
+    }
+    pub fn set_material(&mut self, mp: Arc<dyn Material>) {
+        self.material = Some(mp);
+    }
+}
+
+//刚体变换下原型上的光源套上同一个变换就能采样；有缩放、切变的时候原型的立体角pdf换不过来，
+//这种实例上的光源不采样，和main里一样打一个警告
+fn warn_unsampled_light(reason: &str) {
+    println!(
+        "{}",
+        style(format!(
+            "Warning: a light inside an instance {} cannot be importance sampled, the image will be wrong.",
+            reason
+        ))
+        .yellow()
+    );
+}
+impl Hittable for Instance {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        //方向不单位化，两边的t是一样的
+        let object_r = Ray::new(
+            self.to_object.point(r.orig),
+            self.to_object.vector(r.dir),
+            r.tm,
+        );
+        let mut rec = self.prototype.hit(object_r, t_min, t_max)?;
+        rec.p = self.to_world.point(rec.p);
+        //线性变换不改变法向量朝着光线的哪一面，front_face不用动
+        let normal = |n: Vec3| Vec3::unit_vector(self.to_object.normal_from_inverse(n));
+        rec.normal = normal(rec.normal);
+        rec.geo_normal = normal(rec.geo_normal);
+        rec.set_world_derivatives(
+            self.to_world.vector(rec.dpdu),
+            self.to_world.vector(rec.dpdv),
+            self.to_object.normal_from_inverse(rec.dndu),
+            self.to_object.normal_from_inverse(rec.dndv),
+        );
+        if let Some(mp) = &self.material {
+            rec.mat_ptr = mp.clone();
+        }
+        Some(rec)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        let b = self.prototype.bounding_box(time0, time1)?;
+        let mut minimum = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut maximum = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { b.minimum.x } else { b.maximum.x },
+                if i & 2 == 0 { b.minimum.y } else { b.maximum.y },
+                if i & 4 == 0 { b.minimum.z } else { b.maximum.z },
+            );
+            let p = self.to_world.point(corner);
+            for c in 0..3 {
+                minimum[c] = minimum[c].min(p[c]);
+                maximum[c] = maximum[c].max(p[c]);
+            }
+        }
+        Some(AABB::new(minimum, maximum))
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        self.prototype
+            .pdf_value(self.to_object.point(o), self.to_object.vector(v))
+    }
+    fn random(&self, o: Point3) -> Vec3 {
+        self.to_world
+            .vector(self.prototype.random(self.to_object.point(o)))
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        //换了材质的话原型自己的光源不亮了；换成发光的材质就是整个原型带着新材质当一个光源
+        let lights = match &self.material {
+            Some(mp) if is_emissive(mp) => {
+                if self.prototype.area() <= 0. {
+                    warn_unsampled_light("whose prototype has no area to sample");
+                    return Vec::new();
+                }
+                vec![self.prototype.clone()]
+            }
+            Some(_) => return Vec::new(),
+            None => self.prototype.lights(),
+        };
+        if lights.is_empty() {
+            return lights;
+        }
+        if !self.to_world.is_rigid() {
+            warn_unsampled_light("with a scaled or sheared transform");
+            return Vec::new();
+        }
+        lights
+            .into_iter()
+            .map(|l| {
+                Arc::new(Instance {
+                    prototype: l,
+                    to_world: self.to_world,
+                    to_object: self.to_object,
+                    material: self.material.clone(),
+                }) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+    fn power(&self) -> f64 {
+        match &self.material {
+            Some(mp) => emitted_power(mp, self.prototype.area()),
+            None => self.prototype.power(),
+        }
+    }
+    //只有刚体变换面积不变，别的变换下实例上的光源本来也不采样
+    fn area(&self) -> f64 {
+        self.prototype.area()
+    }
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        let (w, cos_theta) = self.prototype.normal_bounds();
+        (Vec3::unit_vector(self.to_world.vector(w)), cos_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_close, check_sampling, gray};
+    use crate::material::Diffuse_Light;
+    use crate::sphere::Sphere;
+    use crate::vec3::RGBColor;
+
+    fn skewed() -> Transform {
+        Transform::scale(Vec3::new(2., 1., 0.5))
+            .then(&Transform::rotate(Vec3::new(1., 2., 3.), 30.))
+            .then(&Transform::translate(Vec3::new(1., -2., 3.)))
+    }
+
+    #[test]
+    fn then_order() {
+        //先平移再放大，平移量也被放大
+        let t = Transform::translate(Vec3::new(1., 0., 0.))
+            .then(&Transform::scale(Vec3::new(2., 2., 2.)));
+        assert_close(t.point(Point3::new(0., 0., 0.)), Point3::new(2., 0., 0.));
+        //绕z转90度，x轴转到y轴
+        let r = Transform::rotate(Vec3::new(0., 0., 1.), 90.);
+        assert_close(r.vector(Vec3::new(1., 0., 0.)), Vec3::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let t = skewed();
+        let inv = t.inverse().unwrap();
+        for p in [
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 2., 3.),
+            Point3::new(-4., 0.5, 7.),
+        ]
+        .iter()
+        {
+            assert_close(inv.point(t.point(*p)), *p);
+            assert_close(t.point(inv.point(*p)), *p);
+        }
+        //两边乘起来是单位矩阵
+        let id = t.then(&inv);
+        for i in 0..3 {
+            for j in 0..4 {
+                let expected = if i == j { 1. } else { 0. };
+                assert!((id.m[i][j] - expected).abs() < 1e-9);
+            }
+        }
+        //法向量：表面上的切向量变换之后还和法向量垂直
+        let n = Vec3::new(0.3, -1., 0.4);
+        let tangent = Vec3::cross(n, Vec3::new(1., 0., 0.));
+        let n_world = inv.normal_from_inverse(n);
+        assert!(Vec3::dot(n_world, t.vector(tangent)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn singular_inverse() {
+        assert!(Transform::scale(Vec3::new(1., 0., 1.)).inverse().is_none());
+        assert!(Transform::scale(Vec3::new(1., 1e-3, 1.))
+            .inverse()
+            .is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "flattens its prototype")]
+    fn flattened_instance() {
+        let ball: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0., 0., 0.), 1., gray()));
+        Instance::new(ball, Transform::scale(Vec3::new(1., 0., 1.)));
+    }
+
+    #[test]
+    fn rigid() {
+        let turn = Transform::rotate(Vec3::new(1., 1., 0.), 40.);
+        assert!(turn.is_rigid());
+        assert!(turn
+            .then(&Transform::translate(Vec3::new(3., 0., 1.)))
+            .is_rigid());
+        assert!(Transform::scale(Vec3::new(-1., 1., 1.)).is_rigid());
+        assert!(!Transform::scale(Vec3::new(2., 2., 2.)).is_rigid());
+        assert!(!skewed().is_rigid());
+    }
+
+    #[test]
+    fn instance_hit() {
+        //单位球放大两倍挪到z=5
+        let ball: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0., 0., 0.), 1., gray()));
+        let t = Transform::scale(Vec3::new(2., 2., 2.))
+            .then(&Transform::translate(Vec3::new(0., 0., 5.)));
+        let inst = Instance::new(ball, t);
+        let r = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., 0., 1.), 0.);
+        let rec = inst.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.).abs() < 1e-9);
+        assert_close(rec.p, Point3::new(0., 0., 3.));
+        assert_close(rec.normal, Vec3::new(0., 0., -1.));
+        assert!(inst.hit(r, 0.001, 2.5).is_none());
+    }
+
+    #[test]
+    fn instanced_lights() {
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.))),
+        ));
+        let rigid = Transform::rotate(Vec3::new(0., 1., 0.), 30.)
+            .then(&Transform::translate(Vec3::new(0.5, 0., 3.)));
+        let lights = Instance::new(lamp.clone(), rigid).lights();
+        assert_eq!(lights.len(), 1);
+        check_sampling(lights[0].as_ref(), Point3::new(0., 0., 0.));
+        //放大过的光源没法按立体角采样，不交出去
+        let scaled = Transform::scale(Vec3::new(2., 2., 2.));
+        assert!(Instance::new(lamp.clone(), scaled).lights().is_empty());
+        let mut dark = Instance::new(lamp, rigid);
+        dark.set_material(gray());
+        assert!(dark.lights().is_empty());
+        assert_eq!(dark.power(), 0.);
+    }
+
+    #[test]
+    fn emissive_override() {
+        //同一个不发光的原型，换上发光的材质之后整个原型就是光源
+        let ball: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0., 0., 0.), 1., gray()));
+        let glow: Arc<dyn Material> = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+        let lit = Sphere::new(Point3::new(0., 0., 0.), 1., glow.clone());
+        let rigid = Transform::rotate(Vec3::new(1., 0., 0.), 60.)
+            .then(&Transform::translate(Vec3::new(-0.5, 0.5, 3.)));
+        let mut lamp = Instance::new(ball.clone(), rigid);
+        lamp.set_material(glow.clone());
+        let lights = lamp.lights();
+        assert_eq!(lights.len(), 1);
+        assert!(lights[0].power() > 0.);
+        assert!((lights[0].power() - lit.power()).abs() < 1e-9 * lit.power());
+        assert!((lamp.power() - lit.power()).abs() < 1e-9 * lit.power());
+        check_sampling(lights[0].as_ref(), Point3::new(0., 0., 0.));
+        //采样到的光源打中的是新材质
+        let o = Point3::new(0., 0., 0.);
+        let r = Ray::new(o, lights[0].random(o), 0.);
+        let rec = lights[0].hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!(is_emissive(&rec.mat_ptr));
+        //缩放过的还是不采样
+        let mut scaled = Instance::new(ball, Transform::scale(Vec3::new(2., 2., 2.)));
+        scaled.set_material(glow);
+        assert!(scaled.lights().is_empty());
+    }
+}
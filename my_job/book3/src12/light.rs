@@ -0,0 +1,464 @@
+use crate::hittable::{Hittable, HittableList};
+use crate::light_bvh::{LightBounds, LightBvh};
+use crate::ray::Ray;
+use crate::vec3::{Point3, RGBColor, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+//光源：点光源、聚光灯、平行光是delta光源，面光源包一个形状
+
+pub struct LightSample {
+    pub wi: Vec3, //指向光源的单位向量
+    pub dist: f64,
+    pub radiance: RGBColor,
+    pub pdf: f64,
+}
+
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p: Point3) -> Option<LightSample>;
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64;
+    fn is_delta(&self) -> bool {
+        false
+    }
+    fn can_sample(&self) -> bool {
+        true
+    }
+    fn power(&self) -> f64 {
+        0.
+    }
+    //光源BVH用的包围盒和法向量范围，phi由LightList填
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: RGBColor,
+}
+impl PointLight {
+    pub fn new(position: Point3, intensity: RGBColor) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let d = self.position - p;
+        let dist = d.length();
+        if dist <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            wi: d / dist,
+            dist,
+            radiance: self.intensity / (dist * dist),
+            pdf: 1.,
+        })
+    }
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.
+    }
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+pub struct SpotLight {
+    pub position: Point3,
+    pub direction: Vec3,
+    pub intensity: RGBColor,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+}
+impl SpotLight {
+    //角度用度数，falloff_start以内全亮，total_width以外全暗
+    pub fn new(
+        position: Point3,
+        lookat: Point3,
+        intensity: RGBColor,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        Self {
+            position,
+            direction: Vec3::unit_vector(lookat - position),
+            intensity,
+            cos_total_width: total_width.to_radians().cos(),
+            cos_falloff_start: falloff_start.to_radians().cos(),
+        }
+    }
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cos_theta = Vec3::dot(w, self.direction);
+        if cos_theta < self.cos_total_width {
+            return 0.;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.;
+        }
+        let delta =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        delta * delta * delta * delta
+    }
+}
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let d = self.position - p;
+        let dist = d.length();
+        if dist <= 0. {
+            return None;
+        }
+        let wi = d / dist;
+        let falloff = self.falloff(-wi);
+        if falloff <= 0. {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            dist,
+            radiance: self.intensity * falloff / (dist * dist),
+            pdf: 1.,
+        })
+    }
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.
+    }
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+pub struct DirectionalLight {
+    pub direction: Vec3, //光传播的方向
+    pub radiance: RGBColor,
+}
+impl DirectionalLight {
+    pub fn new(direction: Vec3, radiance: RGBColor) -> Self {
+        Self {
+            direction: Vec3::unit_vector(direction),
+            radiance,
+        }
+    }
+}
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            dist: f64::INFINITY,
+            radiance: self.radiance,
+            pdf: 1.,
+        })
+    }
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.
+    }
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+//面光源，形状要实现pdf_value和random
+//不发光的形状（比如玻璃球）也可以放进来，只用来引导采样
+pub struct AreaLight {
+    pub shape: Arc<dyn Hittable>,
+}
+impl AreaLight {
+    pub fn new(shape: Arc<dyn Hittable>) -> Self {
+        Self { shape }
+    }
+}
+impl Light for AreaLight {
+    fn sample_li(&self, p: Point3) -> Option<LightSample> {
+        let dir = self.shape.random(p);
+        let pdf = self.shape.pdf_value(p, dir);
+        let mut sample = LightSample {
+            wi: Vec3::unit_vector(dir),
+            dist: f64::INFINITY,
+            radiance: RGBColor::new(0., 0., 0.),
+            pdf,
+        };
+        let r = Ray::new(p, dir, 0.);
+        if let Some(rec) = self.shape.hit(r, 0.001, f64::INFINITY) {
+            sample.dist = rec.t * dir.length();
+            sample.radiance = rec
+                .mat_ptr
+                .emitted(r, rec.clone(), rec.u, rec.v, rec.p)
+                .unwrap_or_default();
+        }
+        Some(sample)
+    }
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        self.shape.pdf_value(p, wi)
+    }
+    fn power(&self) -> f64 {
+        self.shape.power()
+    }
+    fn bounds(&self) -> Option<LightBounds> {
+        let bbox = self.shape.bounding_box(0., 1.)?;
+        let (w, cos_theta_o) = self.shape.normal_bounds();
+        //发不发光看材质是哪一面，这里保守地当成两面
+        Some(LightBounds::new(bbox, 0., w, cos_theta_o, true))
+    }
+    //从包围盒外面找个点试着采样一次，形状没实现pdf_value/random的话pdf是0
+    fn can_sample(&self) -> bool {
+        if let Some(bbox) = self.shape.bounding_box(0., 1.) {
+            let center = (bbox.minimum + bbox.maximum) / 2.;
+            let diag = (bbox.maximum - bbox.minimum).length();
+            let o = center + Vec3::new(0.48, 0.6, 0.64) * (diag + 1.);
+            let dir = self.shape.random(o);
+            let pdf = self.shape.pdf_value(o, dir);
+            pdf > 0. && pdf.is_finite()
+        } else {
+            false
+        }
+    }
+}
+
+//面光源按功率挑，delta光源在积分器里单独算
+#[derive(Default)]
+pub struct LightList {
+    pub lights: Vec<Arc<dyn Light>>,
+    powers: Vec<f64>,
+    weights: Vec<f64>,
+    total_weight: f64,
+    bvh: Option<LightBvh>,
+}
+impl LightList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //自动把场景里发光的图元都找出来
+    pub fn from_world(world: &HittableList) -> Self {
+        let mut list = LightList::new();
+        for shape in world.lights() {
+            list.add(Arc::new(AreaLight::new(shape)));
+        }
+        list
+    }
+    pub fn add(&mut self, light: Arc<dyn Light>) {
+        self.powers.push(light.power());
+        self.lights.push(light);
+        self.update_weights();
+        self.bvh = None;
+    }
+    //光源很多的时候用光源BVH按着色点挑光源，光源加完之后再调用
+    pub fn build_bvh(&mut self) {
+        let mut items = Vec::new();
+        for i in 0..self.lights.len() {
+            if self.weights[i] <= 0. {
+                continue;
+            }
+            if let Some(mut bounds) = self.lights[i].bounds() {
+                bounds.phi = self.weights[i];
+                items.push((self.lights[i].clone(), bounds));
+            } else {
+                //没有包围盒的光源没法放进BVH，还是按功率挑
+                return;
+            }
+        }
+        self.bvh = Some(LightBvh::new(items));
+    }
+    //不发光但想引导采样的物体（比如玻璃球）
+    pub fn add_importance(&mut self, shape: Arc<dyn Hittable>) {
+        self.add(Arc::new(AreaLight::new(shape)));
+    }
+    //功率估不出来的面光源（包括importance物体）按平均功率算
+    fn update_weights(&mut self) {
+        let mut sum = 0.;
+        let mut count = 0;
+        for i in 0..self.lights.len() {
+            if !self.lights[i].is_delta() && self.powers[i] > 0. {
+                sum += self.powers[i];
+                count += 1;
+            }
+        }
+        let average = if count == 0 { 1. } else { sum / count as f64 };
+        self.weights.clear();
+        for i in 0..self.lights.len() {
+            if self.lights[i].is_delta() {
+                self.weights.push(0.);
+            } else if self.powers[i] > 0. {
+                self.weights.push(self.powers[i]);
+            } else {
+                self.weights.push(average);
+            }
+        }
+        self.total_weight = self.weights.iter().sum();
+    }
+    pub fn delta_lights(&self) -> impl Iterator<Item = &Arc<dyn Light>> {
+        self.lights.iter().filter(|l| l.is_delta())
+    }
+    pub fn area_count(&self) -> usize {
+        self.lights.iter().filter(|l| !l.is_delta()).count()
+    }
+    pub fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        if let Some(bvh) = &self.bvh {
+            if bvh.is_empty() {
+                return 0.;
+            }
+            if !bvh.reaches(o) {
+                return 1. / (4. * PI);
+            }
+            return bvh.pdf_value(o, v);
+        }
+        if self.total_weight <= 0. {
+            return 0.;
+        }
+        let mut sum = 0.;
+        for i in 0..self.lights.len() {
+            if self.weights[i] > 0. {
+                sum += self.weights[i] * self.lights[i].pdf_li(o, v);
+            }
+        }
+        sum / self.total_weight
+    }
+    pub fn random(&self, o: Point3) -> Vec3 {
+        if let Some(bvh) = &self.bvh {
+            //哪个光源都照不到这里，退回均匀球面采样，和pdf_value对应
+            return match bvh.sample(o) {
+                Some((light, _)) => match light.sample_li(o) {
+                    Some(sample) => sample.wi,
+                    None => Vec3::new(1., 0., 0.),
+                },
+                None => Vec3::random_unit_vector(),
+            };
+        }
+        if self.total_weight <= 0. {
+            return Vec3::new(1., 0., 0.);
+        }
+        let mut pick = rand::thread_rng().gen_range(0.0..self.total_weight);
+        let mut target = 0;
+        for i in 0..self.lights.len() {
+            if self.weights[i] <= 0. {
+                continue;
+            }
+            target = i;
+            if pick < self.weights[i] {
+                break;
+            }
+            pick -= self.weights[i];
+        }
+        match self.lights[target].sample_li(o) {
+            Some(sample) => sample.wi,
+            None => Vec3::new(1., 0., 0.),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{luminance, Diffuse_Light};
+    use crate::sphere::Sphere;
+
+    //地面上方一片亮度不同的小球灯
+    fn light_field() -> HittableList {
+        let mut world = HittableList::new();
+        for a in -5..5 {
+            for b in -5..5 {
+                let k = ((a + 5) * 10 + b + 5) % 7 + 1;
+                world.add(Arc::new(Sphere::new(
+                    Point3::new(a as f64 * 2., 0.3, b as f64 * 2.),
+                    0.2,
+                    Arc::new(Diffuse_Light::new(RGBColor::new(1., 1., 1.) * k as f64)),
+                )));
+            }
+        }
+        world
+    }
+
+    //地面上一点的辐照度，只按光源列表采样：E ≈ L·cosθ/pdf
+    fn irradiance(world: &HittableList, lights: &LightList, p: Point3, n: usize) -> f64 {
+        let mut sum = 0.;
+        for _ in 0..n {
+            let wi = lights.random(p);
+            let pdf = lights.pdf_value(p, wi);
+            let cos = Vec3::dot(Vec3::unit_vector(wi), Vec3::new(0., 1., 0.));
+            if pdf <= 0. || cos <= 0. {
+                continue;
+            }
+            let r = Ray::new(p, wi, 0.);
+            if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
+                let le = rec
+                    .mat_ptr
+                    .emitted(r, rec.clone(), rec.u, rec.v, rec.p)
+                    .unwrap_or_default();
+                sum += luminance(le) * cos / pdf;
+            }
+        }
+        sum / n as f64
+    }
+
+    //同样的样本数，光源BVH按着色点挑光源的误差要比按功率挑小；
+    //参考值用按功率挑的两万个样本，和BVH没关系，BVH有偏的话误差会变大
+    #[test]
+    fn light_bvh_beats_power_sampling() {
+        let world = light_field();
+        let by_power = LightList::from_world(&world);
+        let mut by_bvh = LightList::from_world(&world);
+        by_bvh.build_bvh();
+        let points: Vec<Point3> = (0..16)
+            .map(|i| Point3::new((i % 4) as f64 * 4. - 7., 0., (i / 4) as f64 * 4. - 7.))
+            .collect();
+        let (mut err_power, mut err_bvh) = (0., 0.);
+        for p in points.iter() {
+            let reference = irradiance(&world, &by_power, *p, 20000);
+            for _ in 0..40 {
+                err_power += (irradiance(&world, &by_power, *p, 16) - reference).powi(2);
+                err_bvh += (irradiance(&world, &by_bvh, *p, 16) - reference).powi(2);
+            }
+        }
+        let rmse_power = (err_power / 640.).sqrt();
+        let rmse_bvh = (err_bvh / 640.).sqrt();
+        assert!(
+            rmse_bvh < rmse_power,
+            "16 spp RMSE: power {:.4}, light BVH {:.4}",
+            rmse_power,
+            rmse_bvh
+        );
+    }
+
+    //朝某个光源方向的pdf_value除以这个光源自己的pdf就是BVH挑中它的概率，
+    //要和sample返回的概率、真的挑中它的频率都对上
+    #[test]
+    fn light_bvh_pdf_matches_sampling() {
+        let mut list = LightList::from_world(&light_field());
+        list.build_bvh();
+        let bvh = list.bvh.as_ref().unwrap();
+        //从上面看下去，每个方向上最多一个小球
+        let o = Point3::new(0.5, 3., 0.5);
+        let index = |light: &Arc<dyn Light>| {
+            list.lights
+                .iter()
+                .position(|l| Arc::ptr_eq(l, light))
+                .unwrap()
+        };
+        let n = 200000;
+        let mut counts = vec![0.; list.lights.len()];
+        let mut pmfs = vec![0.; list.lights.len()];
+        for _ in 0..n {
+            let (light, pmf) = bvh.sample(o).unwrap();
+            let i = index(light);
+            counts[i] += 1.;
+            pmfs[i] = pmf;
+        }
+        for (i, light) in list.lights.iter().enumerate() {
+            let wi = light.sample_li(o).unwrap().wi;
+            let pmf = bvh.pdf_value(o, wi) / light.pdf_li(o, wi);
+            let freq = counts[i] / n as f64;
+            let tolerance = 5. * (pmf / n as f64).sqrt() + 1e-4;
+            assert!(
+                (freq - pmf).abs() < tolerance,
+                "light {}: picked {:.5} of the time, pdf_value says {:.5}",
+                i,
+                freq,
+                pmf
+            );
+            if counts[i] > 0. {
+                assert!((pmfs[i] - pmf).abs() < 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::aabb::AABB;
+use crate::light::Light;
+use crate::vec3::{Point3, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+//光源BVH：每个节点存包围盒、总功率和法向量的圆锥，按对着色点的贡献估计往下挑
+
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub bbox: AABB,
+    pub phi: f64,
+    pub w: Vec3, //法向量圆锥的轴
+    pub cos_theta_o: f64,
+    pub two_sided: bool,
+}
+
+fn rotate(v: Vec3, axis: Vec3, theta: f64) -> Vec3 {
+    let k = Vec3::unit_vector(axis);
+    v * theta.cos() + Vec3::cross(k, v) * theta.sin() + k * Vec3::dot(k, v) * (1. - theta.cos())
+}
+
+impl LightBounds {
+    pub fn new(bbox: AABB, phi: f64, w: Vec3, cos_theta_o: f64, two_sided: bool) -> Self {
+        Self {
+            bbox,
+            phi,
+            w: Vec3::unit_vector(w),
+            cos_theta_o,
+            two_sided,
+        }
+    }
+    fn centroid(&self) -> Point3 {
+        (self.bbox.minimum + self.bbox.maximum) / 2.
+    }
+    //两个法向量圆锥的并
+    fn union_cone(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+        if a.1 <= -1. || b.1 <= -1. {
+            return (a.0, -1.);
+        }
+        let theta_a = a.1.clamp(-1., 1.).acos();
+        let theta_b = b.1.clamp(-1., 1.).acos();
+        let theta_d = Vec3::dot(a.0, b.0).clamp(-1., 1.).acos();
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return a;
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return b;
+        }
+        let theta_o = (theta_a + theta_d + theta_b) / 2.;
+        if theta_o >= PI {
+            return (a.0, -1.);
+        }
+        let wr = Vec3::cross(a.0, b.0);
+        if wr.length_squared() < 1e-12 {
+            return (a.0, -1.);
+        }
+        (rotate(a.0, wr, theta_o - theta_a), theta_o.cos())
+    }
+    pub fn union(a: &LightBounds, b: &LightBounds) -> LightBounds {
+        if a.phi <= 0. {
+            return *b;
+        }
+        if b.phi <= 0. {
+            return *a;
+        }
+        let (w, cos_theta_o) = LightBounds::union_cone((a.w, a.cos_theta_o), (b.w, b.cos_theta_o));
+        LightBounds {
+            bbox: AABB::surrounding_box(a.bbox, b.bbox),
+            phi: a.phi + b.phi,
+            w,
+            cos_theta_o,
+            two_sided: a.two_sided || b.two_sided,
+        }
+    }
+    //从p点看过去这一堆光源大概能贡献多少，不需要归一化
+    pub fn importance(&self, p: Point3) -> f64 {
+        if self.phi <= 0. {
+            return 0.;
+        }
+        let pc = self.centroid();
+        let radius = (self.bbox.maximum - self.bbox.minimum).length() / 2.;
+        let d2 = (p - pc)
+            .length_squared()
+            .max(radius * radius / 4.)
+            .max(1e-12);
+        if self.cos_theta_o <= -1. {
+            return self.phi / d2;
+        }
+        let wi = Vec3::unit_vector(p - pc);
+        let mut cos_theta_w = Vec3::dot(self.w, wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = (1. - cos_theta_w * cos_theta_w).max(0.).sqrt();
+        //包围盒从p点看过去张开的角度
+        let cos_theta_b = if (p - pc).length_squared() <= radius * radius {
+            -1.
+        } else {
+            (1. - radius * radius / (p - pc).length_squared())
+                .max(0.)
+                .sqrt()
+        };
+        let sin_theta_b = (1. - cos_theta_b * cos_theta_b).max(0.).sqrt();
+        let cos_theta_o = self.cos_theta_o;
+        let sin_theta_o = (1. - cos_theta_o * cos_theta_o).max(0.).sqrt();
+        // cos(max(0, θw - θo))
+        let (cos_x, sin_x) = if cos_theta_w > cos_theta_o {
+            (1., 0.)
+        } else {
+            (
+                cos_theta_w * cos_theta_o + sin_theta_w * sin_theta_o,
+                sin_theta_w * cos_theta_o - cos_theta_w * sin_theta_o,
+            )
+        };
+        // cos(max(0, θw - θo - θb))
+        let cos_theta_p = if cos_x > cos_theta_b {
+            1.
+        } else {
+            cos_x * cos_theta_b + sin_x * sin_theta_b
+        };
+        //朗伯光源，发光张角是π/2
+        if cos_theta_p <= 0. {
+            return 0.;
+        }
+        self.phi * cos_theta_p / d2
+    }
+}
+
+struct LightBvhNode {
+    bounds: LightBounds,
+    sphere: (Point3, f64), //包住子树里所有光源采样范围的球，算pdf时用来剪枝
+    children: Option<(usize, usize)>,
+    light: usize,
+}
+
+fn bounding_sphere(bbox: &AABB) -> (Point3, f64) {
+    (
+        (bbox.minimum + bbox.maximum) / 2.,
+        (bbox.maximum - bbox.minimum).length() / 2.,
+    )
+}
+
+fn union_sphere(a: (Point3, f64), b: (Point3, f64)) -> (Point3, f64) {
+    let d = (b.0 - a.0).length();
+    if d + b.1 <= a.1 {
+        return a;
+    }
+    if d + a.1 <= b.1 {
+        return b;
+    }
+    let radius = (d + a.1 + b.1) / 2.;
+    let center = a.0 + (b.0 - a.0) * ((radius - a.1) / d);
+    (center, radius)
+}
+
+fn ray_hits_sphere(o: Point3, v: Vec3, sphere: (Point3, f64)) -> bool {
+    let oc = o - sphere.0;
+    let c = oc.length_squared() - sphere.1 * sphere.1;
+    if c <= 0. {
+        return true;
+    }
+    let a = v.length_squared();
+    let half_b = Vec3::dot(oc, v);
+    half_b < 0. && half_b * half_b - a * c >= 0.
+}
+
+pub struct LightBvh {
+    lights: Vec<Arc<dyn Light>>,
+    nodes: Vec<LightBvhNode>,
+}
+
+impl LightBvh {
+    pub fn new(lights: Vec<(Arc<dyn Light>, LightBounds)>) -> Self {
+        let mut bvh = Self {
+            lights: Vec::new(),
+            nodes: Vec::new(),
+        };
+        let mut items = Vec::new();
+        for (light, bounds) in lights {
+            if bounds.phi > 0. {
+                items.push((bvh.lights.len(), bounds));
+                bvh.lights.push(light);
+            }
+        }
+        if !items.is_empty() {
+            bvh.build(items);
+        }
+        bvh
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn reaches(&self, p: Point3) -> bool {
+        !self.nodes.is_empty() && self.nodes[0].bounds.importance(p) > 0.
+    }
+    //按重心最长的轴从中间分开，返回节点下标
+    fn build(&mut self, mut items: Vec<(usize, LightBounds)>) -> usize {
+        if items.len() == 1 {
+            self.nodes.push(LightBvhNode {
+                bounds: items[0].1,
+                sphere: bounding_sphere(&items[0].1.bbox),
+                children: None,
+                light: items[0].0,
+            });
+            return self.nodes.len() - 1;
+        }
+        let mut minimum = items[0].1.centroid();
+        let mut maximum = items[0].1.centroid();
+        for item in items.iter() {
+            let c = item.1.centroid();
+            for k in 0..3 {
+                minimum[k] = minimum[k].min(c[k]);
+                maximum[k] = maximum[k].max(c[k]);
+            }
+        }
+        let extent = maximum - minimum;
+        let mut axis = 0;
+        if extent.y > extent[axis] {
+            axis = 1;
+        }
+        if extent.z > extent[axis] {
+            axis = 2;
+        }
+        items.sort_by(|a, b| {
+            a.1.centroid()[axis]
+                .partial_cmp(&b.1.centroid()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let right_items = items.split_off(items.len() / 2);
+        let index = self.nodes.len();
+        self.nodes.push(LightBvhNode {
+            bounds: items[0].1,
+            sphere: bounding_sphere(&items[0].1.bbox),
+            children: None,
+            light: 0,
+        });
+        let left = self.build(items);
+        let right = self.build(right_items);
+        self.nodes[index].bounds =
+            LightBounds::union(&self.nodes[left].bounds, &self.nodes[right].bounds);
+        self.nodes[index].sphere = union_sphere(self.nodes[left].sphere, self.nodes[right].sphere);
+        self.nodes[index].children = Some((left, right));
+        index
+    }
+    fn child_probs(&self, left: usize, right: usize, p: Point3) -> (f64, f64) {
+        let i0 = self.nodes[left].bounds.importance(p);
+        let i1 = self.nodes[right].bounds.importance(p);
+        if i0 + i1 <= 0. {
+            return (0.5, 0.5);
+        }
+        (i0 / (i0 + i1), i1 / (i0 + i1))
+    }
+    //挑一个光源，返回光源和被挑中的概率
+    pub fn sample(&self, p: Point3) -> Option<(&Arc<dyn Light>, f64)> {
+        if !self.reaches(p) {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let mut node = 0;
+        let mut pmf = 1.;
+        while let Some((left, right)) = self.nodes[node].children {
+            let (p0, p1) = self.child_probs(left, right, p);
+            if rng.gen::<f64>() < p0 {
+                node = left;
+                pmf *= p0;
+            } else {
+                node = right;
+                pmf *= p1;
+            }
+        }
+        Some((&self.lights[self.nodes[node].light], pmf))
+    }
+    //沿着方向v往下走，只访问包围球被打中的节点，把 pmf * 光源pdf 加起来
+    pub fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        if !self.reaches(o) {
+            return 0.;
+        }
+        let mut sum = 0.;
+        let mut stack = vec![(0, 1.)];
+        while let Some((node, pmf)) = stack.pop() {
+            if !ray_hits_sphere(o, v, self.nodes[node].sphere) {
+                continue;
+            }
+            match self.nodes[node].children {
+                Some((left, right)) => {
+                    let (p0, p1) = self.child_probs(left, right, o);
+                    if p0 > 0. {
+                        stack.push((left, pmf * p0));
+                    }
+                    if p1 > 0. {
+                        stack.push((right, pmf * p1));
+                    }
+                }
+                None => {
+                    sum += pmf * self.lights[self.nodes[node].light].pdf_li(o, v);
+                }
+            }
+        }
+        sum
+    }
+}
@@ -1,12 +1,34 @@
+use crate::aabb::AABB;
 use crate::aarect::{xy_rect, xz_rect, yz_rect};
 use crate::boxes::Box;
 use crate::bvh::BvhNode;
-use crate::camera::Camera;
-use crate::hittable::{flip_face, HittableList, Translate};
-use crate::material::{Dielectric, Lambertian, Metal}; //各向同性的
+use crate::camera::CameraSettings;
+use crate::csg::Csg;
+use crate::hittable::{flip_face, Hittable, HittableList, Translate};
+use crate::instance::{Instance, Transform};
+use crate::light::{LightList, PointLight, SpotLight};
+use crate::material::{
+    BumpMap, Coated, Dielectric, Lambertian, Material, Metal, MixMaterial, ThinFilm,
+}; //各向同性的
+use crate::perlin::NoiseKind;
+use crate::postprocess::{HdrImage, PostProcess};
+use crate::quad::Quad;
+use crate::quadric::{Cone, Cylinder, Disk, Paraboloid};
 use crate::ray::Ray;
+use crate::sdf::{
+    Box_Sdf, Intersect_Sdf, Mandelbulb_Sdf, Repeat_Sdf, SdfObject, SmoothUnion_Sdf, Sphere_Sdf,
+    Subtract_Sdf, Torus_Sdf,
+};
+use crate::spectrum::SampledWavelengths;
 use crate::sphere::{MovingSphere, Sphere};
-use crate::texture::{Checker_Texture, Image_Texture, Noise_Texture};
+use crate::texture::{
+    Brick_Texture, Checker_Texture, ColorRamp, Fbm_Texture, Gradient, Gradient_Texture,
+    Image_Texture, Mapped_Texture, Marble_Texture, Mix_Texture, Multiply_Texture, Noise_Texture,
+    Ramp_Texture, Remap_Texture, Ridged_Texture, Solid_Color, Texture, TextureFilter,
+    TextureMapping, Wood_Texture, WorleyFeature, Worley_Texture,
+};
+use crate::torus::Torus;
+use crate::triangle::Mesh;
 use crate::vec3::{Point3, RGBColor, Vec3};
 use console::style;
 use constant_medium::Constant_Medium;
@@ -15,6 +37,7 @@ use image::{ImageBuffer, RgbImage};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use material::Diffuse_Light;
 use std::{
+    f64::consts::PI,
     fs::File,
     process::exit,
     sync::{mpsc, Arc},
@@ -23,19 +46,35 @@ use std::{
 };
 pub mod aabb;
 pub mod aarect;
+pub mod aperture;
 pub mod boxes;
 pub mod bvh;
 pub mod camera;
 pub mod constant_medium;
+pub mod csg;
+pub mod exposure;
 pub mod hittable;
+pub mod instance;
+pub mod light;
+pub mod light_bvh;
 pub mod material;
+pub mod medium_stack;
 pub mod onb;
 pub mod pdf;
 pub mod perlin;
+pub mod postprocess;
+pub mod quad;
+pub mod quadric;
 pub mod ray;
+pub mod realistic_camera;
+pub mod sdf;
+pub mod spectrum;
 pub mod sphere;
 pub mod texture;
+pub mod torus;
+pub mod triangle;
 pub mod vec3; //调用模块
+pub mod worley;
 use rand::Rng;
 fn write_color(pixel_color: RGBColor, samples_per_pixel: i32) -> [u8; 3] {
     let mut r = pixel_color.x;
@@ -199,7 +238,7 @@ fn simple_light() -> HittableList {
     objects.add(Arc::new(xy_rect::new(3., 5., 1., 3., -2., difflight)));
     return objects;
 }
-fn cornell_box() -> HittableList {
+fn cornell_box() -> (HittableList, LightList, CameraSettings) {
     let mut objects = HittableList::new();
     /*let checker = Arc::new(Checker_Texture::new(
         RGBColor::new(0.2, 0.3, 0.1),
@@ -249,12 +288,88 @@ fn cornell_box() -> HittableList {
     let box1 = Arc::new(Translate::new(box1, Vec3::new(265., 0., 295.)));
     objects.add(box1);
     let glass = Arc::new(Dielectric::new(1.5));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(190., 90., 190.),
-        90.,
-        glass,
+    let glass_sphere = Arc::new(Sphere::new(Point3::new(190., 90., 190.), 90., glass));
+    objects.add(glass_sphere.clone());
+    let mut lights = LightList::from_world(&objects);
+    lights.add_importance(glass_sphere);
+    lights.build_bvh();
+    let mut cam = CameraSettings::new(
+        Point3::new(278., 278., -800.),
+        Point3::new(278., 278., 0.),
+        40.,
+    );
+    cam.focus_dist = 10.;
+    //换成真实镜头：lookfrom是胶片的位置，场景单位当成mm，对焦到盒子中间
+    // cam.focus_dist = 1078.;
+    // cam.projection = camera::Projection::Realistic {
+    //     lens_file: "dgauss.50mm.dat".to_string(),
+    //     aperture_diameter: 8.,
+    //     film_diagonal: 43.27,
+    //     mm_per_unit: 1.,
+    // };
+    //物理曝光：50mm f/1.4、1秒、ISO 200，亮度和原来差不多
+    // cam.focus_dist = 800.;
+    // cam.exposure = Some(exposure::Exposure::new(1.4, 1., 200., 50.));
+    return (objects, lights, cam);
+}
+fn cornell_spot_light() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let red = Arc::new(Lambertian::new(RGBColor::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(RGBColor::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(RGBColor::new(0.12, 0.45, 0.15)));
+    objects.add(Arc::new(yz_rect::new(0., 555., 0., 555., 555., green)));
+    objects.add(Arc::new(yz_rect::new(0., 555., 0., 555., 0., red)));
+    objects.add(Arc::new(xz_rect::new(
+        0.,
+        555.,
+        0.,
+        555.,
+        0.,
+        white.clone(),
     )));
-    return objects;
+    objects.add(Arc::new(xz_rect::new(
+        0.,
+        555.,
+        0.,
+        555.,
+        555.,
+        white.clone(),
+    )));
+    objects.add(Arc::new(xy_rect::new(
+        0.,
+        555.,
+        0.,
+        555.,
+        555.,
+        white.clone(),
+    )));
+    let box1 = Arc::new(Box::new(
+        Point3::new(0., 0., 0.),
+        Point3::new(165., 330., 165.),
+        white,
+    ));
+    let box1 = Arc::new(RotateY::new(box1, 15.));
+    let box1 = Arc::new(Translate::new(box1, Vec3::new(265., 0., 295.)));
+    objects.add(box1);
+    let mut lights = LightList::from_world(&objects);
+    lights.add(Arc::new(SpotLight::new(
+        Point3::new(278., 550., 278.),
+        Point3::new(278., 0., 278.),
+        RGBColor::new(300000., 300000., 300000.),
+        30.,
+        20.,
+    )));
+    lights.add(Arc::new(PointLight::new(
+        Point3::new(100., 400., 100.),
+        RGBColor::new(20000., 20000., 20000.),
+    )));
+    let mut cam = CameraSettings::new(
+        Point3::new(278., 278., -800.),
+        Point3::new(278., 278., 0.),
+        40.,
+    );
+    cam.focus_dist = 10.;
+    return (objects, lights, cam);
 }
 fn cornell_smoke() -> HittableList {
     let mut objects = HittableList::new();
@@ -380,6 +495,856 @@ pub fn cornell_smoke() -> HittableList {
 }
 
 */
+//重火石玻璃三棱镜，光谱模式下能看到色散
+fn prism() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let white = Arc::new(Lambertian::new(RGBColor::new(0.73, 0.73, 0.73)));
+    objects.add(Arc::new(xz_rect::new(-20., 20., -20., 20., 0., white)));
+    //横截面是三角形，沿z方向拉长，三角形都按逆时针朝外
+    let positions = vec![
+        Point3::new(-1., 0., 1.5),
+        Point3::new(1., 0., 1.5),
+        Point3::new(0., 1.7, 1.5),
+        Point3::new(-1., 0., -1.5),
+        Point3::new(1., 0., -1.5),
+        Point3::new(0., 1.7, -1.5),
+    ];
+    let indices = vec![
+        [0, 1, 2],
+        [3, 5, 4],
+        [3, 4, 1],
+        [3, 1, 0],
+        [4, 5, 2],
+        [4, 2, 1],
+        [5, 3, 0],
+        [5, 0, 2],
+    ];
+    objects.add(Arc::new(Mesh::new(
+        positions,
+        Vec::new(),
+        Vec::new(),
+        indices,
+        Arc::new(Dielectric::sf11()),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-6., 3., 0.),
+        0.5,
+        Arc::new(Diffuse_Light::new(RGBColor::new(60., 60., 60.))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 4., 9.), Point3::new(1., 0.5, 0.), 40.);
+    return (objects, lights, cam);
+}
+//肥皂泡和阳极氧化的金属球，膜厚用噪声纹理
+fn thin_film() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let checker = Arc::new(Checker_Texture::new(
+        RGBColor::new(0.2, 0.3, 0.1),
+        RGBColor::new(0.9, 0.9, 0.9),
+    ));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(Lambertian::new_arc(checker)),
+    )));
+    let bubble = ThinFilm::new_arc(
+        Arc::new(Dielectric::new(1.)),
+        Arc::new(Noise_Texture::new(2.)),
+        800.,
+        1.33,
+        1.,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1., 0.),
+        1.,
+        Arc::new(bubble),
+    )));
+    let anodised = ThinFilm::new(
+        Arc::new(Metal::new(RGBColor::new(0.6, 0.6, 0.65), 0.05)),
+        350.,
+        2.,
+        2.5,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.2, 1., 0.),
+        1.,
+        Arc::new(anodised),
+    )));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -3., 3., -3., 3., 6., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 7.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//有色玻璃和嵌套的介质：左边是吸收红光以外的玻璃球，右边是装满水的玻璃球，水里有个气泡
+fn nested_glass() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let checker = Arc::new(Checker_Texture::new(
+        RGBColor::new(0.2, 0.3, 0.1),
+        RGBColor::new(0.9, 0.9, 0.9),
+    ));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(Lambertian::new_arc(checker)),
+    )));
+    let mut ruby = Dielectric::new(1.5);
+    ruby.set_color(RGBColor::new(0.9, 0.2, 0.25), 1.);
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1., 0.),
+        1.,
+        Arc::new(ruby),
+    )));
+    //水和玻璃重叠的部分算水，玻璃只剩下外面一层壳
+    let mut glass = Dielectric::new(1.5);
+    glass.set_priority(1);
+    let mut water = Dielectric::new(1.33);
+    water.set_color(RGBColor::new(0.6, 0.85, 0.95), 1.);
+    water.set_priority(2);
+    let mut bubble = Dielectric::new(1.);
+    bubble.set_priority(3);
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.2, 1., 0.),
+        1.,
+        Arc::new(glass),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.2, 1., 0.),
+        0.9,
+        Arc::new(water),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.3, 1.2, 0.2),
+        0.3,
+        Arc::new(bubble),
+    )));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -3., 3., -3., 3., 6., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 7.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//次表面散射：大理石、蜡、皮肤，后面放个灯能看出透光
+fn subsurface() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let ground = Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5)));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        ground,
+    )));
+    let marble = Dielectric::subsurface(
+        1.5,
+        RGBColor::new(0.9, 0.9, 0.88),
+        RGBColor::new(0.3, 0.3, 0.3),
+    );
+    let wax = Dielectric::subsurface(
+        1.45,
+        RGBColor::new(0.95, 0.75, 0.4),
+        RGBColor::new(0.5, 0.3, 0.15),
+    );
+    let skin = Dielectric::subsurface(
+        1.4,
+        RGBColor::new(0.85, 0.55, 0.45),
+        RGBColor::new(0.4, 0.15, 0.08),
+    );
+    for (x, mat) in [(-2.2, marble), (0., wax), (2.2, skin)] {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(x, 1., 0.),
+            1.,
+            Arc::new(mat),
+        )));
+    }
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -3., 3., -3., 3., 6., light,
+    )))));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., 1.5, -3.),
+        0.3,
+        Arc::new(Diffuse_Light::new(RGBColor::new(20., 16., 12.))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 9.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//混合和分层的材质：地面按棋盘格在金属和漫反射之间挑，两个球是刷了清漆的漫反射
+fn layered() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let mask = Arc::new(Checker_Texture::new(
+        RGBColor::new(0., 0., 0.),
+        RGBColor::new(1., 1., 1.),
+    ));
+    let ground = MixMaterial::new_arc(
+        Arc::new(Metal::new(RGBColor::new(0.8, 0.8, 0.8), 0.1)),
+        Arc::new(Lambertian::new(RGBColor::new(0.2, 0.3, 0.1))),
+        mask,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(ground),
+    )));
+    let glossy = Coated::new(
+        Arc::new(Lambertian::new(RGBColor::new(0.7, 0.1, 0.1))),
+        1.5,
+        0.,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1., 0.),
+        1.,
+        Arc::new(glossy),
+    )));
+    let satin = Coated::new(
+        Arc::new(Lambertian::new(RGBColor::new(0.1, 0.2, 0.6))),
+        1.5,
+        0.2,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.2, 1., 0.),
+        1.,
+        Arc::new(satin),
+    )));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -3., 3., -3., 3., 6., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 7.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//各种面光源：天花板上两面发光、按功率定亮度、带聚光衰减的灯板，后面是贴了图的屏幕
+fn area_lights() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let ground = Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5)));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        ground,
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1., 0.),
+        1.,
+        Arc::new(Lambertian::new(RGBColor::new(0.7, 0.7, 0.7))),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.2, 1., 0.),
+        1.,
+        Arc::new(Metal::new(RGBColor::new(0.8, 0.8, 0.8), 0.2)),
+    )));
+    //不用flip_face也能从下面照下来
+    let mut panel = Diffuse_Light::blackbody(3500., 1.);
+    panel.set_two_sided(true);
+    panel.set_spot(40., 20.);
+    panel.set_power(300., 4.);
+    objects.add(Arc::new(xz_rect::new(
+        -1.,
+        1.,
+        -1.,
+        1.,
+        4.,
+        Arc::new(panel),
+    )));
+    let mut screen = Diffuse_Light::new_arc(Arc::new(Image_Texture::new("earthmap.jpg")));
+    screen.set_intensity(1.5);
+    objects.add(Arc::new(xy_rect::new(
+        -3.,
+        3.,
+        0.5,
+        3.5,
+        -3.,
+        Arc::new(screen),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 8.), Point3::new(0., 1.5, 0.), 40.);
+    return (objects, lights, cam);
+}
+//贴图重复很多次的地面，低角度看过去远处靠mipmap和光线微分不闪，镜面球里的倒影也一样
+fn texture_filtering() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let mut floor = Image_Texture::new("earthmap.jpg");
+    floor.filter = TextureFilter::Trilinear;
+    floor.scale = (40., 40.);
+    objects.add(Arc::new(xz_rect::new(
+        -100.,
+        100.,
+        -100.,
+        100.,
+        0.,
+        Arc::new(Lambertian::new_arc(Arc::new(floor))),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., 1., 0.),
+        1.,
+        Arc::new(Metal::new(RGBColor::new(0.9, 0.9, 0.9), 0.)),
+    )));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -5., 5., -5., 5., 10., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 1.5, 8.), Point3::new(0., 0.8, 0.), 40.);
+    return (objects, lights, cam);
+}
+//凹凸和置换：地面和左边的球用噪声做凹凸贴图，右边是细分以后按噪声置换的八面体
+fn bump_mapping() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let ground = BumpMap::new(
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+        Arc::new(Noise_Texture::new(4.)),
+        0.05,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(ground),
+    )));
+    let hammered = BumpMap::new(
+        Arc::new(Metal::new(RGBColor::new(0.8, 0.6, 0.2), 0.05)),
+        Arc::new(Noise_Texture::new(8.)),
+        0.02,
+    );
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1., 0.),
+        1.,
+        Arc::new(hammered),
+    )));
+    let positions = vec![
+        Point3::new(1., 0., 0.),
+        Point3::new(-1., 0., 0.),
+        Point3::new(0., 1., 0.),
+        Point3::new(0., -1., 0.),
+        Point3::new(0., 0., 1.),
+        Point3::new(0., 0., -1.),
+    ];
+    let indices = vec![
+        [0, 2, 4],
+        [4, 2, 1],
+        [1, 2, 5],
+        [5, 2, 0],
+        [4, 3, 0],
+        [1, 3, 4],
+        [5, 3, 1],
+        [0, 3, 5],
+    ];
+    let rock = Mesh::new(
+        positions,
+        Vec::new(),
+        Vec::new(),
+        indices,
+        Arc::new(Lambertian::new(RGBColor::new(0.6, 0.4, 0.3))),
+    )
+    .displace(&Noise_Texture::new(3.), 0.15, 5);
+    objects.add(Arc::new(Translate::new(
+        Arc::new(rock),
+        Vec3::new(1.2, 1., 0.),
+    )));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -3., 3., -3., 3., 6., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 2., 7.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//程序纹理：一排球分别是木头、大理石、砖、Worley、ridged，地面是按fBm混合的两种瓷砖
+fn procedural_textures() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let solid = |r: f64, g: f64, b: f64| Arc::new(Solid_Color::new(r, g, b)) as Arc<dyn Texture>;
+    let tiles: Arc<dyn Texture> = Arc::new(Brick_Texture::tiles(
+        solid(0.8, 0.8, 0.75),
+        solid(0.2, 0.2, 0.2),
+        200.,
+    ));
+    let dirty: Arc<dyn Texture> =
+        Arc::new(Multiply_Texture::new(tiles.clone(), solid(0.5, 0.45, 0.35)));
+    let mask = Arc::new(Remap_Texture::new(
+        Arc::new(Fbm_Texture::new(0.5, 6)),
+        (0.45, 0.55),
+        (0., 1.),
+    ));
+    let ground = Arc::new(Mix_Texture::new(tiles, dirty, mask));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(Lambertian::new_arc(ground)),
+    )));
+    let wood = Wood_Texture::new(
+        RGBColor::new(0.75, 0.5, 0.25),
+        RGBColor::new(0.4, 0.2, 0.08),
+        6.,
+    );
+    let marble = Marble_Texture::new(
+        ColorRamp::new(vec![
+            (0., RGBColor::new(0.1, 0.1, 0.12)),
+            (0.4, RGBColor::new(0.6, 0.6, 0.6)),
+            (1., RGBColor::new(0.95, 0.95, 0.9)),
+        ]),
+        4.,
+    );
+    let bricks = Brick_Texture::new(solid(0.6, 0.2, 0.1), solid(0.8, 0.8, 0.8));
+    let cells = Ramp_Texture::new(
+        Arc::new(Worley_Texture::new(4., WorleyFeature::F2MinusF1)),
+        ColorRamp::two(RGBColor::new(0.05, 0.2, 0.05), RGBColor::new(0.6, 0.9, 0.3)),
+    );
+    let ridges = Gradient_Texture::new(
+        Gradient::V,
+        ColorRamp::two(RGBColor::new(0.1, 0.2, 0.6), RGBColor::new(0.9, 0.9, 0.9)),
+    );
+    let ridges = Multiply_Texture::new(Arc::new(ridges), Arc::new(Ridged_Texture::new(2., 6)));
+    let textures: Vec<Arc<dyn Texture>> = vec![
+        Arc::new(wood),
+        Arc::new(marble),
+        Arc::new(bricks),
+        Arc::new(cells),
+        Arc::new(ridges),
+    ];
+    for (i, tex) in textures.into_iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-4.4 + 2.2 * i as f64, 1., 0.),
+            1.,
+            Arc::new(Lambertian::new_arc(tex)),
+        )));
+    }
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -6., 6., -3., 3., 8., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 3., 14.), Point3::new(0., 1., 0.), 40.);
+    return (objects, lights, cam);
+}
+//几种噪声：经典、改进、单纯形，最右边的随时间变，快门开着的时候纹路会糊开
+fn noise_variants() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+    )));
+    let kinds = [
+        NoiseKind::Classic,
+        NoiseKind::Improved,
+        NoiseKind::Simplex,
+        NoiseKind::Improved,
+    ];
+    for (i, kind) in kinds.iter().enumerate() {
+        let mut tex = Noise_Texture::with_seed(4., 7);
+        tex.set_kind(*kind);
+        if i == 3 {
+            tex.set_speed(2.);
+        }
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-3.3 + 2.2 * i as f64, 1., 0.),
+            1.,
+            Arc::new(Lambertian::new_arc(Arc::new(tex))),
+        )));
+    }
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -6., 6., -3., 3., 8., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 3., 12.), Point3::new(0., 1., 0.), 35.);
+    return (objects, lights, cam);
+}
+//纹理坐标：转过、挪过的盒子上的棋盘格跟着盒子走，球上分别是三平面投影和柱面投影，
+//右边的方片用第二套uv贴砖
+fn texture_mapping() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+    )));
+    let checker = Arc::new(Checker_Texture::new(
+        RGBColor::new(0.8, 0.1, 0.1),
+        RGBColor::new(0.9, 0.9, 0.9),
+    ));
+    let cube = Arc::new(Box::new(
+        Point3::new(-0.8, 0., -0.8),
+        Point3::new(0.8, 1.6, 0.8),
+        Arc::new(Lambertian::new_arc(checker)),
+    ));
+    objects.add(Arc::new(Translate::new(
+        Arc::new(RotateY::new(cube, 30.)),
+        Vec3::new(-3.3, 0., 0.),
+    )));
+    let bricks: Arc<dyn Texture> = Arc::new(Brick_Texture::new(
+        Arc::new(Solid_Color::new(0.6, 0.2, 0.1)),
+        Arc::new(Solid_Color::new(0.8, 0.8, 0.8)),
+    ));
+    let triplanar = Mapped_Texture::new(bricks.clone(), TextureMapping::Triplanar(4.));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.1, 1., 0.),
+        1.,
+        Arc::new(Lambertian::new_arc(Arc::new(triplanar))),
+    )));
+    let cylindrical = Mapped_Texture::new(
+        bricks.clone(),
+        TextureMapping::Cylindrical(Point3::new(0., 0., 0.)),
+    );
+    objects.add(Arc::new(Translate::new(
+        Arc::new(Sphere::new(
+            Point3::new(0., 1., 0.),
+            1.,
+            Arc::new(Lambertian::new_arc(Arc::new(cylindrical))),
+        )),
+        Vec3::new(1.1, 0., 0.),
+    )));
+    //第二套uv转了45度、放大了一倍
+    let positions = vec![
+        Point3::new(2.4, 0., 0.),
+        Point3::new(4.2, 0., 0.),
+        Point3::new(4.2, 1.8, 0.),
+        Point3::new(2.4, 1.8, 0.),
+    ];
+    let uvs = vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)];
+    let rotated = vec![(0., 1.), (1., 0.), (2., 1.), (1., 2.)];
+    let quad = Mesh::new_uv_sets(
+        positions,
+        Vec::new(),
+        uvs,
+        vec![rotated],
+        vec![[0, 1, 2], [0, 2, 3]],
+        Arc::new(Lambertian::new_arc(Arc::new(Mapped_Texture::new(
+            bricks,
+            TextureMapping::UvSet(1),
+        )))),
+    );
+    objects.add(Arc::new(quad));
+    let light = Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 4.)));
+    objects.add(Arc::new(flip_face::new(Arc::new(xz_rect::new(
+        -6., 6., -3., 3., 8., light,
+    )))));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 3., 14.), Point3::new(0., 1., 0.), 40.);
+    return (objects, lights, cam);
+}
+//新的图元：圆柱、圆锥、抛物面、圆环、圆盘，斜着的四边形灯不用再套RotateY
+fn primitives() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-20., 0., -20.),
+        Vec3::new(0., 0., 40.),
+        Vec3::new(40., 0., 0.),
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+    )));
+    let checker = Arc::new(Checker_Texture::new(
+        RGBColor::new(0.2, 0.3, 0.1),
+        RGBColor::new(0.9, 0.9, 0.9),
+    ));
+    objects.add(Arc::new(Cylinder::new(
+        Point3::new(-4.4, 0., 0.),
+        Vec3::new(0., 2., 0.),
+        0.8,
+        true,
+        Arc::new(Lambertian::new(RGBColor::new(0.7, 0.2, 0.2))),
+    )));
+    objects.add(Arc::new(Cone::new(
+        Point3::new(-2.2, 0., 0.),
+        Vec3::new(0., 2., 0.),
+        0.9,
+        true,
+        Arc::new(Lambertian::new_arc(checker)),
+    )));
+    objects.add(Arc::new(Paraboloid::new(
+        Point3::new(0., 0.2, 0.),
+        Vec3::new(0., 1.6, 0.),
+        0.9,
+        Arc::new(Metal::new(RGBColor::new(0.8, 0.8, 0.8), 0.1)),
+    )));
+    objects.add(Arc::new(Torus::new(
+        Point3::new(2.2, 1., 0.),
+        Vec3::new(0., 1., 1.),
+        0.7,
+        0.25,
+        Arc::new(Lambertian::new(RGBColor::new(0.2, 0.3, 0.7))),
+    )));
+    objects.add(Arc::new(Disk::new(
+        Point3::new(4.4, 1., 0.),
+        Vec3::new(-0.3, 0.2, 1.),
+        0.9,
+        Arc::new(Diffuse_Light::new(RGBColor::new(3., 2.5, 1.5))),
+    )));
+    //朝下斜着的灯板
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-3., 6., 2.),
+        Vec3::new(0., 1., -3.),
+        Vec3::new(6., 0., 0.),
+        Arc::new(Diffuse_Light::new(RGBColor::new(5., 5., 5.))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 3., 14.), Point3::new(0., 1., 0.), 40.);
+    return (objects, lights, cam);
+}
+//构造实体几何：透镜、挖开的空心球、倒角的方块、打了孔的方块
+fn csg_shapes() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-20., 0., -20.),
+        Vec3::new(0., 0., 40.),
+        Vec3::new(40., 0., 0.),
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+    )));
+    //两个球的交集是双凸透镜
+    let glass = Arc::new(Dielectric::new(1.5));
+    objects.add(Arc::new(Csg::intersection(
+        Arc::new(Sphere::new(Point3::new(-3.3, 1.2, -1.5), 2., glass.clone())),
+        Arc::new(Sphere::new(Point3::new(-3.3, 1.2, 1.5), 2., glass)),
+    )));
+    //空心球，再切掉前上方的一块露出里面
+    let red = Arc::new(Lambertian::new(RGBColor::new(0.7, 0.2, 0.2)));
+    let shell = Arc::new(Csg::difference(
+        Arc::new(Sphere::new(Point3::new(-1.1, 1., 0.), 1., red.clone())),
+        Arc::new(Sphere::new(Point3::new(-1.1, 1., 0.), 0.85, red.clone())),
+    ));
+    objects.add(Arc::new(Csg::difference(
+        shell,
+        Arc::new(Box::new(
+            Point3::new(-1.1, 1., 0.),
+            Point3::new(0.5, 2.5, 1.5),
+            red,
+        )),
+    )));
+    //方块和球求交就是倒了圆角的方块
+    let blue = Arc::new(Lambertian::new(RGBColor::new(0.2, 0.3, 0.7)));
+    objects.add(Arc::new(Csg::intersection(
+        Arc::new(Box::new(
+            Point3::new(0.4, 0., -0.8),
+            Point3::new(2., 1.6, 0.8),
+            blue.clone(),
+        )),
+        Arc::new(Sphere::new(Point3::new(1.2, 0.8, 0.), 1.1, blue)),
+    )));
+    //方块减掉横竖两根圆柱
+    let metal = Arc::new(Metal::new(RGBColor::new(0.8, 0.7, 0.5), 0.2));
+    let cross = Arc::new(Csg::union(
+        Arc::new(Cylinder::new(
+            Point3::new(3.3, 0.8, -1.),
+            Vec3::new(0., 0., 2.),
+            0.5,
+            true,
+            metal.clone(),
+        )),
+        Arc::new(Cylinder::new(
+            Point3::new(2.5, 0.8, 0.),
+            Vec3::new(1.6, 0., 0.),
+            0.5,
+            true,
+            metal.clone(),
+        )),
+    ));
+    objects.add(Arc::new(Csg::difference(
+        Arc::new(Box::new(
+            Point3::new(2.5, 0., -0.8),
+            Point3::new(4.1, 1.6, 0.8),
+            metal,
+        )),
+        cross,
+    )));
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-3., 6., 7.),
+        Vec3::new(0., 1., -3.),
+        Vec3::new(6., 0., 0.),
+        Arc::new(Diffuse_Light::new(RGBColor::new(5., 5., 5.))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(2., 5., 12.), Point3::new(0.4, 0.8, 0.), 40.);
+    return (objects, lights, cam);
+}
+
+//距离场：Mandelbulb分形、平滑融合的球和圆环、重复的小球挖出来的方块
+fn sdf_shapes() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-20., 0., -20.),
+        Vec3::new(0., 0., 40.),
+        Vec3::new(40., 0., 0.),
+        Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5))),
+    )));
+    let mut bulb = SdfObject::new(
+        Arc::new(Mandelbulb_Sdf::new(Point3::new(0., 1.2, 0.), 1.1)),
+        AABB::new(Point3::new(-1.3, -0.1, -1.3), Point3::new(1.3, 2.5, 1.3)),
+        Arc::new(Lambertian::new(RGBColor::new(0.8, 0.6, 0.3))),
+    );
+    bulb.set_step_scale(0.8);
+    bulb.set_max_steps(512);
+    objects.add(Arc::new(bulb));
+    let blob = SdfObject::new(
+        Arc::new(SmoothUnion_Sdf::new(
+            Arc::new(Sphere_Sdf::new(Point3::new(-3., 1.3, 0.), 0.6)),
+            Arc::new(Torus_Sdf::new(Point3::new(-3., 0.6, 0.), 0.9, 0.25)),
+            0.4,
+        )),
+        AABB::new(Point3::new(-4.3, 0., -1.3), Point3::new(-1.7, 2.1, 1.3)),
+        Arc::new(Dielectric::new(1.5)),
+    );
+    objects.add(Arc::new(blob));
+    //每隔0.5重复一个小球，从圆角方块里减掉
+    let holes = Arc::new(Repeat_Sdf::new(
+        Arc::new(Sphere_Sdf::new(Point3::new(0., 0., 0.), 0.2)),
+        Vec3::new(0.5, 0.5, 0.5),
+    ));
+    let cheese = Subtract_Sdf::new(
+        Arc::new(Box_Sdf::new(
+            Point3::new(3., 0.75, 0.),
+            Vec3::new(0.9, 0.75, 0.9),
+            0.1,
+        )),
+        holes,
+    );
+    let cheese = Intersect_Sdf::new(
+        Arc::new(cheese),
+        Arc::new(Sphere_Sdf::new(Point3::new(3., 0.75, 0.), 1.4)),
+    );
+    objects.add(Arc::new(SdfObject::new(
+        Arc::new(cheese),
+        AABB::new(Point3::new(2., 0., -1.), Point3::new(4., 1.6, 1.)),
+        Arc::new(Lambertian::new(RGBColor::new(0.9, 0.8, 0.3))),
+    )));
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-3., 6., 7.),
+        Vec3::new(0., 1., -3.),
+        Vec3::new(6., 0., 0.),
+        Arc::new(Diffuse_Light::new(RGBColor::new(5., 5., 5.))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 4., 11.), Point3::new(0., 1., 0.), 40.);
+    return (objects, lights, cam);
+}
+
+//低模的树冠：三层叠起来的圆锥，底面朝下，树干另外放
+fn tree_crown(mp: Arc<dyn Material>) -> Mesh {
+    let segments = 10;
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for (layer, (y, radius, height)) in [(0.4, 0.9, 1.2), (0.9, 0.7, 1.), (1.4, 0.5, 0.8)]
+        .iter()
+        .enumerate()
+    {
+        let base = layer * (segments + 1);
+        for i in 0..segments {
+            let phi = 2. * PI * i as f64 / segments as f64;
+            positions.push(Point3::new(radius * phi.cos(), *y, radius * phi.sin()));
+        }
+        positions.push(Point3::new(0., y + height, 0.));
+        for i in 0..segments {
+            let next = (i + 1) % segments;
+            indices.push([base + i, base + segments, base + next]);
+        }
+    }
+    Mesh::new(positions, Vec::new(), Vec::new(), indices, mp)
+}
+
+//几千棵树只用一份树冠网格和一根树干，每棵树是两个实例，自己的变换加上换过的颜色
+fn forest() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-60., 0., -60.),
+        Vec3::new(0., 0., 120.),
+        Vec3::new(120., 0., 0.),
+        Arc::new(Lambertian::new(RGBColor::new(0.35, 0.3, 0.2))),
+    )));
+    let crown: Arc<dyn Hittable> = Arc::new(tree_crown(Arc::new(Lambertian::new(RGBColor::new(
+        0.1, 0.4, 0.1,
+    )))));
+    let trunk: Arc<dyn Hittable> = Arc::new(Cylinder::new(
+        Point3::new(0., 0., 0.),
+        Vec3::new(0., 0.5, 0.),
+        0.12,
+        true,
+        Arc::new(Lambertian::new(RGBColor::new(0.3, 0.2, 0.1))),
+    ));
+    let greens: Vec<Arc<dyn Material>> = (0..5)
+        .map(|i| {
+            let g = 0.25 + 0.08 * i as f64;
+            Arc::new(Lambertian::new(RGBColor::new(
+                0.08 + 0.03 * i as f64,
+                g,
+                0.08,
+            ))) as Arc<dyn Material>
+        })
+        .collect();
+    let mut rng = rand::thread_rng();
+    for a in -30..30 {
+        for b in -30..30 {
+            let at = Vec3::new(
+                a as f64 * 1.8 + rng.gen_range(-0.6..0.6),
+                0.,
+                b as f64 * 1.8 + rng.gen_range(-0.6..0.6),
+            );
+            let size = rng.gen_range(0.7..1.4);
+            let transform = Transform::scale(Vec3::new(size, size * rng.gen_range(0.8..1.3), size))
+                .then(&Transform::rotate(
+                    Vec3::new(0., 1., 0.),
+                    rng.gen_range(0.0..360.),
+                ))
+                .then(&Transform::translate(at));
+            objects.add(Arc::new(Instance::new(trunk.clone(), transform)));
+            let mut tree = Instance::new(crown.clone(), transform);
+            tree.set_material(greens[rng.gen_range(0..greens.len())].clone());
+            objects.add(Arc::new(tree));
+        }
+    }
+    objects.add(Arc::new(Quad::parallelogram(
+        Point3::new(-20., 40., 30.),
+        Vec3::new(0., 0., -40.),
+        Vec3::new(40., 0., 0.),
+        Arc::new(Diffuse_Light::new(RGBColor::new(4., 4., 3.6))),
+    )));
+    let lights = LightList::from_world(&objects);
+    let cam = CameraSettings::new(Point3::new(0., 8., 40.), Point3::new(0., 0., 10.), 45.);
+    return (objects, lights, cam);
+}
+
+//几百个小发光球，用来比较光源BVH和按功率均匀挑；
+//light.rs里的测试在同样16个样本下量过地面辐照度的RMSE：按功率挑0.25，光源BVH 0.08
+fn many_lights() -> (HittableList, LightList, CameraSettings) {
+    let mut objects = HittableList::new();
+    let ground = Arc::new(Lambertian::new(RGBColor::new(0.5, 0.5, 0.5)));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        ground,
+    )));
+    let mut rng = rand::thread_rng();
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+            if (center - Point3::new(4., 0.2, 0.)).length() > 0.9 {
+                let emit = RGBColor::random(0.2, 1.) * 4.;
+                objects.add(Arc::new(Sphere::new(
+                    center,
+                    0.2,
+                    Arc::new(Diffuse_Light::new(emit)),
+                )));
+            }
+        }
+    }
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-4., 1., 0.),
+        1.,
+        Arc::new(Lambertian::new(RGBColor::new(0.4, 0.2, 0.1))),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(4., 1., 0.),
+        1.,
+        Arc::new(Metal::new(RGBColor::new(0.7, 0.6, 0.5), 0.)),
+    )));
+    let mut lights = LightList::from_world(&objects);
+    lights.build_bvh();
+    let cam = CameraSettings::new(Point3::new(13., 2., 3.), Point3::new(0., 0., 0.), 20.);
+    return (objects, lights, cam);
+}
 pub fn final_scene() -> HittableList {
     let mut boxes1: HittableList = HittableList::new();
     let ground = Arc::new(Lambertian::new(RGBColor::new(0.48, 0.83, 0.53)));
@@ -499,6 +1464,7 @@ fn main() {
     let path = "output/output.jpg";
     const SAMPLES_PER_PIXEL: i32 = 1000;
     const MAX_DEPTH: i32 = 50;
+    const SPECTRAL: bool = false; //光谱模式，玻璃色散要开这个
     const THREAD_NUMBER: u32 = 8;
     const SECTION_LINE_NUM: u32 = IMAGE_HEIGHT / THREAD_NUMBER;
 
@@ -508,20 +1474,6 @@ fn main() {
         style(IMAGE_QUALITY.to_string()).yellow(),
     );
 
-    // Camera
-    let lookfrom = Point3::new(278., 278., -800.);
-    let lookat = Point3::new(278., 278., 0.);
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0., 1., 0.),
-        40.,
-        ASPECT_RATIO,
-        0.0,
-        10.,
-        0.,
-        1.,
-    );
     let background = RGBColor::new(0., 0., 0.);
     // Progress bar
     let multiprogress = Arc::new(MultiProgress::new());
@@ -534,7 +1486,22 @@ fn main() {
     // World
     // let main_world = random_scene();
     //let main_world = BvhNode::new_list(&random_scene(), 0., 1.);
-    let main_world = BvhNode::new_list(&cornell_box(), 0., 1.);
+    let (main_objects, main_lights, cam_settings) = cornell_box();
+    // Camera
+    let main_camera = cam_settings.build(ASPECT_RATIO);
+    let main_world = BvhNode::new_list(&main_objects, cam_settings.time0, cam_settings.time1);
+    for light in main_lights.lights.iter() {
+        if !light.can_sample() {
+            println!(
+                "{}",
+                style("Warning: a light cannot be importance sampled, the image will be wrong.")
+                    .yellow()
+            );
+        }
+    }
+    let main_lights = Arc::new(main_lights);
+    //微分缩到一个像素，每个像素样本多的时候每个样本管的范围更小
+    let spp_scale = (1. / (SAMPLES_PER_PIXEL as f64).sqrt()).max(0.125);
     for thread_id in 0..THREAD_NUMBER {
         // line
         let line_beg = thread_id * SECTION_LINE_NUM;
@@ -545,34 +1512,8 @@ fn main() {
 
         // world
         let world = main_world.clone();
-        let mut lights = HittableList::new();
-        lights.add(Arc::new(xz_rect::new(
-            213.,
-            343.,
-            227.,
-            332.,
-            554.,
-            Arc::new(Dielectric::new(0.)),
-        )));
-        lights.add(Arc::new(Sphere::new(
-            Point3::new(190., 90., 190.),
-            90.,
-            Arc::new(Dielectric::new(0.)),
-        )));
-        /* let lights = Arc::new(xz_rect::new(
-            213.,
-            343.,
-            227.,
-            332.,
-            554.,
-            Arc::new(Dielectric::new(0.)),
-        ));
-
-        let lights = Arc::new(Sphere::new(
-            Point3::new(190., 90., 190.),
-            90.,
-            Arc::new(Dielectric::new(0.)),
-        ));*/
+        let lights = main_lights.clone();
+        let cam = main_camera.clone();
 
         //progress
         let mp = multiprogress.clone();
@@ -583,7 +1524,6 @@ fn main() {
 
         // thread code
         let (tx, rx) = mpsc::channel();
-        let lights = Arc::new(lights);
         thread_pool.push((
             thread::spawn(move || {
                 let mut progress = 0;
@@ -600,9 +1540,31 @@ fn main() {
                             let rand_v: f64 = rng.gen();
                             let u = (x as f64 + rand_u) / (IMAGE_WIDTH - 1) as f64;
                             let v = (y as f64 + rand_v) / (IMAGE_HEIGHT - 1) as f64;
-                            let r = cam.get_ray(u, v);
-                            pixel_color +=
-                                Ray::ray_color(r, background, &world, lights.clone(), MAX_DEPTH);
+                            let (mut r, weight) = match cam.get_ray(u, v) {
+                                Some(sample) => sample,
+                                None => continue, //拍不到东西，这个样本是黑的
+                            };
+                            r.scale_differentials(
+                                spp_scale / (IMAGE_WIDTH - 1) as f64,
+                                spp_scale / (IMAGE_HEIGHT - 1) as f64,
+                            );
+                            let color = if SPECTRAL {
+                                let mut lambdas = SampledWavelengths::sample_visible(rng.gen());
+                                let mut r = r;
+                                r.wavelength = lambdas.lambda[0];
+                                let l = Ray::ray_color_spectral(
+                                    r,
+                                    background,
+                                    &world,
+                                    lights.clone(),
+                                    MAX_DEPTH,
+                                    &mut lambdas,
+                                );
+                                lambdas.to_rgb(l)
+                            } else {
+                                Ray::ray_color(r, background, &world, lights.clone(), MAX_DEPTH)
+                            };
+                            pixel_color += color * weight;
                         }
                         section_pixel_color.push(pixel_color);
                     }
@@ -636,14 +1598,27 @@ fn main() {
         exit(0);
     }
 
+    // Post process
+    let mut hdr = HdrImage::new(
+        IMAGE_WIDTH as usize,
+        IMAGE_HEIGHT as usize,
+        output_pixel_color
+            .iter()
+            .map(|c| *c / SAMPLES_PER_PIXEL as f64)
+            .collect(),
+    );
+    let mut post = PostProcess::new();
+    // post.bloom = Some(postprocess::Bloom::new(1., 0.2));
+    // post.flare = Some(postprocess::LensFlare::new(2.));
+    // post.starburst = postprocess::Starburst::from_aperture(&cam_settings.aperture_shape, 2., 40.);
+    post.apply(&mut hdr);
+
     let mut img: RgbImage = ImageBuffer::new(IMAGE_WIDTH, IMAGE_HEIGHT);
-    let mut pixel_id = 0;
     for y in 0..IMAGE_HEIGHT {
         for x in 0..IMAGE_WIDTH {
-            let pixel_color = output_pixel_color[pixel_id];
+            let pixel_color = hdr.get(x as usize, y as usize);
             let pixel = img.get_pixel_mut(x, IMAGE_HEIGHT - y - 1);
-            *pixel = image::Rgb(write_color(pixel_color, SAMPLES_PER_PIXEL));
-            pixel_id += 1;
+            *pixel = image::Rgb(write_color(pixel_color, 1));
         }
     }
 
@@ -1,7 +1,9 @@
 use crate::hittable;
+use crate::medium_stack::Medium;
 use crate::onb::Onb;
 use crate::pdf::{cosine_pdf, Pdf};
-use crate::texture::{Solid_Color, Texture};
+use crate::spectrum::{blackbody_rgb, SampledSpectrum, SampledWavelengths, SPECTRAL_SAMPLES};
+use crate::texture::{Image_Texture, Solid_Color, Texture};
 use crate::vec3::Point3;
 use crate::{hittable::HitRecord, ray::Ray, vec3::RGBColor, vec3::Vec3};
 use rand::Rng;
@@ -21,6 +23,8 @@ pub struct Scattered_Record {
     pub specular_ray: Option<Ray>,
     pub attenuation: RGBColor,
     pub pdf_ptr: Option<Arc<dyn Pdf>>,
+    pub spectral: Option<SampledSpectrum>, //光谱模式下每个波长自己的权重，有的话代替attenuation
+    pub lobe: Option<HitRecord>, //MixMaterial、Coated挑中的lobe（resolve好的），scattering_pdf要问它
 }
 impl Scattered_Record {
     pub fn new(
@@ -32,8 +36,15 @@ impl Scattered_Record {
             specular_ray,
             attenuation,
             pdf_ptr,
+            spectral: None,
+            lobe: None,
         }
     }
+    //attenuation乘上它才是这次散射的BSDF；挑过lobe的话是挑中的那个lobe的pdf
+    pub fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Option<f64> {
+        let rec = self.lobe.as_ref().unwrap_or(rec);
+        rec.mat_ptr.scattering_pdf(r_in, rec.clone(), scattered)
+    }
 }
 
 pub trait Material: Send + Sync {
@@ -43,9 +54,127 @@ pub trait Material: Send + Sync {
     fn scattering_pdf(&self, r_in: Ray, rec: HitRecord, scattered: Ray) -> Option<f64> {
         return Some(0.);
     }
-    fn emitted(&self, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+    //r_in是打到光源上的光线，聚光的面光源要用它的方向
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
         return Some(RGBColor::new(0., 0., 0.));
     }
+    //折射率随波长变，光谱模式下打到它要只留hero波长
+    fn dispersive(&self) -> bool {
+        false
+    }
+    //透明介质的边界，穿过它时光线上的介质栈由材质自己改
+    fn bounds_medium(&self) -> bool {
+        false
+    }
+    //法线贴图、凹凸贴图这种包装在这里交出底下真正用的材质，ray_color先换好再算scatter和scattering_pdf；
+    //按概率挑lobe的材质（MixMaterial、Coated）不走这里，它们在scatter里挑，挑中的记在Scattered_Record::lobe里
+    fn select(&self, _r_in: Ray, _rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        None
+    }
+    //法线贴图、凹凸贴图在这里给出新的着色法向量（朝外的），resolve写进rec再往下挑
+    fn shading_normal(&self, _rec: &HitRecord) -> Option<Vec3> {
+        None
+    }
+    //发光的材质每单位面积、正面沿法向的亮度为1时发出的功率（聚光衰减、两面发光都算进去），
+    //光源BVH按它估功率；没有的话按两面朗伯发光估
+    fn power_factor(&self) -> Option<f64> {
+        None
+    }
+}
+
+//一直换到不再是包装为止，路上碰到的贴图都把法向量改好
+pub fn resolve(r_in: Ray, rec: &HitRecord) -> HitRecord {
+    let mut rec = rec.clone();
+    loop {
+        let mat = rec.mat_ptr.clone();
+        if let Some(n) = mat.shading_normal(&rec) {
+            rec.set_shading_normal(n);
+        }
+        match mat.select(r_in, &rec) {
+            Some(m) => rec.mat_ptr = m,
+            None => return rec,
+        }
+    }
+}
+
+//包装的材质把rec交给底下的材质之前也要resolve，不然底下是贴图包装的时候scatter是空的
+fn resolve_inner(mat: &Arc<dyn Material>, r_in: Ray, rec: &HitRecord) -> HitRecord {
+    let mut inner = rec.clone();
+    inner.mat_ptr = mat.clone();
+    resolve(r_in, &inner)
+}
+//底下的材质不管介质的话，镜面出去的光线还在原来的介质里，包装的材质自己把介质栈带上
+fn scatter_inner(mat: &Arc<dyn Material>, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
+    scatter_resolved(&resolve_inner(mat, r_in, rec), r_in)
+}
+fn scatter_resolved(inner: &HitRecord, r_in: Ray) -> Option<Scattered_Record> {
+    let mut srec = inner.mat_ptr.scatter(r_in, inner)?;
+    if !inner.mat_ptr.bounds_medium() {
+        if let Some(specular) = srec.specular_ray.as_mut() {
+            specular.media = r_in.media;
+        }
+    }
+    Some(srec)
+}
+fn scattering_pdf_inner(
+    mat: &Arc<dyn Material>,
+    r_in: Ray,
+    rec: &HitRecord,
+    scattered: Ray,
+) -> f64 {
+    let inner = resolve_inner(mat, r_in, rec);
+    let m = inner.mat_ptr.clone();
+    m.scattering_pdf(r_in, inner, scattered).unwrap_or(0.)
+}
+//按概率挑中一个lobe：挑的概率等于它在BSDF里占的比例，两个相除是1，
+//所以衰减和pdf都直接用这个lobe自己的，lobe记下来让scattering_pdf也只问它
+fn scatter_lobe(mat: &Arc<dyn Material>, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
+    let inner = resolve_inner(mat, r_in, rec);
+    let mut srec = scatter_resolved(&inner, r_in)?;
+    if srec.lobe.is_none() {
+        srec.lobe = Some(inner);
+    }
+    Some(srec)
+}
+
+//在几个uv和给定的面上沿着法向探一下emitted，取平均
+fn probe_emission(mat: &Arc<dyn Material>, faces: &[bool]) -> RGBColor {
+    let mut sum = RGBColor::new(0., 0., 0.);
+    let mut count = 0.;
+    for front_face in faces.iter() {
+        for i in 0..3 {
+            for j in 0..3 {
+                let u = (i as f64 + 0.5) / 3.;
+                let v = (j as f64 + 0.5) / 3.;
+                let p = Point3::new(0., 0., 0.);
+                let rec =
+                    HitRecord::new(p, Vec3::new(0., 1., 0.), 1., u, v, *front_face, mat.clone());
+                //沿着法向看过去
+                let probe = Ray::new(Point3::new(0., 1., 0.), Vec3::new(0., -1., 0.), 0.);
+                sum += mat.emitted(probe, rec, u, v, p).unwrap_or_default();
+                count += 1.;
+            }
+        }
+    }
+    sum / count
+}
+//估计材质正反两面平均发出的radiance
+pub fn average_emission(mat: &Arc<dyn Material>) -> RGBColor {
+    probe_emission(mat, &[true, false])
+}
+pub fn luminance(c: RGBColor) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+pub fn is_emissive(mat: &Arc<dyn Material>) -> bool {
+    luminance(average_emission(mat)) > 0.
+}
+//材质给了power_factor就用正面的亮度乘它；不然当成朗伯面光源，
+//单面的功率是 π·A·L，average_emission是正反两面的平均，所以乘2π
+pub fn emitted_power(mat: &Arc<dyn Material>, area: f64) -> f64 {
+    match mat.power_factor() {
+        Some(factor) => factor * area * luminance(probe_emission(mat, &[true])),
+        None => 2. * PI * area * luminance(average_emission(mat)),
+    }
 }
 
 pub struct Lambertian {
@@ -83,13 +212,14 @@ impl Material for Lambertian {
         Some((alb, scattered, pdf))*/
         return Some(Scattered_Record::new(
             None,
-            self.albedo.value(rec.u, rec.v, rec.p),
+            self.albedo.evaluate(rec),
             Some(Arc::new(cosine_pdf::new(rec.normal))),
         ));
     }
     fn scattering_pdf(&self, r_in: Ray, rec: HitRecord, scattered: Ray) -> Option<f64> {
         let cosine = Vec3::dot(rec.normal, Vec3::unit_vector(scattered.dir));
-        if (cosine < 0.) {
+        //着色法向量歪了以后可能采到几何面底下，那是别的面的事
+        if cosine < 0. || Vec3::dot(rec.geo_normal, scattered.dir) <= 0. {
             return Some(0.);
         } else {
             return Some(cosine / PI);
@@ -114,21 +244,117 @@ impl Metal {
 impl Material for Metal {
     fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
         let reflected = Vec3::reflect(Vec3::unit_vector(r_in.dir), rec.normal);
-        let scattered = Ray::new(
+        let mut scattered = Ray::new(
             rec.p,
             reflected + Vec3::random_in_unit_sphere() * self.fuzz,
             0.,
         );
+        //反射到几何面底下就当被吸收了
+        if Vec3::dot(scattered.dir, rec.geo_normal) <= 0. {
+            return None;
+        }
+        scattered.diff = rec.reflect_differential(&r_in, reflected);
         return Some(Scattered_Record::new(Some(scattered), self.albedo, None));
     }
 }
+//色散公式，波长用μm
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    None,
+    Cauchy { a: f64, b: f64 },              // n = a + b/λ²
+    Sellmeier { b: [f64; 3], c: [f64; 3] }, // n² = 1 + Σ bλ²/(λ² - c)
+}
 pub struct Dielectric {
     ir: f64,
+    dispersion: Dispersion,
+    absorption: RGBColor, //每单位距离的吸收系数，0是无色透明
+    scattering: RGBColor, //每单位距离的散射系数，0是不做次表面散射
+    priority: u32,        //和别的介质重叠的地方优先级高的算数
 }
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             ir: index_of_refraction,
+            dispersion: Dispersion::None,
+            absorption: RGBColor::new(0., 0., 0.),
+            scattering: RGBColor::new(0., 0., 0.),
+            priority: 0,
+        }
+    }
+    //次表面散射（皮肤、大理石、蜡、牛奶），albedo是看上去的颜色，
+    //mean_free_path是每个通道平均走多远碰到一次散射或吸收
+    pub fn subsurface(
+        index_of_refraction: f64,
+        albedo: RGBColor,
+        mean_free_path: RGBColor,
+    ) -> Self {
+        let mut d = Self::new(index_of_refraction);
+        d.set_subsurface(albedo, mean_free_path);
+        d
+    }
+    pub fn cauchy(a: f64, b: f64) -> Self {
+        Self::new_dispersion(Dispersion::Cauchy { a, b })
+    }
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Self {
+        Self::new_dispersion(Dispersion::Sellmeier { b, c })
+    }
+    //常见的冕牌玻璃
+    pub fn bk7() -> Self {
+        Self::sellmeier(
+            [1.03961212, 0.231792344, 1.01046945],
+            [0.00600069867, 0.0200179144, 103.560653],
+        )
+    }
+    //重火石玻璃，色散很强，适合做三棱镜
+    pub fn sf11() -> Self {
+        Self::sellmeier(
+            [1.73759695, 0.313747346, 1.89878101],
+            [0.013188707, 0.0623068142, 155.23629],
+        )
+    }
+    fn new_dispersion(dispersion: Dispersion) -> Self {
+        let mut d = Self::new(1.);
+        d.dispersion = dispersion;
+        d.ir = d.ior(0.);
+        d
+    }
+    pub fn set_absorption(&mut self, absorption: RGBColor) {
+        self.absorption = absorption;
+    }
+    //有色玻璃：在里面走distance之后剩下color
+    pub fn set_color(&mut self, color: RGBColor, distance: f64) {
+        let sigma = |c: f64| -c.max(1e-6).ln() / distance;
+        self.absorption = RGBColor::new(sigma(color.x), sigma(color.y), sigma(color.z));
+    }
+    pub fn set_subsurface(&mut self, albedo: RGBColor, mean_free_path: RGBColor) {
+        //看上去的颜色是散射很多次之后的结果，按Chiang等人2016的拟合换成单次散射的albedo
+        let single = |a: f64| {
+            let a = a.clamp(0., 0.999);
+            1. - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        };
+        for i in 0..3 {
+            let sigma_t = 1. / mean_free_path[i].max(1e-6);
+            self.scattering[i] = single(albedo[i]) * sigma_t;
+            self.absorption[i] = sigma_t - self.scattering[i];
+        }
+    }
+    pub fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+    //wavelength是nm，0（RGB模式）按钠黄光587.6nm算
+    pub fn ior(&self, wavelength: f64) -> f64 {
+        let l = if wavelength > 0. { wavelength } else { 587.6 } / 1000.;
+        match self.dispersion {
+            Dispersion::None => self.ir,
+            Dispersion::Cauchy { a, b } => a + b / (l * l),
+            Dispersion::Sellmeier { b, c } => {
+                let l2 = l * l;
+                let mut n2 = 1.;
+                for i in 0..3 {
+                    n2 += b[i] * l2 / (l2 - c[i]);
+                }
+                n2.sqrt()
+            }
         }
     }
     fn reflectance(&self, cosine: f64, ref_idx: f64) -> f64 {
@@ -139,11 +365,38 @@ impl Dielectric {
 }
 impl Material for Dielectric {
     fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
-        let attenuation = RGBColor::new(1.0, 1.0, 1.0);
-        let mut refraction_ratio = self.ir;
+        let ir = self.ior(r_in.wavelength);
+        let medium = Medium {
+            id: self as *const Self as usize,
+            priority: self.priority,
+            ior: ir,
+            absorption: self.absorption,
+            scattering: self.scattering,
+        };
+        let mut media = r_in.media;
         if rec.front_face {
-            refraction_ratio = 1.0 / self.ir;
+            media.push(medium);
+        } else {
+            media.remove(medium.id);
         }
+        //面的另一侧是什么介质，在优先级更高的介质里面这个面不算数，光线直接穿过去
+        let outside = r_in.media.top_except(medium.id);
+        if outside.map_or(false, |m| m.priority > self.priority) {
+            let mut passed = Ray::new(rec.p, r_in.dir, r_in.tm);
+            passed.media = media;
+            passed.diff = rec.refract_differential(&r_in, r_in.dir, 1.);
+            return Some(Scattered_Record::new(
+                Some(passed),
+                RGBColor::new(1., 1., 1.),
+                None,
+            ));
+        }
+        let outside_ior = outside.map_or(1., |m| m.ior);
+        let refraction_ratio = if rec.front_face {
+            outside_ior / ir
+        } else {
+            ir / outside_ior
+        };
         let unit_direction = Vec3::unit_vector(r_in.dir);
         let cos_theta = f64::min(Vec3::dot(-unit_direction, rec.normal), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
@@ -153,41 +406,517 @@ impl Material for Dielectric {
         }
         let mut direction = Vec3::refract(unit_direction, rec.normal, refraction_ratio);
         let mut rnd = rand::thread_rng();
+        let mut diff = rec.refract_differential(&r_in, direction, refraction_ratio);
         if (cannot_refract || self.reflectance(cos_theta, refraction_ratio) > rnd.gen()) {
             direction = Vec3::reflect(unit_direction, rec.normal);
+            diff = rec.reflect_differential(&r_in, direction);
+            //反射回去还在原来的介质里
+            media = r_in.media;
         }
         let mut scattered = Ray::new(rec.p, direction, r_in.tm);
+        scattered.media = media;
+        scattered.diff = diff;
         return Some(Scattered_Record::new(
             Some(scattered),
             RGBColor::new(1., 1., 1.),
             None,
         ));
     }
+    fn dispersive(&self) -> bool {
+        !matches!(self.dispersion, Dispersion::None)
+    }
+    fn bounds_medium(&self) -> bool {
+        true
+    }
 }
 
+//薄膜干涉：在别的材质外面镀一层膜（肥皂泡、油膜、阳极氧化），
+//膜上下两个面反射的光按膜厚和波长相干叠加，反射率用Airy公式算
+pub struct ThinFilm {
+    base: Arc<dyn Material>,
+    thickness: Arc<dyn Texture>, //nm，取三个通道的平均再乘thickness_scale
+    thickness_scale: f64,
+    film_ior: f64,
+    substrate_ior: f64, //膜下面那一层的折射率，肥皂泡里面是空气就是1
+}
+impl ThinFilm {
+    pub fn new(base: Arc<dyn Material>, thickness: f64, film_ior: f64, substrate_ior: f64) -> Self {
+        Self::new_arc(
+            base,
+            Arc::new(Solid_Color::new(thickness, thickness, thickness)),
+            1.,
+            film_ior,
+            substrate_ior,
+        )
+    }
+    pub fn new_arc(
+        base: Arc<dyn Material>,
+        thickness: Arc<dyn Texture>,
+        thickness_scale: f64,
+        film_ior: f64,
+        substrate_ior: f64,
+    ) -> Self {
+        Self {
+            base,
+            thickness,
+            thickness_scale,
+            film_ior,
+            substrate_ior,
+        }
+    }
+    //外面是空气，cos_i是入射角的余弦，wavelength和thickness都是nm
+    fn airy_reflectance(&self, cos_i: f64, thickness: f64, wavelength: f64) -> f64 {
+        let (n1, n2, n3) = (1., self.film_ior, self.substrate_ior);
+        let sin_i = (1. - cos_i * cos_i).max(0.).sqrt();
+        let sin_2 = n1 / n2 * sin_i;
+        let cos_2 = (1. - sin_2 * sin_2).max(0.).sqrt();
+        let sin_3 = n1 / n3 * sin_i;
+        if sin_3 >= 1. {
+            //膜底下全反射
+            return 1.;
+        }
+        let cos_3 = (1. - sin_3 * sin_3).sqrt();
+        let phase = 4. * PI * n2 * thickness * cos_2 / wavelength;
+        let airy = |r12: f64, r23: f64| {
+            let c = 2. * r12 * r23 * phase.cos();
+            ((r12 * r12 + r23 * r23 + c) / (1. + r12 * r12 * r23 * r23 + c)).clamp(0., 1.)
+        };
+        let rs = airy(
+            (n1 * cos_i - n2 * cos_2) / (n1 * cos_i + n2 * cos_2),
+            (n2 * cos_2 - n3 * cos_3) / (n2 * cos_2 + n3 * cos_3),
+        );
+        let rp = airy(
+            (n2 * cos_i - n1 * cos_2) / (n2 * cos_i + n1 * cos_2),
+            (n3 * cos_2 - n2 * cos_3) / (n3 * cos_2 + n2 * cos_3),
+        );
+        (rs + rp) / 2.
+    }
+    fn film(&self, r_in: &Ray, rec: &HitRecord) -> (f64, f64) {
+        let cos_i = Vec3::dot(-Vec3::unit_vector(r_in.dir), rec.normal).clamp(0., 1.);
+        let t = self.thickness.evaluate(rec);
+        (cos_i, (t.x + t.y + t.z) / 3. * self.thickness_scale)
+    }
+    //RGB模式下用三个代表波长，光谱模式下用光线自己的（hero）波长
+    fn reflectance(&self, r_in: &Ray, rec: &HitRecord) -> RGBColor {
+        let (cos_i, thickness) = self.film(r_in, rec);
+        if r_in.wavelength > 0. {
+            let r = self.airy_reflectance(cos_i, thickness, r_in.wavelength);
+            return RGBColor::new(r, r, r);
+        }
+        RGBColor::new(
+            self.airy_reflectance(cos_i, thickness, 650.),
+            self.airy_reflectance(cos_i, thickness, 550.),
+            self.airy_reflectance(cos_i, thickness, 450.),
+        )
+    }
+    //光谱模式下光线带着的每个波长上的反射率
+    fn spectral_reflectance(&self, r_in: &Ray, rec: &HitRecord) -> SampledSpectrum {
+        let (cos_i, thickness) = self.film(r_in, rec);
+        let lambdas = SampledWavelengths::from_hero(r_in.wavelength);
+        let mut s = SampledSpectrum::new(0.);
+        for i in 0..SPECTRAL_SAMPLES {
+            s.v[i] = self.airy_reflectance(cos_i, thickness, lambdas.lambda[i]);
+        }
+        s
+    }
+}
+impl Material for ThinFilm {
+    //按平均反射率挑是膜反射还是交给底下的材质，权重补回来保证能量对
+    //光谱模式下按hero波长挑，每个波长的权重单独算，反射方向和波长无关，其他波长不用丢掉
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
+        let r = self.reflectance(&r_in, rec);
+        let p_reflect = ((r.x + r.y + r.z) / 3.).clamp(0.001, 0.999);
+        let film = if r_in.wavelength > 0. {
+            Some(self.spectral_reflectance(&r_in, rec))
+        } else {
+            None
+        };
+        if rand::thread_rng().gen::<f64>() < p_reflect {
+            let reflected = Vec3::reflect(Vec3::unit_vector(r_in.dir), rec.normal);
+            let mut scattered = Ray::new(rec.p, reflected, r_in.tm);
+            scattered.media = r_in.media;
+            scattered.diff = rec.reflect_differential(&r_in, reflected);
+            let mut srec = Scattered_Record::new(Some(scattered), r / p_reflect, None);
+            srec.spectral = film.map(|f| f / p_reflect);
+            return Some(srec);
+        }
+        let mut srec = scatter_inner(&self.base, r_in, rec)?;
+        if let Some(f) = film {
+            let lambdas = SampledWavelengths::from_hero(r_in.wavelength);
+            let mut s = srec
+                .spectral
+                .unwrap_or_else(|| SampledSpectrum::from_rgb(srec.attenuation, &lambdas));
+            for i in 0..SPECTRAL_SAMPLES {
+                s.v[i] *= (1. - f.v[i]) / (1. - p_reflect);
+            }
+            srec.spectral = Some(s);
+        }
+        srec.attenuation = srec.attenuation * (RGBColor::new(1., 1., 1.) - r) / (1. - p_reflect);
+        Some(srec)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: HitRecord, scattered: Ray) -> Option<f64> {
+        Some(scattering_pdf_inner(&self.base, r_in, &rec, scattered))
+    }
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        self.base.emitted(r_in, rec, u, v, p)
+    }
+    fn power_factor(&self) -> Option<f64> {
+        self.base.power_factor()
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    //介质栈在scatter里已经处理好了
+    fn bounds_medium(&self) -> bool {
+        true
+    }
+}
+
+//按纹理的值（三个通道的平均）在两个材质之间随机挑，值是a的比例
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    weight: Arc<dyn Texture>,
+}
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: f64) -> Self {
+        Self::new_arc(a, b, Arc::new(Solid_Color::new(weight, weight, weight)))
+    }
+    pub fn new_arc(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: Arc<dyn Texture>) -> Self {
+        Self { a, b, weight }
+    }
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        let w = self.weight.evaluate(rec);
+        ((w.x + w.y + w.z) / 3.).clamp(0., 1.)
+    }
+}
+impl Material for MixMaterial {
+    //按混合的比例挑一个lobe；scattering_pdf是两个的加权和，路径追踪里用的是挑中的lobe自己的
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
+        let pick = if rand::thread_rng().gen::<f64>() < self.weight(rec) {
+            &self.a
+        } else {
+            &self.b
+        };
+        scatter_lobe(pick, r_in, rec)
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: HitRecord, scattered: Ray) -> Option<f64> {
+        let w = self.weight(&rec);
+        Some(
+            w * scattering_pdf_inner(&self.a, r_in, &rec, scattered)
+                + (1. - w) * scattering_pdf_inner(&self.b, r_in, &rec, scattered),
+        )
+    }
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        let w = self.weight(&rec);
+        let a = self
+            .a
+            .emitted(r_in, rec.clone(), u, v, p)
+            .unwrap_or_default();
+        let b = self.b.emitted(r_in, rec, u, v, p).unwrap_or_default();
+        Some(a * w + b * (1. - w))
+    }
+    fn dispersive(&self) -> bool {
+        self.a.dispersive() || self.b.dispersive()
+    }
+    //介质栈在scatter里已经处理好了
+    fn bounds_medium(&self) -> bool {
+        true
+    }
+}
+
+//清漆：底下任意材质，上面盖一层透明的膜，按菲涅尔反射率挑膜反射还是进到底下
+pub struct Coated {
+    base: Arc<dyn Material>,
+    coat: Arc<dyn Material>,
+    ior: f64,
+}
+impl Coated {
+    //roughness是0的时候膜是镜面
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            coat: Arc::new(Metal::new(RGBColor::new(1., 1., 1.), roughness)),
+            ior,
+        }
+    }
+}
+impl Coated {
+    fn fresnel(&self, r_in: &Ray, rec: &HitRecord) -> f64 {
+        let cos = Vec3::dot(-Vec3::unit_vector(r_in.dir), rec.normal).clamp(0., 1.);
+        let r0 = ((1. - self.ior) / (1. + self.ior)).powi(2);
+        r0 + (1. - r0) * (1. - cos).powi(5)
+    }
+}
+impl Material for Coated {
+    //挑到膜的概率是反射率，挑到底下的概率是透过的比例，和MixMaterial一样用挑中的lobe
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
+        if rand::thread_rng().gen::<f64>() < self.fresnel(&r_in, rec) {
+            scatter_lobe(&self.coat, r_in, rec)
+        } else {
+            scatter_lobe(&self.base, r_in, rec)
+        }
+    }
+    fn scattering_pdf(&self, r_in: Ray, rec: HitRecord, scattered: Ray) -> Option<f64> {
+        let fresnel = self.fresnel(&r_in, &rec);
+        Some(
+            fresnel * scattering_pdf_inner(&self.coat, r_in, &rec, scattered)
+                + (1. - fresnel) * scattering_pdf_inner(&self.base, r_in, &rec, scattered),
+        )
+    }
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        self.base.emitted(r_in, rec, u, v, p)
+    }
+    fn power_factor(&self) -> Option<f64> {
+        self.base.power_factor()
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    //介质栈在scatter里已经处理好了
+    fn bounds_medium(&self) -> bool {
+        true
+    }
+}
+
+//切空间的法线贴图：纹理的rgb从[0,1]换到[-1,1]，分别是沿dpdu、副切向量和法向量的分量
+pub struct NormalMap {
+    base: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+    strength: f64, //切向分量乘的系数，0就是没有贴图
+}
+impl NormalMap {
+    //法线贴图存的是数据不是颜色，不能按sRGB解码
+    pub fn new(base: Arc<dyn Material>, filename: &str) -> Self {
+        Self::new_arc(base, Arc::new(Image_Texture::new_linear(filename)))
+    }
+    pub fn new_arc(base: Arc<dyn Material>, map: Arc<dyn Texture>) -> Self {
+        Self {
+            base,
+            map,
+            strength: 1.,
+        }
+    }
+    pub fn set_strength(&mut self, strength: f64) {
+        self.strength = strength;
+    }
+}
+impl Material for NormalMap {
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let c = self.map.evaluate(rec);
+        let frame = rec.tangent_frame();
+        let x = (2. * c.x - 1.) * self.strength;
+        let y = (2. * c.y - 1.) * self.strength;
+        let z = (2. * c.z - 1.).max(0.001);
+        Some(frame.local(x, y, z))
+    }
+    fn select(&self, _r_in: Ray, _rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        Some(self.base.clone())
+    }
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        self.base.emitted(r_in, rec, u, v, p)
+    }
+    fn power_factor(&self) -> Option<f64> {
+        self.base.power_factor()
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    fn bounds_medium(&self) -> bool {
+        self.base.bounds_medium()
+    }
+}
+
+//凹凸贴图：任意纹理（三个通道的平均）当高度，沿法向量把面推出去scale倍，按高度的差分算新的法向量
+pub struct BumpMap {
+    base: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    scale: f64,
+}
+impl BumpMap {
+    pub fn new(base: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            base,
+            height,
+            scale,
+        }
+    }
+    //在rec的基础上沿dpdu、dpdv挪一点取高度，纹理用的是物体坐标系里的位置，挪的方向也要用物体坐标系的
+    fn height(&self, rec: &HitRecord, du: f64, dv: f64) -> f64 {
+        let mut shifted = rec.clone();
+        shifted.u += du;
+        shifted.v += dv;
+        shifted.p_object += rec.dpdu_object * du + rec.dpdv_object * dv;
+        let h = self.height.evaluate(&shifted);
+        (h.x + h.y + h.z) / 3. * self.scale
+    }
+}
+impl Material for BumpMap {
+    //pbrt的做法：p' = p + h·n，对u、v求偏导，叉乘得到新的法向量
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let n = rec.outward_normal();
+        //差分的步长跟着像素在uv上的大小走，没有光线微分就用一个小的固定值
+        let mut du = 0.5 * (rec.dudx.abs() + rec.dudy.abs());
+        if du == 0. {
+            du = 0.0005;
+        }
+        let mut dv = 0.5 * (rec.dvdx.abs() + rec.dvdy.abs());
+        if dv == 0. {
+            dv = 0.0005;
+        }
+        let h = self.height(rec, 0., 0.);
+        let hu = self.height(rec, du, 0.);
+        let hv = self.height(rec, 0., dv);
+        let dpdu = rec.dpdu + n * ((hu - h) / du) + rec.dndu * h;
+        let dpdv = rec.dpdv + n * ((hv - h) / dv) + rec.dndv * h;
+        let bumped = Vec3::cross(dpdu, dpdv);
+        if bumped.length_squared() < 1e-16 {
+            return None;
+        }
+        //uv的方向可能是左手系，叉乘出来反了就翻回和原来同一边
+        if Vec3::dot(bumped, n) < 0. {
+            Some(-bumped)
+        } else {
+            Some(bumped)
+        }
+    }
+    fn select(&self, _r_in: Ray, _rec: &HitRecord) -> Option<Arc<dyn Material>> {
+        Some(self.base.clone())
+    }
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        self.base.emitted(r_in, rec, u, v, p)
+    }
+    fn power_factor(&self) -> Option<f64> {
+        self.base.power_factor()
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    fn bounds_medium(&self) -> bool {
+        self.base.bounds_medium()
+    }
+}
+
+//面光源的材质。默认只有正面发光，亮度就是纹理的值（可以用Image_Texture做灯箱、屏幕）；
+//也可以两面发光、乘一个系数、按总功率（瓦或流明）除以面积定亮度、像聚光灯一样随角度变暗
 pub struct Diffuse_Light {
     emit: Arc<dyn Texture>,
+    intensity: f64,
+    scale: f64, //intensity乘上按功率归一化的系数
+    two_sided: bool,
+    spot: Option<(f64, f64)>,    //(cos_total_width, cos_falloff_start)
+    power_per_area: Option<f64>, //瓦每单位面积
 }
 impl Diffuse_Light {
     pub fn new(c: RGBColor) -> Self {
+        Self::new_arc(Arc::new(Solid_Color::new(c.x, c.y, c.z)))
+    }
+    pub fn new_arc(emit: Arc<dyn Texture>) -> Self {
         Self {
-            emit: Arc::new(Solid_Color::new(c.x, c.y, c.z)),
+            emit,
+            intensity: 1.,
+            scale: 1.,
+            two_sided: false,
+            spot: None,
+            power_per_area: None,
         }
     }
-    pub fn new_arc(emit: Arc<dyn Texture>) -> Self {
-        Self { emit }
+    //色温（开尔文）的黑体颜色，亮度是intensity
+    pub fn blackbody(kelvin: f64, intensity: f64) -> Self {
+        let mut light = Self::new(blackbody_rgb(kelvin));
+        light.set_intensity(intensity);
+        light
+    }
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+        self.update_scale();
+    }
+    pub fn set_two_sided(&mut self, two_sided: bool) {
+        self.two_sided = two_sided;
+        self.update_scale();
+    }
+    //和SpotLight一样，角度是和法向的夹角，用度数
+    pub fn set_spot(&mut self, total_width: f64, falloff_start: f64) {
+        self.spot = Some((
+            total_width.to_radians().cos(),
+            falloff_start.to_radians().cos(),
+        ));
+        self.update_scale();
+    }
+    //area是挂这个材质的形状的面积，总共发出watts瓦，intensity不再起作用
+    pub fn set_power(&mut self, watts: f64, area: f64) {
+        self.power_per_area = Some(watts / area);
+        self.update_scale();
+    }
+    //光视效能按555nm的683 lm/W算
+    pub fn set_lumens(&mut self, lumens: f64, area: f64) {
+        self.set_power(lumens / 683., area);
+    }
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        match self.spot {
+            None => 1.,
+            Some((cos_total_width, cos_falloff_start)) => {
+                if cos_theta < cos_total_width {
+                    0.
+                } else if cos_theta >= cos_falloff_start {
+                    1.
+                } else {
+                    let delta =
+                        (cos_theta - cos_total_width) / (cos_falloff_start - cos_total_width);
+                    delta * delta * delta * delta
+                }
+            }
+        }
+    }
+    fn update_scale(&mut self) {
+        self.scale = match self.power_per_area {
+            None => self.intensity,
+            Some(power_per_area) => {
+                //纹理在几个uv上的平均亮度，纹理只看rec里的uv和位置，材质随便给一个
+                let probe: Arc<dyn Material> = Arc::new(Lambertian::new(RGBColor::new(0., 0., 0.)));
+                let mut sum = 0.;
+                for i in 0..3 {
+                    for j in 0..3 {
+                        let u = (i as f64 + 0.5) / 3.;
+                        let v = (j as f64 + 0.5) / 3.;
+                        let p = Point3::new(0., 0., 0.);
+                        let rec =
+                            HitRecord::new(p, Vec3::new(0., 1., 0.), 0., u, v, true, probe.clone());
+                        sum += luminance(self.emit.evaluate(&rec)) / 9.;
+                    }
+                }
+                let denom = self.power_factor().unwrap_or(0.) * sum;
+                if denom > 0. {
+                    power_per_area / denom
+                } else {
+                    0.
+                }
+            }
+        };
     }
 }
 impl Material for Diffuse_Light {
     /*fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<Scattered_Record> {
         return None;
     }*/
-    fn emitted(&self, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
-        if rec.front_face {
-            return Some(self.emit.value(u, v, p));
-        } else {
+    fn emitted(&self, r_in: Ray, rec: HitRecord, u: f64, v: f64, p: Point3) -> Option<RGBColor> {
+        if !rec.front_face && !self.two_sided {
             return Some(RGBColor::new(0., 0., 0.));
         }
+        //rec.normal总是朝着来的光线
+        let cos_theta = Vec3::dot(-Vec3::unit_vector(r_in.dir), rec.normal);
+        Some(self.emit.evaluate(&rec) * (self.scale * self.falloff(cos_theta)))
+    }
+    //每一面是∫falloff·cosθ dω，没有聚光的时候是π
+    fn power_factor(&self) -> Option<f64> {
+        let n = 256;
+        let mut sum = 0.;
+        for i in 0..n {
+            let cos_theta = (i as f64 + 0.5) / n as f64;
+            sum += self.falloff(cos_theta) * cos_theta / n as f64;
+        }
+        let sides = if self.two_sided { 2. } else { 1. };
+        Some(sides * 2. * PI * sum)
     }
 }
 
@@ -497,3 +1226,50 @@ impl Scatter_Record {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //正上方打下来，按半球均匀取散射方向（和挑哪个lobe无关，相当于光源采样），
+    //衰减乘scattering_pdf除以取样的pdf平均下来应该是反照率按比例混合
+    fn average_albedo(mat: Arc<dyn Material>) -> f64 {
+        let p = Point3::new(0., 0., 0.);
+        let normal = Vec3::new(0., 1., 0.);
+        let rec = HitRecord::new(p, normal, 1., 0.5, 0.5, true, mat.clone());
+        let r_in = Ray::new(Point3::new(0., 1., 0.), Vec3::new(0., -1., 0.), 0.);
+        let n = 200000;
+        let mut sum = 0.;
+        for _ in 0..n {
+            let srec = mat.scatter(r_in, &rec).unwrap();
+            if srec.specular_ray.is_some() {
+                sum += srec.attenuation.x;
+                continue;
+            }
+            let mut v = Vec3::random_unit_vector();
+            if Vec3::dot(v, normal) < 0. {
+                v = -v;
+            }
+            let scattered = Ray::new(p, v, 0.);
+            let f = srec.scattering_pdf(r_in, &rec, scattered).unwrap();
+            sum += srec.attenuation.x * f * 2. * PI;
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn mix_weights_lobes() {
+        let bright: Arc<dyn Material> = Arc::new(Lambertian::new(RGBColor::new(0.8, 0.8, 0.8)));
+        let dark: Arc<dyn Material> = Arc::new(Lambertian::new(RGBColor::new(0.2, 0.2, 0.2)));
+        let mirror: Arc<dyn Material> = Arc::new(Metal::new(RGBColor::new(0.6, 0.6, 0.6), 0.));
+        let a = average_albedo(Arc::new(MixMaterial::new(bright.clone(), dark, 0.25)));
+        assert!((a - 0.35).abs() < 0.01, "{}", a);
+        let a = average_albedo(Arc::new(MixMaterial::new(bright.clone(), mirror, 0.5)));
+        assert!((a - 0.7).abs() < 0.01, "{}", a);
+        //清漆底下的漫反射：膜挑中的概率就是反射率，底下还是原来的反照率
+        let coated = Coated::new(bright, 1.5, 0.);
+        let a = average_albedo(Arc::new(coated));
+        let r0 = 0.04;
+        assert!((a - (r0 + (1. - r0) * 0.8)).abs() < 0.01, "{}", a);
+    }
+}
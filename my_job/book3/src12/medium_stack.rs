@@ -0,0 +1,79 @@
+use crate::vec3::RGBColor;
+//嵌套的透明介质（杯子里的水、水里的冰块）：光线带着一个栈记录现在在哪些介质里面，
+//几个介质重叠的地方算优先级最高的那个，优先级低的面在里面当作不存在
+
+pub const MEDIUM_STACK_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Default)]
+pub struct Medium {
+    pub id: usize, //材质的地址，出去的时候靠它找到要删的那一层
+    pub priority: u32,
+    pub ior: f64,
+    pub absorption: RGBColor, //每单位距离的吸收系数
+    pub scattering: RGBColor, //每单位距离的散射系数，不是0的话光线在里面随机游走
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct MediumStack {
+    media: [Medium; MEDIUM_STACK_SIZE],
+    len: usize,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    //优先级最高的那一层，一样高的取后进来的
+    fn top_where(&self, keep: impl Fn(&Medium) -> bool) -> Option<Medium> {
+        let mut top: Option<Medium> = None;
+        for m in self.media[..self.len].iter().filter(|m| keep(m)) {
+            if top.map_or(true, |t| m.priority >= t.priority) {
+                top = Some(*m);
+            }
+        }
+        top
+    }
+    //光线现在所在的介质，空的话就是空气
+    pub fn top(&self) -> Option<Medium> {
+        self.top_where(|_| true)
+    }
+    //不算id这一层的话光线在哪个介质里，也就是这个面另一侧的介质
+    pub fn top_except(&self, id: usize) -> Option<Medium> {
+        self.top_where(|m| m.id != id)
+    }
+    //栈满了就不记了，最多是嵌套太深的地方折射率不对
+    pub fn push(&mut self, m: Medium) {
+        if self.len < MEDIUM_STACK_SIZE {
+            self.media[self.len] = m;
+            self.len += 1;
+        }
+    }
+    pub fn remove(&mut self, id: usize) {
+        if let Some(i) = self.media[..self.len].iter().rposition(|m| m.id == id) {
+            self.media.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+        }
+    }
+    //现在所在的介质会不会散射
+    pub fn scatters(&self) -> bool {
+        self.top().map_or(false, |m| {
+            m.scattering.x > 0. || m.scattering.y > 0. || m.scattering.z > 0.
+        })
+    }
+    pub fn absorption(&self) -> RGBColor {
+        self.top()
+            .map_or(RGBColor::new(0., 0., 0.), |m| m.absorption)
+    }
+    //Beer-Lambert：在当前介质里走了distance之后剩下多少
+    pub fn transmittance(&self, distance: f64) -> RGBColor {
+        let a = self.absorption();
+        RGBColor::new(
+            (-a.x * distance).exp(),
+            (-a.y * distance).exp(),
+            (-a.z * distance).exp(),
+        )
+    }
+}
@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::material::Material;
 use crate::{ray::Ray, vec3::Point3, vec3::Vec3};
 use std::sync::Arc;
+#[derive(Clone, Copy)]
 pub struct Onb {
     pub axis: [Vec3; 3],
 }
@@ -21,6 +22,14 @@ impl Onb {
     pub fn local_vec(&self, a: Vec3) -> Vec3 {
         return self.u() * a.x + self.v() * a.y + self.w() * a.z;
     }
+    //local_vec反过来：世界坐标的向量在这组基下的分量
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(a, self.u()),
+            Vec3::dot(a, self.v()),
+            Vec3::dot(a, self.w()),
+        )
+    }
     pub fn build_from_w(n: Vec3) -> Self {
         let w = Vec3::unit_vector(n);
         let mut a = Vec3::new(0., 0., 0.);
@@ -33,4 +42,11 @@ impl Onb {
         let u = Vec3::cross(w, v);
         Self { axis: [u, v, w] }
     }
+    //w是法向量，u是大概的切向量，正交化一下
+    pub fn build_from_wu(n: Vec3, t: Vec3) -> Self {
+        let w = Vec3::unit_vector(n);
+        let u = Vec3::unit_vector(t - w * Vec3::dot(t, w));
+        let v = Vec3::cross(w, u);
+        Self { axis: [u, v, w] }
+    }
 }
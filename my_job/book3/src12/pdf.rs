@@ -1,5 +1,6 @@
 use crate::aabb::AABB;
 use crate::hittable::Hittable;
+use crate::light::LightList;
 use crate::material::Material;
 use crate::onb::Onb;
 use crate::{ray::Ray, vec3::Point3, vec3::Vec3};
@@ -53,6 +54,24 @@ impl Pdf for hittable_pdf {
         return self.ptr.random(self.o);
     }
 }
+pub struct light_pdf {
+    o: Point3,
+    lights: Arc<LightList>,
+}
+impl light_pdf {
+    pub fn new(lights: Arc<LightList>, origin: Point3) -> Self {
+        Self { o: origin, lights }
+    }
+}
+impl Pdf for light_pdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        return self.lights.pdf_value(self.o, direction);
+    }
+
+    fn generate(&self) -> Vec3 {
+        return self.lights.random(self.o);
+    }
+}
 pub struct mixture_pdf {
     p0: Arc<dyn Pdf>,
     p1: Arc<dyn Pdf>,
@@ -1,44 +1,74 @@
 use super::vec3::{Point3, Vec3};
 use indicatif::WeakProgressBar;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 const POINT_COUNT: usize = 256;
 
+//noise用哪一种：Classic是原来的随机梯度加三次的fade，
+//Improved是Ken Perlin 2002年改进的版本（五次fade、固定的12个梯度），Simplex是单纯形噪声
+#[derive(Copy, Clone, PartialEq)]
+pub enum NoiseKind {
+    Classic,
+    Improved,
+    Simplex,
+}
+
 pub struct Perlin {
     // pub ranfloat: [f64; POINT_COUNT],
     pub ranvec: [Vec3; POINT_COUNT],
     pub perm_x: [usize; POINT_COUNT],
     pub perm_y: [usize; POINT_COUNT],
     pub perm_z: [usize; POINT_COUNT],
+    pub kind: NoiseKind,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Perlin {
+    //默认种子是0，每次运行都是一样的图案
     pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+    //种子一样图案就一样，同一个场景里想要不一样的噪声就换种子
+    pub fn with_seed(seed: u64) -> Self {
         // let mut ranfloat = [0.; POINT_COUNT];
         let mut ranvec = [Vec3::new(0., 0., 0.); POINT_COUNT];
         let mut permx = [0; POINT_COUNT];
         let mut permy = [0; POINT_COUNT];
         let mut permz = [0; POINT_COUNT];
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed);
         for i in 0..POINT_COUNT {
-            ranvec[i] = Vec3::unit_vector(Vec3::random(-1.0, 1.0));
+            ranvec[i] = Vec3::unit_vector(Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            ));
         }
-        Perlin::perline_generate_perm(&mut permx);
-        Perlin::perline_generate_perm(&mut permy);
-        Perlin::perline_generate_perm(&mut permz);
+        Perlin::perline_generate_perm(&mut permx, &mut rng);
+        Perlin::perline_generate_perm(&mut permy, &mut rng);
+        Perlin::perline_generate_perm(&mut permz, &mut rng);
 
         Self {
             ranvec: ranvec,
             perm_x: permx,
             perm_y: permy,
             perm_z: permz,
+            kind: NoiseKind::Classic,
         }
     }
-    pub fn perline_generate_perm(p: &mut [usize; POINT_COUNT]) {
+    pub fn set_kind(&mut self, kind: NoiseKind) {
+        self.kind = kind;
+    }
+    pub fn perline_generate_perm(p: &mut [usize; POINT_COUNT], rng: &mut impl Rng) {
         for i in 0..POINT_COUNT {
             p[i] = i;
         }
         for i in (0..POINT_COUNT).rev() {
-            let target = rand::thread_rng().gen_range(0..i + 1);
+            let target = rng.gen_range(0..i + 1);
             let tmp = p[i];
             p[i] = p[target];
             p[target] = tmp;
@@ -78,6 +108,13 @@ impl Perlin {
     }
 
     pub fn noise(&self, p: Point3) -> f64 {
+        match self.kind {
+            NoiseKind::Classic => self.classic_noise(p),
+            NoiseKind::Improved => self.improved_noise(p),
+            NoiseKind::Simplex => self.simplex_noise(p),
+        }
+    }
+    fn classic_noise(&self, p: Point3) -> f64 {
         let mut u = p.x - p.x.floor();
         let mut v = p.y - p.y.floor();
         let mut w = p.z - p.z.floor();
@@ -100,6 +137,183 @@ impl Perlin {
         }
         return Perlin::trilinear_interp(&c, u, v, w);
     }
+    //改进的噪声只用一张排列表，格点坐标一层层套进去
+    fn hash(&self, i: i32, j: i32, k: i32) -> usize {
+        let p = &self.perm_x;
+        p[(p[(p[(i & 255) as usize] + (j & 255) as usize) & 255] + (k & 255) as usize) & 255]
+    }
+    fn hash4(&self, i: i32, j: i32, k: i32, l: i32) -> usize {
+        self.perm_x[(self.hash(i, j, k) + (l & 255) as usize) & 255]
+    }
+    //6t^5-15t^4+10t^3，二阶导数在格点上也连续，不会有三次fade那样的折痕
+    fn fade(t: f64) -> f64 {
+        t * t * t * (t * (t * 6. - 15.) + 10.)
+    }
+    fn lerp(t: f64, a: f64, b: f64) -> f64 {
+        a + t * (b - a)
+    }
+    //立方体12条棱的方向（补成16个），点积只要加减
+    fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+        let h = hash & 15;
+        let u = if h < 8 { x } else { y };
+        let v = if h < 4 {
+            y
+        } else if h == 12 || h == 14 {
+            x
+        } else {
+            z
+        };
+        (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+    }
+    //四维超立方体32条棱的方向：一维是0，另外三维是±1
+    fn grad4(hash: usize, x: f64, y: f64, z: f64, w: f64) -> f64 {
+        let h = hash & 31;
+        let (a, b, c) = match h >> 3 {
+            0 => (y, z, w),
+            1 => (x, z, w),
+            2 => (x, y, w),
+            _ => (x, y, z),
+        };
+        (if h & 4 == 0 { a } else { -a })
+            + (if h & 2 == 0 { b } else { -b })
+            + (if h & 1 == 0 { c } else { -c })
+    }
+    fn improved_noise(&self, p: Point3) -> f64 {
+        let (i, j, k) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let (x, y, z) = (p.x - p.x.floor(), p.y - p.y.floor(), p.z - p.z.floor());
+        let (u, v, w) = (Perlin::fade(x), Perlin::fade(y), Perlin::fade(z));
+        let g = |di: i32, dj: i32, dk: i32| {
+            Perlin::grad(
+                self.hash(i + di, j + dj, k + dk),
+                x - di as f64,
+                y - dj as f64,
+                z - dk as f64,
+            )
+        };
+        Perlin::lerp(
+            w,
+            Perlin::lerp(
+                v,
+                Perlin::lerp(u, g(0, 0, 0), g(1, 0, 0)),
+                Perlin::lerp(u, g(0, 1, 0), g(1, 1, 0)),
+            ),
+            Perlin::lerp(
+                v,
+                Perlin::lerp(u, g(0, 0, 1), g(1, 0, 1)),
+                Perlin::lerp(u, g(0, 1, 1), g(1, 1, 1)),
+            ),
+        )
+    }
+    //单纯形噪声（Gustavson的写法）：空间斜切成四面体，只算4个顶点，没有沿坐标轴的方块感
+    fn simplex_noise(&self, p: Point3) -> f64 {
+        const F3: f64 = 1. / 3.;
+        const G3: f64 = 1. / 6.;
+        let s = (p.x + p.y + p.z) * F3;
+        let (i, j, k) = (
+            (p.x + s).floor() as i32,
+            (p.y + s).floor() as i32,
+            (p.z + s).floor() as i32,
+        );
+        let t = (i + j + k) as f64 * G3;
+        let x0 = p.x - (i as f64 - t);
+        let y0 = p.y - (j as f64 - t);
+        let z0 = p.z - (k as f64 - t);
+        //按x0、y0、z0的大小顺序决定走的是哪个四面体
+        let (i1, j1, k1, i2, j2, k2) = if x0 >= y0 {
+            if y0 >= z0 {
+                (1, 0, 0, 1, 1, 0)
+            } else if x0 >= z0 {
+                (1, 0, 0, 1, 0, 1)
+            } else {
+                (0, 0, 1, 1, 0, 1)
+            }
+        } else if y0 < z0 {
+            (0, 0, 1, 0, 1, 1)
+        } else if x0 < z0 {
+            (0, 1, 0, 0, 1, 1)
+        } else {
+            (0, 1, 0, 1, 1, 0)
+        };
+        let corners = [
+            (0, 0, 0, 0.),
+            (i1, j1, k1, G3),
+            (i2, j2, k2, 2. * G3),
+            (1, 1, 1, 3. * G3),
+        ];
+        let mut n = 0.;
+        for &(di, dj, dk, g) in corners.iter() {
+            let x = x0 - di as f64 + g;
+            let y = y0 - dj as f64 + g;
+            let z = z0 - dk as f64 + g;
+            let t = 0.6 - x * x - y * y - z * z;
+            if t > 0. {
+                let t2 = t * t;
+                n += t2 * t2 * Perlin::grad(self.hash(i + di, j + dj, k + dk), x, y, z);
+            }
+        }
+        //缩放到[-1,1]左右
+        32. * n
+    }
+    //四维的改进噪声，第四维一般放时间，图案会连续地变
+    pub fn noise4(&self, p: Point3, t: f64) -> f64 {
+        let (i, j, k, l) = (
+            p.x.floor() as i32,
+            p.y.floor() as i32,
+            p.z.floor() as i32,
+            t.floor() as i32,
+        );
+        let (x, y, z, w) = (
+            p.x - p.x.floor(),
+            p.y - p.y.floor(),
+            p.z - p.z.floor(),
+            t - t.floor(),
+        );
+        let fades = [
+            Perlin::fade(x),
+            Perlin::fade(y),
+            Perlin::fade(z),
+            Perlin::fade(w),
+        ];
+        //16个角上的值，按x、y、z、w的顺序一维一维插值下去
+        let mut c = [0.; 16];
+        for (n, v) in c.iter_mut().enumerate() {
+            let (di, dj, dk, dl) = (
+                (n & 1) as i32,
+                (n >> 1 & 1) as i32,
+                (n >> 2 & 1) as i32,
+                (n >> 3) as i32,
+            );
+            *v = Perlin::grad4(
+                self.hash4(i + di, j + dj, k + dk, l + dl),
+                x - di as f64,
+                y - dj as f64,
+                z - dk as f64,
+                w - dl as f64,
+            );
+        }
+        let mut len = 16;
+        for f in fades.iter() {
+            len /= 2;
+            for n in 0..len {
+                c[n] = Perlin::lerp(*f, c[2 * n], c[2 * n + 1]);
+            }
+        }
+        c[0]
+    }
+    //和turb一样，每一层都在时间t上取
+    pub fn turb4(&self, p: Point3, t: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut temp_t = t;
+        let mut weight = 1.0;
+        for _ in 0..7 {
+            accum += self.noise4(temp_p, temp_t) * weight;
+            weight *= 0.5;
+            temp_p = temp_p * 2.0;
+            temp_t *= 2.0;
+        }
+        accum.abs()
+    }
     pub fn turb(&self, p: Point3) -> f64 {
         let mut accum = 0.0;
         let mut temp_p = p;
@@ -111,4 +325,76 @@ impl Perlin {
         }
         return accum.abs();
     }
+    //分形布朗运动：每一层频率乘lacunarity、幅度乘gain，结果有正有负
+    pub fn fbm(&self, p: Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += self.noise(temp_p) * weight;
+            weight *= gain;
+            temp_p = temp_p * lacunarity;
+        }
+        accum
+    }
+    //ridged multifractal（Musgrave）：噪声取绝对值翻过来变成山脊，上一层越高下一层权重越大
+    pub fn ridged(&self, p: Point3, octaves: u32, lacunarity: f64, gain: f64, offset: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        for _ in 0..octaves {
+            let mut signal = offset - self.noise(temp_p).abs();
+            signal *= signal * weight;
+            weight = (signal * 2.).clamp(0., 1.);
+            accum += signal * amplitude;
+            total += amplitude;
+            amplitude *= gain;
+            temp_p = temp_p * lacunarity;
+        }
+        //归一化到[0,1]左右
+        accum / (total * offset * offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //一串不在整数格点上的点，格点上的噪声恒为0
+    fn points() -> Vec<Point3> {
+        (0..64)
+            .map(|i| {
+                let i = i as f64;
+                Point3::new(0.37 * i - 5.1, 1.13 * i + 0.21, -0.71 * i + 2.9)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_noise() {
+        for &kind in [NoiseKind::Classic, NoiseKind::Improved, NoiseKind::Simplex].iter() {
+            let with = |seed| {
+                let mut p = Perlin::with_seed(seed);
+                p.set_kind(kind);
+                p
+            };
+            let (a, b, c) = (with(7), with(7), with(8));
+            let mut zero = Perlin::new();
+            zero.set_kind(kind);
+            let mut differ = 0;
+            for (i, p) in points().into_iter().enumerate() {
+                let t = 0.3 * i as f64;
+                assert_eq!(a.noise(p), b.noise(p));
+                assert_eq!(a.noise4(p, t), b.noise4(p, t));
+                assert_eq!(zero.noise(p), with(0).noise(p));
+                if a.noise(p) != c.noise(p) && a.noise4(p, t) != c.noise4(p, t) {
+                    differ += 1;
+                }
+            }
+            //换了种子几乎每个点都不一样
+            assert!(differ > 60, "{}", differ);
+        }
+    }
 }
@@ -0,0 +1,386 @@
+use crate::aperture::{Aperture, ApertureShape};
+use crate::material::luminance;
+use crate::vec3::RGBColor;
+use std::f64::consts::PI;
+//拼好整张图之后、write_color之前在HDR图上做的后期：泛光、镜头光晕、星芒
+
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RGBColor>, //一行一行存，第0行是画面最下面
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, mut pixels: Vec<RGBColor>) -> Self {
+        if pixels.len() != width * height {
+            panic!(
+                "image has {} pixels, expected {}",
+                pixels.len(),
+                width * height
+            );
+        }
+        //NaN和无穷大先当成0，不然模糊的时候会糊成一整块
+        for c in pixels.iter_mut() {
+            for i in 0..3 {
+                if !c[i].is_finite() {
+                    c[i] = 0.;
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+    pub fn get(&self, x: usize, y: usize) -> RGBColor {
+        self.pixels[y * self.width + x]
+    }
+    //超出画面返回黑色，坐标是像素中心
+    fn get_bilinear(&self, x: f64, y: f64) -> RGBColor {
+        let x = x - 0.5;
+        let y = y - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let mut c = RGBColor::new(0., 0., 0.);
+        for &(dx, dy, w) in [
+            (0., 0., (1. - fx) * (1. - fy)),
+            (1., 0., fx * (1. - fy)),
+            (0., 1., (1. - fx) * fy),
+            (1., 1., fx * fy),
+        ]
+        .iter()
+        {
+            let px = x0 + dx;
+            let py = y0 + dy;
+            if px >= 0. && py >= 0. && px < self.width as f64 && py < self.height as f64 {
+                c += self.get(px as usize, py as usize) * w;
+            }
+        }
+        c
+    }
+    //只留下比threshold亮的那部分
+    fn bright_pass(&self, threshold: f64) -> HdrImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|c| {
+                let l = luminance(*c);
+                if l <= threshold || l <= 0. {
+                    RGBColor::new(0., 0., 0.)
+                } else {
+                    *c * ((l - threshold) / l)
+                }
+            })
+            .collect();
+        HdrImage::new(self.width, self.height, pixels)
+    }
+    //可分离的高斯模糊，边界外当成黑的
+    fn gaussian_blur(&self, sigma: f64) -> HdrImage {
+        let radius = (3. * sigma).ceil() as i64;
+        let mut kernel = Vec::new();
+        let mut sum = 0.;
+        for i in -radius..=radius {
+            let w = (-((i * i) as f64) / (2. * sigma * sigma)).exp();
+            kernel.push(w);
+            sum += w;
+        }
+        for w in kernel.iter_mut() {
+            *w /= sum;
+        }
+        let (width, height) = (self.width as i64, self.height as i64);
+        let mut tmp = vec![RGBColor::new(0., 0., 0.); self.pixels.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut c = RGBColor::new(0., 0., 0.);
+                for (k, w) in kernel.iter().enumerate() {
+                    let sx = x + k as i64 - radius;
+                    if sx >= 0 && sx < width {
+                        c += self.pixels[(y * width + sx) as usize] * *w;
+                    }
+                }
+                tmp[(y * width + x) as usize] = c;
+            }
+        }
+        let mut out = vec![RGBColor::new(0., 0., 0.); self.pixels.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut c = RGBColor::new(0., 0., 0.);
+                for (k, w) in kernel.iter().enumerate() {
+                    let sy = y + k as i64 - radius;
+                    if sy >= 0 && sy < height {
+                        c += tmp[(sy * width + x) as usize] * *w;
+                    }
+                }
+                out[(y * width + x) as usize] = c;
+            }
+        }
+        HdrImage::new(self.width, self.height, out)
+    }
+    fn add(&mut self, other: &HdrImage, scale: f64) {
+        for (c, o) in self.pixels.iter_mut().zip(other.pixels.iter()) {
+            *c += *o * scale;
+        }
+    }
+}
+
+//泛光：亮的部分按几个尺度做高斯模糊再加回去，sigma是最小那一层的像素数，每层翻倍
+pub struct Bloom {
+    pub threshold: f64,
+    pub intensity: f64,
+    pub sigma: f64,
+    pub levels: u32,
+}
+impl Bloom {
+    pub fn new(threshold: f64, intensity: f64) -> Self {
+        Self {
+            threshold,
+            intensity,
+            sigma: 2.,
+            levels: 4,
+        }
+    }
+    fn glow(&self, img: &HdrImage) -> HdrImage {
+        let bright = img.bright_pass(self.threshold);
+        let mut sigma = self.sigma;
+        let mut glow = HdrImage::new(
+            img.width,
+            img.height,
+            vec![RGBColor::new(0., 0., 0.); img.pixels.len()],
+        );
+        for _ in 0..self.levels {
+            glow.add(
+                &bright.gaussian_blur(sigma),
+                self.intensity / self.levels as f64,
+            );
+            sigma *= 2.;
+        }
+        glow
+    }
+}
+
+//镜头光晕：亮的地方沿着过画面中心的直线在对面产生一串鬼影，再加一圈光环
+pub struct LensFlare {
+    pub threshold: f64,
+    pub ghosts: u32,
+    pub ghost_spacing: f64, //相邻鬼影之间隔多远，以到画面中心的距离为单位
+    pub ghost_intensity: f64,
+    pub halo_radius: f64, //光环半径，以画面短边的一半为单位
+    pub halo_intensity: f64,
+}
+impl LensFlare {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            ghosts: 5,
+            ghost_spacing: 0.4,
+            ghost_intensity: 0.02,
+            halo_radius: 0.6,
+            halo_intensity: 0.01,
+        }
+    }
+    fn glow(&self, img: &HdrImage) -> HdrImage {
+        let bright = img.bright_pass(self.threshold).gaussian_blur(2.);
+        let (cx, cy) = (img.width as f64 / 2., img.height as f64 / 2.);
+        let half = cx.min(cy);
+        let mut flare = vec![RGBColor::new(0., 0., 0.); img.pixels.len()];
+        for y in 0..img.height {
+            for x in 0..img.width {
+                let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                let (gx, gy) = (
+                    (cx - px) * self.ghost_spacing,
+                    (cy - py) * self.ghost_spacing,
+                );
+                let mut c = RGBColor::new(0., 0., 0.);
+                for i in 1..=self.ghosts {
+                    let (sx, sy) = (px + gx * i as f64, py + gy * i as f64);
+                    //离画面中心越远的鬼影越暗
+                    let d = ((sx - cx).powi(2) + (sy - cy).powi(2)).sqrt() / half;
+                    let falloff = (1. - d).max(0.).powi(2);
+                    c += bright.get_bilinear(sx, sy) * (falloff * self.ghost_intensity);
+                }
+                let len = (gx * gx + gy * gy).sqrt();
+                if len > 0. {
+                    let (hx, hy) = (
+                        px + gx / len * self.halo_radius * half,
+                        py + gy / len * self.halo_radius * half,
+                    );
+                    //光环只在画面中间一圈比较亮
+                    let d = ((hx - cx).powi(2) + (hy - cy).powi(2)).sqrt() / half;
+                    let falloff = (1. - (d - self.halo_radius).abs() * 4.).max(0.);
+                    c += bright.get_bilinear(hx, hy) * (falloff * self.halo_intensity);
+                }
+                flare[y * img.width + x] = c;
+            }
+        }
+        HdrImage::new(img.width, img.height, flare).gaussian_blur(3.)
+    }
+}
+
+//星芒：光圈叶片的衍射，偶数片叶片有N条，奇数片有2N条
+pub struct Starburst {
+    pub threshold: f64,
+    pub spikes: u32,
+    pub rotation: f64, //度数
+    pub length: f64,   //像素
+    pub intensity: f64,
+}
+impl Starburst {
+    //圆形光圈没有星芒
+    pub fn from_aperture(aperture: &Aperture, threshold: f64, length: f64) -> Option<Self> {
+        match aperture.shape {
+            ApertureShape::Polygon { blades, rotation } => Some(Self {
+                threshold,
+                spikes: if blades % 2 == 0 { blades } else { 2 * blades },
+                //星芒和叶片的边垂直，边的法向就是相邻两个顶点中间的方向
+                rotation: rotation + 180. / blades as f64,
+                length,
+                intensity: 0.01,
+            }),
+            _ => None,
+        }
+    }
+    fn glow(&self, img: &HdrImage) -> HdrImage {
+        let bright = img.bright_pass(self.threshold);
+        let mut burst = vec![RGBColor::new(0., 0., 0.); img.pixels.len()];
+        let steps = self.length.ceil() as usize;
+        for y in 0..img.height {
+            for x in 0..img.width {
+                let c = bright.get(x, y);
+                if luminance(c) <= 0. {
+                    continue;
+                }
+                for k in 0..self.spikes {
+                    let angle =
+                        self.rotation.to_radians() + 2. * PI * k as f64 / self.spikes as f64;
+                    let (dx, dy) = (angle.cos(), angle.sin());
+                    for step in 1..=steps {
+                        let sx = (x as f64 + 0.5 + dx * step as f64).floor();
+                        let sy = (y as f64 + 0.5 + dy * step as f64).floor();
+                        if sx < 0. || sy < 0. || sx >= img.width as f64 || sy >= img.height as f64 {
+                            break;
+                        }
+                        let falloff = (1. - step as f64 / self.length).max(0.).powi(2);
+                        burst[sy as usize * img.width + sx as usize] +=
+                            c * (falloff * self.intensity);
+                    }
+                }
+            }
+        }
+        HdrImage::new(img.width, img.height, burst)
+    }
+}
+
+#[derive(Default)]
+pub struct PostProcess {
+    pub bloom: Option<Bloom>,
+    pub flare: Option<LensFlare>,
+    pub starburst: Option<Starburst>,
+}
+impl PostProcess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //几种效果都从原图取亮的部分，算完一起加回去
+    pub fn apply(&self, img: &mut HdrImage) {
+        let mut glows = Vec::new();
+        if let Some(bloom) = &self.bloom {
+            glows.push(bloom.glow(img));
+        }
+        if let Some(flare) = &self.flare {
+            glows.push(flare.glow(img));
+        }
+        if let Some(starburst) = &self.starburst {
+            glows.push(starburst.glow(img));
+        }
+        for glow in glows.iter() {
+            img.add(glow, 1.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //黑底正中间一个亮点
+    fn spot(size: usize, value: f64) -> HdrImage {
+        let mut pixels = vec![RGBColor::new(0., 0., 0.); size * size];
+        pixels[size / 2 * size + size / 2] = RGBColor::new(value, value, value);
+        HdrImage::new(size, size, pixels)
+    }
+
+    fn total(img: &HdrImage) -> RGBColor {
+        img.pixels
+            .iter()
+            .fold(RGBColor::new(0., 0., 0.), |acc, c| acc + *c)
+    }
+
+    #[test]
+    fn bloom_conserves_energy() {
+        //最大的sigma是16，模糊半径48，121x121的图碰不到边
+        let mut img = spot(121, 5.);
+        let bloom = Bloom::new(1., 0.3);
+        //亮点超过阈值的部分是4，模糊不增不减，乘上intensity加回去
+        let glow = total(&bloom.glow(&img));
+        for i in 0..3 {
+            assert!((glow[i] - 0.3 * 4.).abs() < 1e-9, "{}", glow[i]);
+        }
+        let mut post = PostProcess::new();
+        post.bloom = Some(bloom);
+        post.apply(&mut img);
+        let after = total(&img);
+        for i in 0..3 {
+            assert!((after[i] - (5. + 0.3 * 4.)).abs() < 1e-9, "{}", after[i]);
+        }
+        //阈值以下的图不变
+        let mut dim = spot(121, 0.5);
+        post.apply(&mut dim);
+        assert_eq!(total(&dim)[0], 0.5);
+    }
+
+    #[test]
+    fn starburst_symmetry() {
+        assert!(Starburst::from_aperture(&Aperture::circle(), 1., 15.).is_none());
+        let size = 41;
+        let c = size / 2;
+        for &(blades, rotation, spikes) in [(4, 0., 4), (5, 90., 10), (8, 0., 8)].iter() {
+            let burst =
+                Starburst::from_aperture(&Aperture::polygon(blades, rotation), 1., 15.).unwrap();
+            assert_eq!(burst.spikes, spikes);
+            let glow = burst.glow(&spot(size, 10.));
+            //这几种叶片的星芒左右、上下都对称
+            for y in 0..size {
+                for x in 0..size {
+                    let v = glow.get(x, y)[0];
+                    assert!((v - glow.get(2 * c - x, y)[0]).abs() < 1e-12);
+                    assert!((v - glow.get(x, 2 * c - y)[0]).abs() < 1e-12);
+                }
+            }
+            //星芒都在画面里，每条都是完整的一串，亮点超过阈值的部分是9
+            let per_spike: f64 = (1..=15)
+                .map(|step| (1. - step as f64 / 15.).powi(2) * burst.intensity * 9.)
+                .sum();
+            let energy = total(&glow)[0];
+            assert!(
+                (energy - per_spike * spikes as f64).abs() < 1e-9,
+                "{}",
+                energy
+            );
+            //星芒的方向上亮，两条中间是黑的
+            let at = |angle: f64| {
+                let a = (burst.rotation + angle).to_radians();
+                let x = (c as f64 + 0.5 + 10. * a.cos()).floor() as usize;
+                let y = (c as f64 + 0.5 + 10. * a.sin()).floor() as usize;
+                glow.get(x, y)[0]
+            };
+            let step = 360. / spikes as f64;
+            for k in 0..spikes {
+                assert!(at(k as f64 * step) > 0.);
+                assert_eq!(at((k as f64 + 0.5) * step), 0.);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use super::aabb::AABB;
+use crate::hittable::area_pdf_value;
+use crate::material::{emitted_power, is_emissive};
+use crate::onb::Onb;
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use rand::Rng;
+use std::sync::Arc;
+
+//任意朝向的平面四边形，四个角按顺序绕一圈，要是凸的；
+//uv是双线性的，p[0]是(0,0)，p[1]是(1,0)，p[2]是(1,1)，p[3]是(0,1)
+#[derive(Clone)]
+pub struct Quad {
+    pub p: [Point3; 4],
+    pub mp: Arc<dyn Material>,
+    normal: Vec3,
+    plane: Onb,
+    area: [f64; 2], //按p[0]p[1]p[2]和p[0]p[2]p[3]分成两个三角形
+}
+impl Quad {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, p3: Point3, mp: Arc<dyn Material>) -> Self {
+        //四个角不完全共面的时候用两条对角线的叉乘当法向量
+        let normal = Vec3::unit_vector(Vec3::cross(p2 - p0, p3 - p1));
+        let area = [
+            Vec3::cross(p1 - p0, p2 - p0).length() / 2.,
+            Vec3::cross(p2 - p0, p3 - p0).length() / 2.,
+        ];
+        Self {
+            p: [p0, p1, p2, p3],
+            mp,
+            normal,
+            plane: Onb::build_from_w(normal),
+            area,
+        }
+    }
+    //平行四边形：一个角q加两条边u、v，法向量是u×v
+    pub fn parallelogram(q: Point3, u: Vec3, v: Vec3, mp: Arc<dyn Material>) -> Self {
+        Self::new(q, q + u, q + u + v, q + v, mp)
+    }
+    //平面上的二维坐标，原点是p[0]
+    fn flat(&self, q: Point3) -> (f64, f64) {
+        let l = self.plane.to_local(q - self.p[0]);
+        (l.x, l.y)
+    }
+    //双线性插值反过来求uv（Inigo Quilez的写法），不在四边形里面就是None
+    fn inverse_bilinear(&self, q: Point3) -> Option<(f64, f64)> {
+        let cross = |a: (f64, f64), b: (f64, f64)| a.0 * b.1 - a.1 * b.0;
+        let b = self.flat(self.p[1]);
+        let c = self.flat(self.p[2]);
+        let d = self.flat(self.p[3]);
+        let h = self.flat(q);
+        let e = b;
+        let f = d;
+        let g = (c.0 - b.0 - d.0, c.1 - b.1 - d.1);
+        let k2 = cross(g, f);
+        let k1 = cross(e, f) + cross(h, g);
+        let k0 = cross(h, e);
+        let v = if k2.abs() < 1e-12 {
+            -k0 / k1
+        } else {
+            let w = k1 * k1 - 4. * k0 * k2;
+            if w < 0. {
+                return None;
+            }
+            let w = w.sqrt();
+            let v = (-k1 - w) / (2. * k2);
+            if (0. ..=1.).contains(&v) {
+                v
+            } else {
+                (-k1 + w) / (2. * k2)
+            }
+        };
+        //分母大的那一维解u比较稳
+        let (dx, dy) = (e.0 + g.0 * v, e.1 + g.1 * v);
+        let u = if dx.abs() > dy.abs() {
+            (h.0 - f.0 * v) / dx
+        } else {
+            (h.1 - f.1 * v) / dy
+        };
+        if (0. ..=1.).contains(&u) && (0. ..=1.).contains(&v) {
+            Some((u, v))
+        } else {
+            None
+        }
+    }
+}
+impl Hittable for Quad {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (self.normal, 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        self.area[0] + self.area[1]
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = Vec3::dot(self.normal, r.dir);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = Vec3::dot(self.normal, self.p[0] - r.orig) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let (u, v) = self.inverse_bilinear(r.at(t))?;
+        let mut rec = HitRecord::new(r.at(t), self.normal, t, u, v, true, self.mp.clone());
+        rec.set_face_normal(r, self.normal);
+        let zero = Vec3::new(0., 0., 0.);
+        rec.set_derivatives(
+            (self.p[1] - self.p[0]) * (1. - v) + (self.p[2] - self.p[3]) * v,
+            (self.p[3] - self.p[0]) * (1. - u) + (self.p[2] - self.p[1]) * u,
+            zero,
+            zero,
+        );
+        Some(rec)
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let mut minimum = self.p[0];
+        let mut maximum = self.p[0];
+        for q in self.p.iter() {
+            for c in 0..3 {
+                minimum[c] = minimum[c].min(q[c] - 0.0001);
+                maximum[c] = maximum[c].max(q[c] + 0.0001);
+            }
+        }
+        Some(AABB::new(minimum, maximum))
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    //按面积挑一个三角形，再在三角形里均匀取
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let (a, b, c) = if rng.gen::<f64>() * self.area() < self.area[0] {
+            (self.p[0], self.p[1], self.p[2])
+        } else {
+            (self.p[0], self.p[2], self.p[3])
+        };
+        let su = rng.gen::<f64>().sqrt();
+        let r2 = rng.gen::<f64>();
+        a * (1. - su) + b * (su * (1. - r2)) + c * (su * r2) - o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_close, check_sampling, gray};
+
+    fn ray(o: Point3, d: Vec3) -> Ray {
+        Ray::new(o, d, 0.)
+    }
+
+    #[test]
+    fn parallelogram_hit() {
+        //z=2平面上的正方形，正面朝+z，从原点看过去是背面
+        let q = Quad::parallelogram(
+            Point3::new(-1., -1., 2.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+            gray(),
+        );
+        let o = Point3::new(0., 0., 0.);
+        let rec = q
+            .hit(ray(o, Vec3::new(0., 0., 1.)), 0.001, f64::INFINITY)
+            .unwrap();
+        assert!((rec.t - 2.).abs() < 1e-9);
+        assert!(!rec.front_face);
+        assert_close(rec.normal, Vec3::new(0., 0., -1.));
+        assert!((rec.u - 0.5).abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+        //出了边、平行、超过t_max都打不中
+        assert!(q
+            .hit(ray(o, Vec3::new(1., 0., 1.)), 0.001, f64::INFINITY)
+            .is_none());
+        assert!(q
+            .hit(ray(o, Vec3::new(1., 0., 0.)), 0.001, f64::INFINITY)
+            .is_none());
+        assert!(q.hit(ray(o, Vec3::new(0., 0., 1.)), 0.001, 1.5).is_none());
+    }
+
+    #[test]
+    fn trapezoid_uv() {
+        let q = Quad::new(
+            Point3::new(-1., -1., 2.),
+            Point3::new(1., -1., 2.),
+            Point3::new(0.5, 1., 2.),
+            Point3::new(-0.5, 1., 2.),
+            gray(),
+        );
+        let o = Point3::new(0., 0., 0.);
+        let at = |p: Point3| {
+            q.hit(ray(o, p), 0.001, f64::INFINITY)
+                .map(|rec| (rec.u, rec.v))
+        };
+        let (u, v) = at(Point3::new(0., 0., 2.)).unwrap();
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+        //上边比下边短，靠上的地方u变化得快
+        let (u, v) = at(Point3::new(0.25, 0.5, 2.)).unwrap();
+        assert!(
+            (u - 0.7).abs() < 1e-6 && (v - 0.75).abs() < 1e-6,
+            "{} {}",
+            u,
+            v
+        );
+        //腰的外面
+        assert!(at(Point3::new(0.8, 0.9, 2.)).is_none());
+    }
+
+    #[test]
+    fn quad_pdf() {
+        //斜放的梯形
+        let q = Quad::new(
+            Point3::new(-1., -1., 2.),
+            Point3::new(1., -1., 2.5),
+            Point3::new(0.5, 1., 2.625),
+            Point3::new(-0.5, 1., 2.375),
+            gray(),
+        );
+        check_sampling(&q, Point3::new(0., 0., 0.));
+    }
+}
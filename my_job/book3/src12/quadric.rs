@@ -0,0 +1,698 @@
+use super::aabb::AABB;
+use crate::hittable::area_pdf_value;
+use crate::material::{emitted_power, is_emissive};
+use crate::onb::Onb;
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+//圆盘、圆柱、圆锥、抛物面：都在自己的局部坐标系里求交，z轴是形状的轴，不用再套RotateY
+
+//局部坐标系里的交点，n是朝外的法向量（不用单位化）
+pub struct LocalHit {
+    pub t: f64,
+    pub n: Vec3,
+    pub u: f64,
+    pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+}
+
+//原点加一组正交基，w是形状的轴
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub origin: Point3,
+    pub uvw: Onb,
+}
+impl Frame {
+    pub fn new(origin: Point3, axis: Vec3) -> Self {
+        Self {
+            origin,
+            uvw: Onb::build_from_w(axis),
+        }
+    }
+    //光线换到局部坐标系，t的意义不变
+    pub fn to_local(&self, r: &Ray) -> (Point3, Vec3) {
+        (
+            self.uvw.to_local(r.orig - self.origin),
+            self.uvw.to_local(r.dir),
+        )
+    }
+    pub fn to_world(&self, p: Point3) -> Point3 {
+        self.origin + self.uvw.local_vec(p)
+    }
+    pub fn record(&self, r: Ray, hit: LocalHit, mp: Arc<dyn Material>) -> HitRecord {
+        let outward_normal = Vec3::unit_vector(self.uvw.local_vec(hit.n));
+        let mut rec = HitRecord::new(r.at(hit.t), outward_normal, hit.t, hit.u, hit.v, true, mp);
+        rec.set_face_normal(r, outward_normal);
+        rec.set_derivatives(
+            self.uvw.local_vec(hit.dpdu),
+            self.uvw.local_vec(hit.dpdv),
+            self.uvw.local_vec(hit.dndu),
+            self.uvw.local_vec(hit.dndv),
+        );
+        rec
+    }
+    //局部的包围盒八个角转到世界坐标再包起来
+    pub fn bounding_box(&self, lo: Point3, hi: Point3) -> AABB {
+        let mut minimum = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut maximum = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { lo.x } else { hi.x },
+                if i & 2 == 0 { lo.y } else { hi.y },
+                if i & 4 == 0 { lo.z } else { hi.z },
+            );
+            let p = self.to_world(corner);
+            for c in 0..3 {
+                minimum[c] = minimum[c].min(p[c] - 0.0001);
+                maximum[c] = maximum[c].max(p[c] + 0.0001);
+            }
+        }
+        AABB::new(minimum, maximum)
+    }
+}
+
+//二次方程a t² + b t + c = 0的两个根，从小到大
+fn quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    //避免两个差不多的数相减
+    let q = if b < 0. {
+        -0.5 * (b - sqrtd)
+    } else {
+        -0.5 * (b + sqrtd)
+    };
+    let (t0, t1) = if q.abs() < 1e-300 {
+        (0., 0.)
+    } else {
+        (q / a, c / q)
+    };
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+//局部坐标z=height的平面上半径radius以内的圆，up决定法向量朝+z还是-z
+fn disk_hit(
+    o: Point3,
+    d: Vec3,
+    height: f64,
+    radius: f64,
+    up: bool,
+    t_min: f64,
+    t_max: f64,
+) -> Option<LocalHit> {
+    let t = (height - o.z) / d.z;
+    if !(t >= t_min && t <= t_max) {
+        return None;
+    }
+    let x = o.x + t * d.x;
+    let y = o.y + t * d.y;
+    let r2 = x * x + y * y;
+    if r2 > radius * radius {
+        return None;
+    }
+    let r = r2.sqrt().max(1e-8);
+    let phi = y.atan2(x);
+    let zero = Vec3::new(0., 0., 0.);
+    Some(LocalHit {
+        t,
+        n: Vec3::new(0., 0., if up { 1. } else { -1. }),
+        u: (phi + PI) / (2. * PI),
+        v: r / radius,
+        dpdu: Vec3::new(-y, x, 0.) * (2. * PI),
+        dpdv: Vec3::new(x, y, 0.) * (radius / r),
+        dndu: zero,
+        dndv: zero,
+    })
+}
+
+//半径radius以内均匀取一点
+fn sample_disk(radius: f64) -> (f64, f64) {
+    let mut rng = rand::thread_rng();
+    let r = radius * rng.gen::<f64>().sqrt();
+    let phi = 2. * PI * rng.gen::<f64>();
+    (r * phi.cos(), r * phi.sin())
+}
+
+fn closer(a: Option<LocalHit>, b: Option<LocalHit>) -> Option<LocalHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.t <= b.t { a } else { b }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+//圆盘：center是圆心，normal是正面的方向
+#[derive(Clone)]
+pub struct Disk {
+    pub frame: Frame,
+    pub radius: f64,
+    pub mp: Arc<dyn Material>,
+}
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mp: Arc<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(center, normal),
+            radius,
+            mp,
+        }
+    }
+}
+impl Hittable for Disk {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        (self.frame.uvw.w(), 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (o, d) = self.frame.to_local(&r);
+        let hit = disk_hit(o, d, 0., self.radius, true, t_min, t_max)?;
+        Some(self.frame.record(r, hit, self.mp.clone()))
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = self.radius;
+        Some(
+            self.frame
+                .bounding_box(Point3::new(-r, -r, 0.), Point3::new(r, r, 0.)),
+        )
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let (x, y) = sample_disk(self.radius);
+        self.frame.to_world(Point3::new(x, y, 0.)) - o
+    }
+}
+
+//圆柱：base是底面圆心，axis从底面圆心指到顶面圆心，capped是否带上下两个盖子
+#[derive(Clone)]
+pub struct Cylinder {
+    pub frame: Frame,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub mp: Arc<dyn Material>,
+}
+impl Cylinder {
+    pub fn new(base: Point3, axis: Vec3, radius: f64, capped: bool, mp: Arc<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(base, axis),
+            radius,
+            height: axis.length(),
+            capped,
+            mp,
+        }
+    }
+    fn side_area(&self) -> f64 {
+        2. * PI * self.radius * self.height
+    }
+    fn side_hit(&self, o: Point3, d: Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2. * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - self.radius * self.radius;
+        let (t0, t1) = quadratic(a, b, c)?;
+        for &t in [t0, t1].iter() {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let p = o + d * t;
+            if p.z < 0. || p.z > self.height {
+                continue;
+            }
+            let phi = p.y.atan2(p.x);
+            let dpdu = Vec3::new(-p.y, p.x, 0.) * (2. * PI);
+            return Some(LocalHit {
+                t,
+                n: Vec3::new(p.x, p.y, 0.),
+                u: (phi + PI) / (2. * PI),
+                v: p.z / self.height,
+                dpdu,
+                dpdv: Vec3::new(0., 0., self.height),
+                dndu: dpdu / self.radius,
+                dndv: Vec3::new(0., 0., 0.),
+            });
+        }
+        None
+    }
+}
+impl Hittable for Cylinder {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        if self.capped {
+            self.side_area() + 2. * PI * self.radius * self.radius
+        } else {
+            self.side_area()
+        }
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (o, d) = self.frame.to_local(&r);
+        let mut hit = self.side_hit(o, d, t_min, t_max);
+        if self.capped {
+            hit = closer(hit, disk_hit(o, d, 0., self.radius, false, t_min, t_max));
+            hit = closer(
+                hit,
+                disk_hit(o, d, self.height, self.radius, true, t_min, t_max),
+            );
+        }
+        Some(self.frame.record(r, hit?, self.mp.clone()))
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = self.radius;
+        Some(
+            self.frame
+                .bounding_box(Point3::new(-r, -r, 0.), Point3::new(r, r, self.height)),
+        )
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    //按面积挑侧面还是哪个盖子
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let pick = rng.gen::<f64>() * self.area();
+        let p = if pick < self.side_area() {
+            let phi = 2. * PI * rng.gen::<f64>();
+            Point3::new(
+                self.radius * phi.cos(),
+                self.radius * phi.sin(),
+                self.height * rng.gen::<f64>(),
+            )
+        } else {
+            let (x, y) = sample_disk(self.radius);
+            let z = if rng.gen::<bool>() { self.height } else { 0. };
+            Point3::new(x, y, z)
+        };
+        self.frame.to_world(p) - o
+    }
+}
+
+//圆锥：base是底面圆心，axis从底面圆心指到锥尖，capped是否带底面
+#[derive(Clone)]
+pub struct Cone {
+    pub frame: Frame,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub mp: Arc<dyn Material>,
+}
+impl Cone {
+    pub fn new(base: Point3, axis: Vec3, radius: f64, capped: bool, mp: Arc<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(base, axis),
+            radius,
+            height: axis.length(),
+            capped,
+            mp,
+        }
+    }
+    fn side_area(&self) -> f64 {
+        PI * self.radius * (self.radius * self.radius + self.height * self.height).sqrt()
+    }
+    //x² + y² = k²(h - z)²
+    fn side_hit(&self, o: Point3, d: Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let h = self.height;
+        let k2 = (self.radius / h) * (self.radius / h);
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2. * (o.x * d.x + o.y * d.y + k2 * (h - o.z) * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * (h - o.z) * (h - o.z);
+        let (t0, t1) = quadratic(a, b, c)?;
+        for &t in [t0, t1].iter() {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let p = o + d * t;
+            if p.z < 0. || p.z > h {
+                continue;
+            }
+            let phi = p.y.atan2(p.x);
+            let (sin, cos) = phi.sin_cos();
+            let slant = (h * h + self.radius * self.radius).sqrt();
+            return Some(LocalHit {
+                t,
+                n: Vec3::new(cos * h, sin * h, self.radius),
+                u: (phi + PI) / (2. * PI),
+                v: p.z / h,
+                dpdu: Vec3::new(-p.y, p.x, 0.) * (2. * PI),
+                dpdv: Vec3::new(-self.radius * cos, -self.radius * sin, h),
+                dndu: Vec3::new(-sin, cos, 0.) * (2. * PI * h / slant),
+                dndv: Vec3::new(0., 0., 0.),
+            });
+        }
+        None
+    }
+}
+impl Hittable for Cone {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        if self.capped {
+            self.side_area() + PI * self.radius * self.radius
+        } else {
+            self.side_area()
+        }
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (o, d) = self.frame.to_local(&r);
+        let mut hit = self.side_hit(o, d, t_min, t_max);
+        if self.capped {
+            hit = closer(hit, disk_hit(o, d, 0., self.radius, false, t_min, t_max));
+        }
+        Some(self.frame.record(r, hit?, self.mp.clone()))
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = self.radius;
+        Some(
+            self.frame
+                .bounding_box(Point3::new(-r, -r, 0.), Point3::new(r, r, self.height)),
+        )
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    //侧面上离锥尖的距离按sqrt取才是按面积均匀
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let pick = rng.gen::<f64>() * self.area();
+        let p = if pick < self.side_area() {
+            let s = rng.gen::<f64>().sqrt();
+            let phi = 2. * PI * rng.gen::<f64>();
+            Point3::new(
+                self.radius * s * phi.cos(),
+                self.radius * s * phi.sin(),
+                self.height * (1. - s),
+            )
+        } else {
+            let (x, y) = sample_disk(self.radius);
+            Point3::new(x, y, 0.)
+        };
+        self.frame.to_world(p) - o
+    }
+}
+
+//抛物面：vertex是顶点，axis从顶点指到开口的圆心，开口半径radius，没有盖子
+#[derive(Clone)]
+pub struct Paraboloid {
+    pub frame: Frame,
+    pub radius: f64,
+    pub height: f64,
+    pub mp: Arc<dyn Material>,
+}
+impl Paraboloid {
+    pub fn new(vertex: Point3, axis: Vec3, radius: f64, mp: Arc<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(vertex, axis),
+            radius,
+            height: axis.length(),
+            mp,
+        }
+    }
+    //z = k r²
+    fn k(&self) -> f64 {
+        self.height / (self.radius * self.radius)
+    }
+    //面积 ∫ 2πr√(1 + (2kr)²) dr，cdf(r)是从0积到r
+    fn cdf(&self, r: f64) -> f64 {
+        let k2 = 4. * self.k() * self.k();
+        2. * PI / (3. * k2) * ((1. + k2 * r * r).powf(1.5) - 1.)
+    }
+}
+impl Hittable for Paraboloid {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        self.cdf(self.radius)
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (o, d) = self.frame.to_local(&r);
+        let k = self.k();
+        let a = k * (d.x * d.x + d.y * d.y);
+        let b = 2. * k * (o.x * d.x + o.y * d.y) - d.z;
+        let c = k * (o.x * o.x + o.y * o.y) - o.z;
+        let (t0, t1) = quadratic(a, b, c)?;
+        for &t in [t0, t1].iter() {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let p = o + d * t;
+            if p.z > self.height {
+                continue;
+            }
+            let phi = p.y.atan2(p.x);
+            let (sin, cos) = phi.sin_cos();
+            let v = (p.x * p.x + p.y * p.y).sqrt() / self.radius;
+            //朝外的法向量 (s v cos, s v sin, -1)/L
+            let s = 2. * k * self.radius;
+            let len = (1. + s * s * v * v).sqrt();
+            let n = Vec3::new(s * v * cos, s * v * sin, -1.);
+            let hit = LocalHit {
+                t,
+                n,
+                u: (phi + PI) / (2. * PI),
+                v,
+                dpdu: Vec3::new(-p.y, p.x, 0.) * (2. * PI),
+                dpdv: Vec3::new(self.radius * cos, self.radius * sin, 2. * self.height * v),
+                dndu: Vec3::new(-sin, cos, 0.) * (2. * PI * s * v / len),
+                dndv: Vec3::new(s * cos, s * sin, 0.) / len - n * (s * s * v / (len * len * len)),
+            };
+            return Some(self.frame.record(r, hit, self.mp.clone()));
+        }
+        None
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = self.radius;
+        Some(
+            self.frame
+                .bounding_box(Point3::new(-r, -r, 0.), Point3::new(r, r, self.height)),
+        )
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    //反解cdf取半径
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let k2 = 4. * self.k() * self.k();
+        let x = 1. + rng.gen::<f64>() * ((1. + k2 * self.radius * self.radius).powf(1.5) - 1.);
+        let r = ((x.powf(2. / 3.) - 1.) / k2).max(0.).sqrt();
+        let phi = 2. * PI * rng.gen::<f64>();
+        let p = Point3::new(r * phi.cos(), r * phi.sin(), self.k() * r * r);
+        self.frame.to_world(p) - o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_close, check_sampling, gray};
+
+    fn ray(o: Point3, d: Vec3) -> Ray {
+        Ray::new(o, d, 0.)
+    }
+
+    //打中的t和朝外的法向量
+    fn hit(shape: &dyn Hittable, r: Ray, t_min: f64) -> Option<(f64, Vec3)> {
+        shape.hit(r, t_min, f64::INFINITY).map(|rec| {
+            let outward = if rec.front_face {
+                rec.normal
+            } else {
+                -rec.normal
+            };
+            (rec.t, outward)
+        })
+    }
+
+    #[test]
+    fn disk_hit() {
+        let d = Disk::new(Point3::new(0., 0., 2.), Vec3::new(0., 0., -1.), 1., gray());
+        let rec = d
+            .hit(
+                ray(Point3::new(0., 0., 0.), Vec3::new(0., 0., 1.)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((rec.t - 2.).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert_close(rec.normal, Vec3::new(0., 0., -1.));
+        let back = d
+            .hit(
+                ray(Point3::new(0.5, 0., 3.), Vec3::new(0., 0., -1.)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!(!back.front_face);
+        assert_close(back.normal, Vec3::new(0., 0., 1.));
+        assert!(hit(
+            &d,
+            ray(Point3::new(0., 0., 0.), Vec3::new(1.5, 0., 2.)),
+            0.001
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn cylinder_hit() {
+        let open = Cylinder::new(
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 0., 2.),
+            1.,
+            false,
+            gray(),
+        );
+        let r = ray(Point3::new(-3., 0., 0.), Vec3::new(1., 0., 0.));
+        let (t, n) = hit(&open, r, 0.001).unwrap();
+        assert!((t - 2.).abs() < 1e-9);
+        assert_close(n, Vec3::new(-1., 0., 0.));
+        //t_min越过前面那一面就是从里面打到后面
+        let (t, n) = hit(&open, r, 2.5).unwrap();
+        assert!((t - 4.).abs() < 1e-9);
+        assert_close(n, Vec3::new(1., 0., 0.));
+        //高度外面、沿着轴穿过去都打不中
+        assert!(hit(
+            &open,
+            ray(Point3::new(-3., 0., 1.5), Vec3::new(1., 0., 0.)),
+            0.001
+        )
+        .is_none());
+        let along = ray(Point3::new(0., 0., -3.), Vec3::new(0., 0., 1.));
+        assert!(hit(&open, along, 0.001).is_none());
+        let capped = Cylinder::new(
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 0., 2.),
+            1.,
+            true,
+            gray(),
+        );
+        let (t, n) = hit(&capped, along, 0.001).unwrap();
+        assert!((t - 2.).abs() < 1e-9);
+        assert_close(n, Vec3::new(0., 0., -1.));
+        let (t, n) = hit(&capped, along, 2.5).unwrap();
+        assert!((t - 4.).abs() < 1e-9);
+        assert_close(n, Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn cone_hit() {
+        let cone = Cone::new(
+            Point3::new(0., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            1.,
+            true,
+            gray(),
+        );
+        //z=0.5那里半径0.5，侧面的法向量斜45度
+        let (t, n) = hit(
+            &cone,
+            ray(Point3::new(-3., 0., 0.5), Vec3::new(1., 0., 0.)),
+            0.001,
+        )
+        .unwrap();
+        assert!((t - 2.5).abs() < 1e-9);
+        assert_close(n, Vec3::new(-1., 0., 1.) / 2f64.sqrt());
+        assert!(hit(
+            &cone,
+            ray(Point3::new(-3., 0., 1.5), Vec3::new(1., 0., 0.)),
+            0.001
+        )
+        .is_none());
+        let (t, n) = hit(
+            &cone,
+            ray(Point3::new(0.2, 0., -1.), Vec3::new(0., 0., 1.)),
+            0.001,
+        )
+        .unwrap();
+        assert!((t - 1.).abs() < 1e-9);
+        assert_close(n, Vec3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn paraboloid_hit() {
+        //z = r²，开口在z=1
+        let p = Paraboloid::new(Point3::new(0., 0., 0.), Vec3::new(0., 0., 1.), 1., gray());
+        let (t, n) = hit(
+            &p,
+            ray(Point3::new(0., 0., -1.), Vec3::new(0., 0., 1.)),
+            0.001,
+        )
+        .unwrap();
+        assert!((t - 1.).abs() < 1e-9);
+        assert_close(n, Vec3::new(0., 0., -1.));
+        let (t, n) = hit(
+            &p,
+            ray(Point3::new(-3., 0., 0.25), Vec3::new(1., 0., 0.)),
+            0.001,
+        )
+        .unwrap();
+        assert!((t - 2.5).abs() < 1e-9);
+        assert_close(n, Vec3::new(-1., 0., -1.) / 2f64.sqrt());
+        assert!(hit(
+            &p,
+            ray(Point3::new(-3., 0., 1.5), Vec3::new(1., 0., 0.)),
+            0.001
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn quadric_pdfs() {
+        let o = Point3::new(0.3, -0.2, 0.);
+        let axis = Vec3::new(0.3, 1., 0.5);
+        let at = Point3::new(0., -0.5, 3.);
+        check_sampling(&Disk::new(at, axis, 1., gray()), o);
+        check_sampling(&Cylinder::new(at, axis, 0.8, false, gray()), o);
+        check_sampling(&Cylinder::new(at, axis, 0.8, true, gray()), o);
+        check_sampling(&Cone::new(at, axis, 0.8, true, gray()), o);
+        check_sampling(&Paraboloid::new(at, axis, 0.8, gray()), o);
+    }
+}
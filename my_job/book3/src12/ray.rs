@@ -1,16 +1,37 @@
-use super::material::Material;
+use super::material;
 use crate::bvh::BvhNode;
 use crate::hittable::{HitRecord, Hittable, HittableList};
-use crate::pdf::{cosine_pdf, hittable_pdf, mixture_pdf, Pdf};
+use crate::light::LightList;
+use crate::material::Scattered_Record;
+use crate::medium_stack::MediumStack;
+use crate::pdf::{cosine_pdf, light_pdf, mixture_pdf, Pdf};
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
 use crate::sphere::Sphere;
 use crate::vec3::{Point3, RGBColor, Vec3};
 use rand::Rng;
 use std::sync::Arc;
+
+//随机游走最多走这么多步，走不出来就当作被吸收了
+const MAX_WALK_STEPS: u32 = 1024;
+
 #[derive(Copy, Clone)]
 pub struct Ray {
     pub dir: Point3,
     pub orig: Vec3,
     pub tm: f64,
+    pub wavelength: f64,    //光谱模式下的hero波长（nm），RGB模式是0
+    pub media: MediumStack, //光线现在在哪些透明介质里面
+    pub diff: Option<RayDifferential>,
+}
+
+//光线微分：胶片上往右、往上挪一点打出去的两条光线，用来估计这条光线在表面上覆盖多大，
+//纹理按它选滤波宽度；漫反射之后就没有了
+#[derive(Copy, Clone)]
+pub struct RayDifferential {
+    pub rx_orig: Point3,
+    pub rx_dir: Vec3,
+    pub ry_orig: Point3,
+    pub ry_dir: Vec3,
 }
 
 impl Ray {
@@ -19,6 +40,18 @@ impl Ray {
             orig: origin,
             dir: direction,
             tm: time,
+            wavelength: 0.,
+            media: MediumStack::new(),
+            diff: None,
+        }
+    }
+    //相机给的微分是s、t各挪1的，按一个样本实际覆盖的大小缩小
+    pub fn scale_differentials(&mut self, sx: f64, sy: f64) {
+        if let Some(d) = &mut self.diff {
+            d.rx_orig = self.orig + (d.rx_orig - self.orig) * sx;
+            d.rx_dir = self.dir + (d.rx_dir - self.dir) * sx;
+            d.ry_orig = self.orig + (d.ry_orig - self.orig) * sy;
+            d.ry_dir = self.dir + (d.ry_dir - self.dir) * sy;
         }
     }
     pub fn at(&self, t: f64) -> Vec3 {
@@ -43,42 +76,259 @@ impl Ray {
         r: Ray,
         background: RGBColor,
         world: &BvhNode,
-        lights: Arc<dyn Hittable>,
+        lights: Arc<LightList>,
         depth: i32,
     ) -> RGBColor {
         if (depth <= 0) {
             return RGBColor::new(0., 0., 0.);
         }
-        let mut srec: Option<HitRecord> = None;
-        if let Some(rec) = world.hit(r, 0.001, f64::MAX) {
-            if let Some(emitted) = rec.mat_ptr.emitted(rec.clone(), rec.u, rec.v, rec.p) {
-                if let Some(srec) = rec.mat_ptr.scatter(r, &rec) {
-                    if let Some(specular) = srec.specular_ray {
-                        return srec.attenuation
-                            * Ray::ray_color(specular, background, world, lights, depth - 1);
-                    }
-                    let light_ptr = Arc::new(hittable_pdf::new(lights.clone(), rec.p));
-                    //let p1 = Arc::new(cosine_pdf::new(rec.normal));
-                    let p = mixture_pdf::new(light_ptr, srec.pdf_ptr.unwrap());
-                    let scattered = Ray::new(rec.p, p.generate(), r.tm);
-                    let pdf_val = p.value(scattered.dir);
-                    if let Some(pdf1) = rec.mat_ptr.scattering_pdf(r, rec.clone(), scattered) {
-                        return emitted
-                            + srec.attenuation
-                                * Ray::ray_color(scattered, background, world, lights, depth - 1)
-                                * pdf1
-                                / pdf_val;
-                    } else {
-                        return emitted;
-                    }
-                } else {
-                    return emitted;
+        let (r, rec, weight, absorbed) = match Ray::trace_media(r, world) {
+            Some(walk) => walk,
+            None => return background,
+        };
+        weight
+            * r.media.transmittance(absorbed)
+            * Ray::shade(r, &rec, background, world, lights, depth)
+    }
+    //从r出发走到下一个面：在透明介质里按Beer-Lambert衰减，在会散射的介质里随机游走直到走出边界。
+    //返回最后打到面上的那条光线、碰撞记录、随机游走的权重和最后一段在透明介质里走的距离，
+    //这一段的吸收留给调用的地方乘，光谱模式要按每个波长自己算；什么都没打到返回None
+    fn trace_media(mut r: Ray, world: &BvhNode) -> Option<(Ray, HitRecord, RGBColor, f64)> {
+        let mut rng = rand::thread_rng();
+        let mut weight = RGBColor::new(1., 1., 1.);
+        let mut steps = 0;
+        loop {
+            let rec = world.hit(r, 0.001, f64::MAX)?;
+            let dist = rec.t * r.dir.length();
+            if !r.media.scatters() {
+                return Some((r, rec, weight, dist));
+            }
+            let m = r.media.top().unwrap();
+            let sigma_t = m.absorption + m.scattering;
+            let tr = |d: f64| {
+                RGBColor::new(
+                    (-sigma_t.x * d).exp(),
+                    (-sigma_t.y * d).exp(),
+                    (-sigma_t.z * d).exp(),
+                )
+            };
+            //随机挑一个通道按它的自由程采样距离，pdf取三个通道的平均
+            let channel = rng.gen_range(0..3);
+            let d = -(1. - rng.gen::<f64>()).ln() / sigma_t[channel];
+            if d >= dist {
+                let t = tr(dist);
+                return Some((r, rec, weight * t / ((t.x + t.y + t.z) / 3.), 0.));
+            }
+            steps += 1;
+            if steps >= MAX_WALK_STEPS {
+                return Some((r, rec, RGBColor::new(0., 0., 0.), 0.));
+            }
+            let t = tr(d);
+            let pdf = Vec3::dot(sigma_t, t) / 3.;
+            weight = weight * m.scattering * t / pdf;
+            //各向同性散射，相函数和采样的pdf抵消了
+            let mut next = Ray::new(r.at(d / r.dir.length()), Vec3::random_unit_vector(), r.tm);
+            next.wavelength = r.wavelength;
+            next.media = r.media;
+            r = next;
+        }
+    }
+    fn shade(
+        r: Ray,
+        rec: &HitRecord,
+        background: RGBColor,
+        world: &BvhNode,
+        lights: Arc<LightList>,
+        depth: i32,
+    ) -> RGBColor {
+        //先按几何的切平面算好微分，凹凸贴图的差分步长要用
+        let mut rec = rec.clone();
+        rec.tm = r.tm;
+        rec.compute_differentials(&r);
+        let rec = material::resolve(r, &rec);
+        let rec = &rec;
+        let emitted = match rec.mat_ptr.emitted(r, rec.clone(), rec.u, rec.v, rec.p) {
+            Some(emitted) => emitted,
+            None => return background,
+        };
+        let srec = match rec.mat_ptr.scatter(r, rec) {
+            Some(srec) => srec,
+            None => return emitted,
+        };
+        if let Some(mut specular) = srec.specular_ray {
+            if !rec.mat_ptr.bounds_medium() {
+                specular.media = r.media;
+            }
+            return emitted
+                + srec.attenuation
+                    * Ray::ray_color(specular, background, world, lights, depth - 1);
+        }
+        //delta光源没法被随机方向打中，直接连shadow ray，不做MIS
+        let direct = Ray::direct_delta(r, rec, &srec, world, &lights);
+        let p: Arc<dyn Pdf> = if lights.area_count() > 0 {
+            let light_ptr = Arc::new(light_pdf::new(lights.clone(), rec.p));
+            Arc::new(mixture_pdf::new(light_ptr, srec.pdf_ptr.clone().unwrap()))
+        } else {
+            srec.pdf_ptr.clone().unwrap()
+        };
+        let mut scattered = Ray::new(rec.p, p.generate(), r.tm);
+        scattered.media = r.media;
+        let pdf_val = p.value(scattered.dir);
+        match srec.scattering_pdf(r, rec, scattered) {
+            Some(pdf1) if pdf_val > 0. => {
+                emitted
+                    + direct
+                    + srec.attenuation
+                        * Ray::ray_color(scattered, background, world, lights, depth - 1)
+                        * pdf1
+                        / pdf_val
+            }
+            _ => emitted + direct,
+        }
+    }
+    fn direct_delta(
+        r: Ray,
+        rec: &HitRecord,
+        srec: &Scattered_Record,
+        world: &BvhNode,
+        lights: &LightList,
+    ) -> RGBColor {
+        let mut direct = RGBColor::new(0., 0., 0.);
+        for light in lights.delta_lights() {
+            if let Some(ls) = light.sample_li(rec.p) {
+                let shadow = Ray::new(rec.p, ls.wi, r.tm);
+                if world.hit(shadow, 0.001, ls.dist - 0.001).is_some() {
+                    continue;
+                }
+                if let Some(f) = srec.scattering_pdf(r, rec, shadow) {
+                    direct += srec.attenuation * ls.radiance * f / ls.pdf;
                 }
-            } else {
-                return background;
             }
+        }
+        direct
+    }
+    //和ray_color一样，只是每条路径带着几个波长，颜色都在这几个波长上算
+    pub fn ray_color_spectral(
+        r: Ray,
+        background: RGBColor,
+        world: &BvhNode,
+        lights: Arc<LightList>,
+        depth: i32,
+        lambdas: &mut SampledWavelengths,
+    ) -> SampledSpectrum {
+        if depth <= 0 {
+            return SampledSpectrum::new(0.);
+        }
+        //随机游走的权重按RGB算完再升成光谱，透明介质的吸收按每个波长的系数算Beer-Lambert
+        let (r, rec, weight, absorbed) = match Ray::trace_media(r, world) {
+            Some(walk) => walk,
+            None => return SampledSpectrum::from_rgb(background, lambdas),
+        };
+        let sigma = SampledSpectrum::from_rgb(r.media.absorption(), lambdas);
+        let transmittance = (sigma * -absorbed).exp();
+        SampledSpectrum::from_rgb(weight, lambdas)
+            * transmittance
+            * Ray::shade_spectral(r, &rec, background, world, lights, depth, lambdas)
+    }
+    fn shade_spectral(
+        r: Ray,
+        rec: &HitRecord,
+        background: RGBColor,
+        world: &BvhNode,
+        lights: Arc<LightList>,
+        depth: i32,
+        lambdas: &mut SampledWavelengths,
+    ) -> SampledSpectrum {
+        //先按几何的切平面算好微分，凹凸贴图的差分步长要用
+        let mut rec = rec.clone();
+        rec.tm = r.tm;
+        rec.compute_differentials(&r);
+        let rec = material::resolve(r, &rec);
+        let rec = &rec;
+        let emitted = match rec.mat_ptr.emitted(r, rec.clone(), rec.u, rec.v, rec.p) {
+            Some(emitted) => SampledSpectrum::from_rgb(emitted, lambdas),
+            None => return SampledSpectrum::from_rgb(background, lambdas),
+        };
+        let srec = match rec.mat_ptr.scatter(r, rec) {
+            Some(srec) => srec,
+            None => return emitted,
+        };
+        let attenuation = srec
+            .spectral
+            .unwrap_or_else(|| SampledSpectrum::from_rgb(srec.attenuation, lambdas));
+        if let Some(mut specular) = srec.specular_ray {
+            //色散的折射只对hero波长是对的
+            if rec.mat_ptr.dispersive() {
+                lambdas.terminate_secondary();
+            }
+            specular.wavelength = r.wavelength;
+            if !rec.mat_ptr.bounds_medium() {
+                specular.media = r.media;
+            }
+            return emitted
+                + attenuation
+                    * Ray::ray_color_spectral(
+                        specular,
+                        background,
+                        world,
+                        lights,
+                        depth - 1,
+                        lambdas,
+                    );
+        }
+        let direct =
+            Ray::direct_delta_spectral(r, rec, &srec, attenuation, world, &lights, lambdas);
+        let p: Arc<dyn Pdf> = if lights.area_count() > 0 {
+            let light_ptr = Arc::new(light_pdf::new(lights.clone(), rec.p));
+            Arc::new(mixture_pdf::new(light_ptr, srec.pdf_ptr.clone().unwrap()))
         } else {
-            return background;
+            srec.pdf_ptr.clone().unwrap()
+        };
+        let mut scattered = Ray::new(rec.p, p.generate(), r.tm);
+        scattered.wavelength = r.wavelength;
+        scattered.media = r.media;
+        let pdf_val = p.value(scattered.dir);
+        match srec.scattering_pdf(r, rec, scattered) {
+            Some(pdf1) if pdf_val > 0. => {
+                emitted
+                    + direct
+                    + attenuation
+                        * Ray::ray_color_spectral(
+                            scattered,
+                            background,
+                            world,
+                            lights,
+                            depth - 1,
+                            lambdas,
+                        )
+                        * pdf1
+                        / pdf_val
+            }
+            _ => emitted + direct,
+        }
+    }
+    fn direct_delta_spectral(
+        r: Ray,
+        rec: &HitRecord,
+        srec: &Scattered_Record,
+        attenuation: SampledSpectrum,
+        world: &BvhNode,
+        lights: &LightList,
+        lambdas: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let mut direct = SampledSpectrum::new(0.);
+        for light in lights.delta_lights() {
+            if let Some(ls) = light.sample_li(rec.p) {
+                let shadow = Ray::new(rec.p, ls.wi, r.tm);
+                if world.hit(shadow, 0.001, ls.dist - 0.001).is_some() {
+                    continue;
+                }
+                if let Some(f) = srec.scattering_pdf(r, rec, shadow) {
+                    direct +=
+                        attenuation * SampledSpectrum::from_rgb(ls.radiance, lambdas) * f / ls.pdf;
+                }
+            }
         }
+        direct
     }
 }
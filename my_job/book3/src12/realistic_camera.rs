@@ -0,0 +1,555 @@
+use super::ray::Ray;
+use super::vec3::{Point3, Vec3};
+use crate::aperture::Aperture;
+use crate::camera::{finite_differential, random_time, Camera};
+use console::style;
+use rand::Rng;
+use std::fs;
+//真实镜头：光线从胶片出发穿过一组球面镜片，渐晕、畸变、焦外光斑都是自然算出来的
+//镜头文件和pbrt的格式一样：每行 曲率半径 厚度 折射率 通光口径，单位mm，从物体一侧写到胶片一侧
+//半径为0的那一行是光圈，最后一行的厚度是到胶片的距离，对焦时会被改掉
+
+#[derive(Copy, Clone)]
+pub struct LensElement {
+    pub curvature_radius: f64,
+    pub thickness: f64,
+    pub eta: f64, //这个面后面（胶片一侧）介质的折射率，0表示空气
+    pub aperture_radius: f64,
+}
+
+#[derive(Copy, Clone)]
+struct PupilBounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+impl PupilBounds {
+    fn empty() -> Self {
+        Self {
+            min: (f64::INFINITY, f64::INFINITY),
+            max: (-f64::INFINITY, -f64::INFINITY),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.min.0 > self.max.0 || self.min.1 > self.max.1
+    }
+    fn inside(&self, x: f64, y: f64) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+    fn add(&mut self, x: f64, y: f64) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+    fn area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+    }
+}
+
+fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    //wi和n在同一侧，eta = eta_i / eta_t
+    let cos_theta_i = Vec3::dot(n, wi);
+    let sin2_theta_i = (1. - cos_theta_i * cos_theta_i).max(0.);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1. {
+        return None;
+    }
+    let cos_theta_t = (1. - sin2_theta_t).sqrt();
+    Some(-wi * eta + n * (eta * cos_theta_i - cos_theta_t))
+}
+
+//和球心在光轴z_center处、半径radius的球面求交，返回t和朝着来光一侧的法向量
+fn intersect_spherical_element(
+    radius: f64,
+    z_center: f64,
+    o: Point3,
+    d: Vec3,
+) -> Option<(f64, Vec3)> {
+    let oc = o - Vec3::new(0., 0., z_center);
+    let a = d.length_squared();
+    let b = 2. * Vec3::dot(d, oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-b - sqrtd) / (2. * a);
+    let t1 = (-b + sqrtd) / (2. * a);
+    let use_closer_t = (d.z > 0.) ^ (radius < 0.);
+    let t = if use_closer_t { t0.min(t1) } else { t0.max(t1) };
+    if t < 0. {
+        return None;
+    }
+    let mut n = Vec3::unit_vector(oc + d * t);
+    if Vec3::dot(n, -d) < 0. {
+        n = -n;
+    }
+    Some((t, n))
+}
+
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.y, -v.z)
+}
+
+pub struct RealisticCamera {
+    elements: Vec<LensElement>,
+    origin: Point3, //胶片中心的位置
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    film_width: f64,
+    film_height: f64,
+    film_diagonal: f64,
+    mm_per_unit: f64,
+    exit_pupil_bounds: Vec<PupilBounds>,
+    aperture_shape: Aperture,
+    time0: f64,
+    time1: f64,
+}
+
+pub fn load_lens_file(path: &str) -> Vec<LensElement> {
+    let text = fs::read_to_string(path).expect("failed to read lens file");
+    let mut elements = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let nums: Vec<f64> = line
+            .split_whitespace()
+            .map(|x| x.parse().expect("bad number in lens file"))
+            .collect();
+        if nums.len() != 4 {
+            panic!("lens file lines need 4 numbers: {}", line);
+        }
+        elements.push(LensElement {
+            curvature_radius: nums[0],
+            thickness: nums[1],
+            eta: nums[2],
+            aperture_radius: nums[3] / 2.,
+        });
+    }
+    elements
+}
+
+#[allow(clippy::too_many_arguments)]
+impl RealisticCamera {
+    //lookfrom是胶片中心的位置；aperture_diameter、film_diagonal单位mm；
+    //focus_dist是场景单位，从胶片量起；mm_per_unit是场景里一个单位对应多少mm
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        lens_file: &str,
+        aperture_diameter: f64,
+        focus_dist: f64,
+        film_diagonal: f64,
+        aspect_ratio: f64,
+        mm_per_unit: f64,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
+        let mut elements = load_lens_file(lens_file);
+        if elements.is_empty() {
+            panic!("lens file {} has no elements", lens_file);
+        }
+        for e in elements.iter_mut() {
+            //光圈不能开得比镜头里写的还大
+            if e.curvature_radius == 0. {
+                e.aperture_radius = e.aperture_radius.min(aperture_diameter / 2.);
+            }
+        }
+        let w = Vec3::unit_vector(lookfrom - lookat);
+        let u = Vec3::unit_vector(Vec3::cross(vup, w));
+        let v = Vec3::cross(w, u);
+        let film_height = film_diagonal / (1. + aspect_ratio * aspect_ratio).sqrt();
+        let mut cam = Self {
+            elements,
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            film_width: film_height * aspect_ratio,
+            film_height,
+            film_diagonal,
+            mm_per_unit,
+            exit_pupil_bounds: Vec::new(),
+            aperture_shape: Aperture::circle(),
+            time0,
+            time1,
+        };
+        let film_dist = cam.focus_thick_lens(focus_dist * mm_per_unit);
+        cam.elements.last_mut().unwrap().thickness = film_dist;
+        cam.compute_exit_pupil_bounds();
+        cam
+    }
+
+    //按厚透镜近似的焦距把光圈收到f/f_number，不会比镜头里写的还大
+    //之后要调用set_aperture_shape重新算出瞳范围
+    pub fn set_f_number(&mut self, f_number: f64) {
+        let (pz, fz) = self.compute_thick_lens_approximation();
+        let radius = (fz[0] - pz[0]).abs() / f_number / 2.;
+        for e in self.elements.iter_mut() {
+            if e.curvature_radius == 0. {
+                e.aperture_radius = e.aperture_radius.min(radius);
+            }
+        }
+    }
+
+    //光圈那一面的形状，出瞳范围要重新算
+    pub fn set_aperture_shape(&mut self, aperture_shape: Aperture) {
+        self.aperture_shape = aperture_shape;
+        self.compute_exit_pupil_bounds();
+    }
+
+    fn lens_rear_z(&self) -> f64 {
+        self.elements.last().unwrap().thickness
+    }
+    fn lens_front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+    fn rear_element_radius(&self) -> f64 {
+        self.elements.last().unwrap().aperture_radius
+    }
+
+    //相机空间里胶片在z=0，镜头在+z方向；被镜片挡住或者全反射就返回None
+    fn trace_lenses_from_film(&self, o: Point3, d: Vec3) -> Option<(Point3, Vec3)> {
+        self.trace_from_film(o, d).map(|(o, d, _)| (o, d))
+    }
+
+    //顺便返回光圈那一面透过的比例，光圈形状是灰度图的时候可能在0和1之间
+    fn trace_from_film(&self, o: Point3, d: Vec3) -> Option<(Point3, Vec3, f64)> {
+        let mut transmittance = 1.;
+        let mut element_z = 0.;
+        let mut o = flip_z(o);
+        let mut d = flip_z(d);
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+            let is_stop = element.curvature_radius == 0.;
+            let (t, n) = if is_stop {
+                if d.z >= 0. {
+                    return None;
+                }
+                ((element_z - o.z) / d.z, Vec3::new(0., 0., 1.))
+            } else {
+                let radius = element.curvature_radius;
+                intersect_spherical_element(radius, element_z + radius, o, d)?
+            };
+            let p_hit = o + d * t;
+            if p_hit.x * p_hit.x + p_hit.y * p_hit.y
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            o = p_hit;
+            if is_stop {
+                transmittance = self.aperture_shape.transmittance(
+                    p_hit.x / element.aperture_radius,
+                    p_hit.y / element.aperture_radius,
+                );
+                if transmittance <= 0. {
+                    return None;
+                }
+            } else {
+                let eta_i = if element.eta != 0. { element.eta } else { 1. };
+                let eta_t = if i > 0 && self.elements[i - 1].eta != 0. {
+                    self.elements[i - 1].eta
+                } else {
+                    1.
+                };
+                d = refract(Vec3::unit_vector(-d), n, eta_i / eta_t)?;
+            }
+        }
+        Some((flip_z(o), flip_z(d), transmittance))
+    }
+
+    fn trace_lenses_from_scene(&self, o: Point3, d: Vec3) -> Option<(Point3, Vec3)> {
+        let mut element_z = -self.lens_front_z();
+        let mut o = flip_z(o);
+        let mut d = flip_z(d);
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+            let is_stop = element.curvature_radius == 0.;
+            let (t, n) = if is_stop {
+                if d.z <= 0. {
+                    return None;
+                }
+                ((element_z - o.z) / d.z, Vec3::new(0., 0., -1.))
+            } else {
+                let radius = element.curvature_radius;
+                intersect_spherical_element(radius, element_z + radius, o, d)?
+            };
+            let p_hit = o + d * t;
+            if p_hit.x * p_hit.x + p_hit.y * p_hit.y
+                > element.aperture_radius * element.aperture_radius
+            {
+                return None;
+            }
+            o = p_hit;
+            if !is_stop {
+                let eta_i = if i == 0 || self.elements[i - 1].eta == 0. {
+                    1.
+                } else {
+                    self.elements[i - 1].eta
+                };
+                let eta_t = if element.eta != 0. { element.eta } else { 1. };
+                d = refract(Vec3::unit_vector(-d), n, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+        Some((flip_z(o), flip_z(d)))
+    }
+
+    //一条平行于光轴的光线进出镜头，求主平面和焦点的z
+    fn compute_cardinal_points(r_in: (Point3, Vec3), r_out: (Point3, Vec3)) -> (f64, f64) {
+        let tf = -r_out.0.x / r_out.1.x;
+        let fz = -(r_out.0 + r_out.1 * tf).z;
+        let tp = (r_in.0.x - r_out.0.x) / r_out.1.x;
+        let pz = -(r_out.0 + r_out.1 * tp).z;
+        (pz, fz)
+    }
+
+    //厚透镜近似：[0]是物方，[1]是像方
+    fn compute_thick_lens_approximation(&self) -> ([f64; 2], [f64; 2]) {
+        let x = 0.001 * self.film_diagonal;
+        let r_scene = (
+            Point3::new(x, 0., self.lens_front_z() + 1.),
+            Vec3::new(0., 0., -1.),
+        );
+        let r_film = self
+            .trace_lenses_from_scene(r_scene.0, r_scene.1)
+            .expect("paraxial ray blocked when computing the thick lens approximation");
+        let (pz0, fz0) = RealisticCamera::compute_cardinal_points(r_scene, r_film);
+
+        let r_film = (
+            Point3::new(x, 0., self.lens_rear_z() - 1.),
+            Vec3::new(0., 0., 1.),
+        );
+        let r_scene = self
+            .trace_lenses_from_film(r_film.0, r_film.1)
+            .expect("paraxial ray blocked when computing the thick lens approximation");
+        let (pz1, fz1) = RealisticCamera::compute_cardinal_points(r_film, r_scene);
+        ([pz0, pz1], [fz0, fz1])
+    }
+
+    //按厚透镜公式算出要对焦到focus_distance（mm，从胶片量起）胶片该离后镜片多远；
+    //物距小于两倍焦距就成不了实像，太近的话退到最近能对上的距离
+    fn focus_thick_lens(&self, focus_distance: f64) -> f64 {
+        let (pz, fz) = self.compute_thick_lens_approximation();
+        let f = fz[0] - pz[0];
+        let closest = 1.01 * (4. * f + pz[0] - pz[1]);
+        let focus_distance = if focus_distance < closest {
+            println!(
+                "{}",
+                style(format!(
+                    "Warning: focus distance {:.1} mm is too close for this lens, focusing at {:.1} mm instead.",
+                    focus_distance, closest
+                ))
+                .yellow()
+            );
+            closest
+        } else {
+            focus_distance
+        };
+        let z = -focus_distance;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4. * f - pz[0]);
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        self.lens_rear_z() + delta
+    }
+
+    //胶片上离中心不同距离的点，能穿过整个镜头的光线落在后镜片平面上的范围
+    fn compute_exit_pupil_bounds(&mut self) {
+        const N_SEGMENTS: usize = 64;
+        const N_SAMPLES: usize = 16384;
+        let rear_radius = self.rear_element_radius();
+        let rear_z = self.lens_rear_z();
+        let mut rng = rand::thread_rng();
+        let mut all_bounds = Vec::with_capacity(N_SEGMENTS);
+        for i in 0..N_SEGMENTS {
+            let r0 = i as f64 / N_SEGMENTS as f64 * self.film_diagonal / 2.;
+            let r1 = (i + 1) as f64 / N_SEGMENTS as f64 * self.film_diagonal / 2.;
+            let mut bounds = PupilBounds::empty();
+            for j in 0..N_SAMPLES {
+                let x_film = r0 + (r1 - r0) * (j as f64 + 0.5) / N_SAMPLES as f64;
+                let x = rng.gen_range(-1.5 * rear_radius..1.5 * rear_radius);
+                let y = rng.gen_range(-1.5 * rear_radius..1.5 * rear_radius);
+                let p_film = Point3::new(x_film, 0., 0.);
+                let p_rear = Point3::new(x, y, rear_z);
+                if bounds.inside(x, y)
+                    || self
+                        .trace_lenses_from_film(p_film, p_rear - p_film)
+                        .is_some()
+                {
+                    bounds.add(x, y);
+                }
+            }
+            if !bounds.is_empty() {
+                //随机采样会漏掉边缘，往外扩一点
+                let expand = 2. * 3. * rear_radius * 2f64.sqrt() / (N_SAMPLES as f64).sqrt();
+                bounds.min = (bounds.min.0 - expand, bounds.min.1 - expand);
+                bounds.max = (bounds.max.0 + expand, bounds.max.1 + expand);
+            }
+            all_bounds.push(bounds);
+        }
+        self.exit_pupil_bounds = all_bounds;
+    }
+
+    //在出瞳范围里采一个后镜片平面上的点，顺便返回范围的面积
+    fn sample_exit_pupil(&self, x: f64, y: f64) -> Option<(Point3, f64)> {
+        let r_film = (x * x + y * y).sqrt();
+        let index = ((r_film / (self.film_diagonal / 2.) * self.exit_pupil_bounds.len() as f64)
+            as usize)
+            .min(self.exit_pupil_bounds.len() - 1);
+        let bounds = self.exit_pupil_bounds[index];
+        if bounds.is_empty() {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let px = rng.gen_range(bounds.min.0..bounds.max.0);
+        let py = rng.gen_range(bounds.min.1..bounds.max.1);
+        //范围是在x轴上算的，转到胶片点所在的方向
+        let (sin_theta, cos_theta) = if r_film != 0. {
+            (y / r_film, x / r_film)
+        } else {
+            (0., 1.)
+        };
+        Some((
+            Point3::new(
+                cos_theta * px - sin_theta * py,
+                sin_theta * px + cos_theta * py,
+                self.lens_rear_z(),
+            ),
+            bounds.area(),
+        ))
+    }
+}
+
+impl Camera for RealisticCamera {
+    //权重是cos^4乘出瞳面积，画面中心是1
+    fn get_ray(&self, s: f64, t: f64) -> Option<(Ray, f64)> {
+        //镜头成的是倒像，胶片上的坐标要反过来
+        let p_film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.,
+        );
+        let (p_rear, pupil_area) = self.sample_exit_pupil(p_film.x, p_film.y)?;
+        let d = p_rear - p_film;
+        let (o, dir, transmittance) = self.trace_from_film(p_film, d)?;
+        let cos_theta = Vec3::unit_vector(d).z;
+        let center_area = self.exit_pupil_bounds[0].area();
+        let weight = if center_area > 0. {
+            cos_theta.powi(4) * pupil_area / center_area
+        } else {
+            cos_theta.powi(4)
+        } * transmittance;
+        let to_world = |a: Vec3| self.u * a.x + self.v * a.y - self.w * a.z;
+        let mut r = Ray::new(
+            self.origin + to_world(o) / self.mm_per_unit,
+            to_world(dir),
+            random_time(self.time0, self.time1),
+        );
+        //胶片上挪一点，还是打向后镜片上同一个点
+        r.diff = finite_differential(s, t, |s, t| {
+            let p = Point3::new(
+                -(s - 0.5) * self.film_width,
+                -(t - 0.5) * self.film_height,
+                0.,
+            );
+            let (o, dir, _) = self.trace_from_film(p, p_rear - p)?;
+            Some((self.origin + to_world(o) / self.mm_per_unit, to_world(dir)))
+        });
+        Some((r, weight))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //镜头文件编进测试里，不依赖跑测试时的当前目录；几个测试并行跑，只写一次
+    fn camera(focus_dist: f64) -> RealisticCamera {
+        static WRITE: std::sync::Once = std::sync::Once::new();
+        let path = std::env::temp_dir().join("realistic_camera_test.dgauss.50mm.dat");
+        WRITE.call_once(|| fs::write(&path, include_str!("dgauss.50mm.dat")).unwrap());
+        //一个场景单位是1m
+        RealisticCamera::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            path.to_str().unwrap(),
+            20.,
+            focus_dist,
+            35.,
+            1.5,
+            1000.,
+            0.,
+            1.,
+        )
+    }
+
+    //胶片中心发出一条很靠近光轴的光线，出了镜头之后和光轴交在对焦距离上
+    fn paraxial_focus(cam: &RealisticCamera) -> f64 {
+        let x = 0.01 * cam.rear_element_radius();
+        let p_film = Point3::new(0., 0., 0.);
+        let (o, d) = cam
+            .trace_lenses_from_film(p_film, Point3::new(x, 0., cam.lens_rear_z()) - p_film)
+            .unwrap();
+        let t = -o.x / d.x;
+        o.z + d.z * t
+    }
+
+    #[test]
+    fn paraxial_ray_converges_at_focus() {
+        for &focus in [1., 3.].iter() {
+            let z = paraxial_focus(&camera(focus));
+            assert!(
+                (z / 1000. - focus).abs() < 0.01 * focus,
+                "focused at {} m, paraxial ray meets the axis at {} m",
+                focus,
+                z / 1000.
+            );
+        }
+    }
+
+    #[test]
+    fn too_close_focus_is_clamped() {
+        //5cm对50mm的镜头太近了，退到最近能对上的地方，还是在镜头前面成像
+        let cam = camera(0.05);
+        assert!(cam.lens_rear_z().is_finite() && cam.lens_rear_z() > 0.);
+        let z = paraxial_focus(&cam);
+        assert!(z > cam.lens_front_z() && z.is_finite(), "{}", z);
+    }
+
+    //能穿过整个镜头的光线在后镜片平面上的落点都在出瞳范围里面，画面中心的范围左右对称
+    #[test]
+    fn exit_pupil_bounds_cover_traced_rays() {
+        let cam = camera(2.);
+        let bounds = &cam.exit_pupil_bounds;
+        let center = bounds[0];
+        assert!(!center.is_empty());
+        let width = center.max.0 - center.min.0;
+        assert!((center.min.0 + center.max.0).abs() < 0.05 * width);
+        assert!((center.min.1 + center.max.1).abs() < 0.05 * width);
+        let rear = cam.rear_element_radius();
+        let mut rng = rand::thread_rng();
+        for i in (0..bounds.len()).step_by(8) {
+            let x_film = (i as f64 + 0.5) / bounds.len() as f64 * cam.film_diagonal / 2.;
+            let p_film = Point3::new(x_film, 0., 0.);
+            for _ in 0..2000 {
+                let x = rng.gen_range(-rear..rear);
+                let y = rng.gen_range(-rear..rear);
+                let p_rear = Point3::new(x, y, cam.lens_rear_z());
+                if cam
+                    .trace_lenses_from_film(p_film, p_rear - p_film)
+                    .is_some()
+                {
+                    assert!(bounds[i].inside(x, y), "segment {}: ({}, {})", i, x, y);
+                }
+            }
+        }
+    }
+}
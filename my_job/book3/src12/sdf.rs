@@ -0,0 +1,375 @@
+use super::aabb::AABB;
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use std::f64::consts::PI;
+use std::sync::Arc;
+//有向距离场：外面是正的，里面是负的，绝对值不超过到表面的真实距离
+//沿着光线每次走一个距离值（sphere tracing），走到足够近就算打中
+
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: Point3) -> f64;
+}
+
+#[derive(Clone, Copy)]
+pub struct Sphere_Sdf {
+    pub center: Point3,
+    pub radius: f64,
+}
+impl Sphere_Sdf {
+    pub fn new(center: Point3, radius: f64) -> Self {
+        Self { center, radius }
+    }
+}
+impl Sdf for Sphere_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        (p - self.center).length() - self.radius
+    }
+}
+
+//轴对齐的方块，half是三个方向的半边长，round是倒角半径
+#[derive(Clone, Copy)]
+pub struct Box_Sdf {
+    pub center: Point3,
+    pub half: Vec3,
+    pub round: f64,
+}
+impl Box_Sdf {
+    pub fn new(center: Point3, half: Vec3, round: f64) -> Self {
+        Self {
+            center,
+            half,
+            round,
+        }
+    }
+}
+impl Sdf for Box_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let d = p - self.center;
+        let mut q = Vec3::new(0., 0., 0.);
+        for i in 0..3 {
+            q[i] = d[i].abs() - (self.half[i] - self.round);
+        }
+        let outside = Vec3::new(q.x.max(0.), q.y.max(0.), q.z.max(0.)).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.);
+        outside + inside - self.round
+    }
+}
+
+//躺在xz平面上的圆环
+#[derive(Clone, Copy)]
+pub struct Torus_Sdf {
+    pub center: Point3,
+    pub major: f64,
+    pub minor: f64,
+}
+impl Torus_Sdf {
+    pub fn new(center: Point3, major: f64, minor: f64) -> Self {
+        Self {
+            center,
+            major,
+            minor,
+        }
+    }
+}
+impl Sdf for Torus_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let d = p - self.center;
+        let ring = (d.x * d.x + d.z * d.z).sqrt() - self.major;
+        (ring * ring + d.y * d.y).sqrt() - self.minor
+    }
+}
+
+//Mandelbulb分形的距离估计，power一般取8
+#[derive(Clone, Copy)]
+pub struct Mandelbulb_Sdf {
+    pub center: Point3,
+    pub scale: f64,
+    pub power: f64,
+    pub iterations: usize,
+}
+impl Mandelbulb_Sdf {
+    pub fn new(center: Point3, scale: f64) -> Self {
+        Self {
+            center,
+            scale,
+            power: 8.,
+            iterations: 12,
+        }
+    }
+    pub fn set_power(&mut self, power: f64) {
+        self.power = power;
+    }
+    pub fn set_iterations(&mut self, iterations: usize) {
+        self.iterations = iterations;
+    }
+}
+impl Sdf for Mandelbulb_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2. || r < 1e-12 {
+                break;
+            }
+            //球坐标下把角度乘power，半径取power次方
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.) * self.power * dr + 1.;
+            let zr = r.powf(self.power);
+            z = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) * zr
+                + c;
+            r = z.length();
+        }
+        if r < 1e-12 {
+            return 0.;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+}
+
+pub struct Union_Sdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+}
+impl Union_Sdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+impl Sdf for Union_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        self.a.distance(p).min(self.b.distance(p))
+    }
+}
+
+pub struct Intersect_Sdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+}
+impl Intersect_Sdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+impl Sdf for Intersect_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+}
+
+//a减去b
+pub struct Subtract_Sdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+}
+impl Subtract_Sdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+impl Sdf for Subtract_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+}
+
+//平滑的并集，k是两个形状融在一起的那一圈有多宽
+pub struct SmoothUnion_Sdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub k: f64,
+}
+impl SmoothUnion_Sdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, k: f64) -> Self {
+        Self { a, b, k }
+    }
+}
+impl Sdf for SmoothUnion_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let (da, db) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (db - da) / self.k).clamp(0., 1.);
+        db * (1. - h) + da * h - self.k * h * (1. - h)
+    }
+}
+
+//把空间按period切成格子，每一格都是同一个形状；period某一维是0就不在那一维重复
+pub struct Repeat_Sdf {
+    pub input: Arc<dyn Sdf>,
+    pub period: Vec3,
+}
+impl Repeat_Sdf {
+    pub fn new(input: Arc<dyn Sdf>, period: Vec3) -> Self {
+        Self { input, period }
+    }
+}
+impl Sdf for Repeat_Sdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let mut q = p;
+        for i in 0..3 {
+            if self.period[i] > 0. {
+                q[i] = p[i] - self.period[i] * (p[i] / self.period[i]).round();
+            }
+        }
+        self.input.distance(q)
+    }
+}
+
+//用距离场表示的物体，bounds要把整个形状包住，只在盒子里面步进
+pub struct SdfObject {
+    sdf: Arc<dyn Sdf>,
+    bounds: AABB,
+    mp: Arc<dyn Material>,
+    epsilon: f64,
+    max_steps: usize,
+    step_scale: f64,
+}
+impl SdfObject {
+    pub fn new(sdf: Arc<dyn Sdf>, bounds: AABB, mp: Arc<dyn Material>) -> Self {
+        Self {
+            sdf,
+            bounds,
+            mp,
+            epsilon: 1e-4,
+            max_steps: 256,
+            step_scale: 1.,
+        }
+    }
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = epsilon;
+    }
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+    //距离估计不准（比如分形、平滑并集）的时候每步少走一点
+    pub fn set_step_scale(&mut self, step_scale: f64) {
+        self.step_scale = step_scale;
+    }
+    //光线在包围盒里的那一段
+    fn clip(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for a in 0..3 {
+            let inv_d = 1. / r.dir[a];
+            let mut t0 = (self.bounds.minimum[a] - r.orig[a]) * inv_d;
+            let mut t1 = (self.bounds.maximum[a] - r.orig[a]) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+    //中心差分求梯度，就是朝外的法向量
+    fn gradient(&self, p: Point3) -> Vec3 {
+        let h = self.epsilon;
+        let mut n = Vec3::new(0., 0., 0.);
+        for i in 0..3 {
+            let mut dp = Vec3::new(0., 0., 0.);
+            dp[i] = h;
+            n[i] = self.sdf.distance(p + dp) - self.sdf.distance(p - dp);
+        }
+        Vec3::unit_vector(n)
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t0, t1) = self.clip(&r, t_min, t_max)?;
+        let len = r.dir.length();
+        let mut t = t0;
+        //起点在里面（比如折射进去的光线）就反过来找距离变回0的地方；
+        //起点就在表面上（刚从这个面反射、折射出来）的话先走出epsilon再定是哪一边，不然会打中自己
+        let mut side = 0.;
+        for _ in 0..self.max_steps {
+            let dist = self.sdf.distance(r.at(t));
+            if side == 0. {
+                if dist.abs() < self.epsilon {
+                    t += self.epsilon / len;
+                    if t > t1 {
+                        return None;
+                    }
+                    continue;
+                }
+                side = dist.signum();
+            }
+            let d = side * dist;
+            if d < self.epsilon {
+                let p = r.at(t);
+                let outward_normal = self.gradient(p);
+                //uv按包围盒中心的球面坐标取
+                let dir = Vec3::unit_vector(p - (self.bounds.minimum + self.bounds.maximum) / 2.);
+                let u = (f64::atan2(-dir.z, dir.x) + PI) / (2. * PI);
+                let v = (-dir.y).clamp(-1., 1.).acos() / PI;
+                let mut rec = HitRecord::new(p, outward_normal, t, u, v, true, self.mp.clone());
+                rec.set_face_normal(r, outward_normal);
+                return Some(rec);
+            }
+            t += self.step_scale * d / len;
+            if t > t1 {
+                return None;
+            }
+        }
+        None
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::gray;
+
+    //z=3处半径1的球
+    fn ball() -> SdfObject {
+        SdfObject::new(
+            Arc::new(Sphere_Sdf::new(Point3::new(0., 0., 3.), 1.)),
+            AABB::new(Point3::new(-1.1, -1.1, 1.9), Point3::new(1.1, 1.1, 4.1)),
+            gray(),
+        )
+    }
+
+    //方向不是单位向量，t要按长度换算
+    fn ray(z: f64, dz: f64) -> Ray {
+        Ray::new(Point3::new(0., 0., z), Vec3::new(0., 0., dz), 0.)
+    }
+
+    #[test]
+    fn front_and_back() {
+        let s = ball();
+        let rec = s.hit(ray(0., 2.), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.).abs() < 1e-3, "{}", rec.t);
+        assert!(rec.front_face);
+        assert!((rec.normal.z + 1.).abs() < 1e-3);
+        //t_max在表面前面
+        assert!(s.hit(ray(0., 2.), 0.001, 0.9).is_none());
+        //t_min已经进到球里面，打中的是后面出去的那一面
+        let rec = s.hit(ray(0., 2.), 1.2, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.).abs() < 1e-3, "{}", rec.t);
+        assert!(!rec.front_face);
+        assert!((rec.normal.z + 1.).abs() < 1e-3);
+        //从旁边过去
+        let miss = Ray::new(Point3::new(1.05, 0., 0.), Vec3::new(0., 0., 1.), 0.);
+        assert!(s.hit(miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn starting_on_surface() {
+        let s = ball();
+        //从前面那一点反射出去，不能打中自己
+        assert!(s.hit(ray(2., -1.), 0.001, f64::INFINITY).is_none());
+        //折射进去，要一直走到后面那一面
+        let rec = s.hit(ray(2., 1.), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.).abs() < 1e-3, "{}", rec.t);
+        assert!(!rec.front_face);
+    }
+}
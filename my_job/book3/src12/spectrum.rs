@@ -0,0 +1,312 @@
+use crate::vec3::RGBColor;
+use std::ops::{Add, AddAssign, Div, Mul};
+//光谱模式：每条路径带几个波长一起走（hero wavelength），RGB按Smits的方法升成光谱，
+//最后用CIE配色函数转回sRGB
+
+pub const SPECTRAL_SAMPLES: usize = 4;
+pub const LAMBDA_MIN: f64 = 380.;
+pub const LAMBDA_MAX: f64 = 720.;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SampledSpectrum {
+    pub v: [f64; SPECTRAL_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(c: f64) -> Self {
+        Self {
+            v: [c; SPECTRAL_SAMPLES],
+        }
+    }
+    //RGB在这几个波长上的值
+    pub fn from_rgb(c: RGBColor, lambdas: &SampledWavelengths) -> Self {
+        let mut s = SampledSpectrum::new(0.);
+        for i in 0..SPECTRAL_SAMPLES {
+            s.v[i] = rgb_to_spectrum(c, lambdas.lambda[i]);
+        }
+        s
+    }
+    pub fn exp(&self) -> Self {
+        let mut s = *self;
+        for x in s.v.iter_mut() {
+            *x = x.exp();
+        }
+        s
+    }
+    pub fn is_black(&self) -> bool {
+        self.v.iter().all(|x| *x == 0.)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        let mut s = self;
+        for i in 0..SPECTRAL_SAMPLES {
+            s.v[i] += other.v[i];
+        }
+        s
+    }
+}
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+impl Mul for SampledSpectrum {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        let mut s = self;
+        for i in 0..SPECTRAL_SAMPLES {
+            s.v[i] *= other.v[i];
+        }
+        s
+    }
+}
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+    fn mul(self, t: f64) -> Self {
+        let mut s = self;
+        for i in 0..SPECTRAL_SAMPLES {
+            s.v[i] *= t;
+        }
+        s
+    }
+}
+impl Div<f64> for SampledSpectrum {
+    type Output = Self;
+    fn div(self, t: f64) -> Self {
+        self * (1. / t)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; SPECTRAL_SAMPLES], //lambda[0]是hero波长，nm
+    pub pdf: [f64; SPECTRAL_SAMPLES],
+}
+
+impl SampledWavelengths {
+    //u在[0,1)，其他波长在可见光范围里等间隔地绕一圈
+    pub fn sample_visible(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.; SPECTRAL_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let x = (u + i as f64 / SPECTRAL_SAMPLES as f64).fract();
+            *l = LAMBDA_MIN + x * range;
+        }
+        Self {
+            lambda,
+            pdf: [1. / range; SPECTRAL_SAMPLES],
+        }
+    }
+    //光线上只存了hero波长，其他几个按sample_visible的规则推回来
+    pub fn from_hero(lambda: f64) -> Self {
+        Self::sample_visible((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN))
+    }
+    //折射率随波长变的时候各个波长走的方向不一样，只留hero波长
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|p| *p == 0.)
+    }
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for p in self.pdf[1..].iter_mut() {
+            *p = 0.;
+        }
+        self.pdf[0] /= SPECTRAL_SAMPLES as f64;
+    }
+    //几个波长的估计平均一下转成线性sRGB
+    pub fn to_rgb(&self, s: SampledSpectrum) -> RGBColor {
+        let mut c = RGBColor::new(0., 0., 0.);
+        for i in 0..SPECTRAL_SAMPLES {
+            if self.pdf[i] == 0. {
+                continue;
+            }
+            let (x, y, z) = cie_xyz(self.lambda[i]);
+            c += xyz_to_rgb(x, y, z) * (s.v[i] / self.pdf[i]);
+        }
+        c /= SPECTRAL_SAMPLES as f64;
+        RGBColor::new(c.x / WHITE_RGB[0], c.y / WHITE_RGB[1], c.z / WHITE_RGB[2])
+    }
+}
+
+//Wyman等人的多段高斯拟合的CIE 1931配色函数
+fn piecewise_gaussian(x: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+    (-0.5 * t * t).exp()
+}
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> RGBColor {
+    RGBColor::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+//黑体辐射的颜色，亮度归一化成1，色温用开尔文
+pub fn blackbody_rgb(kelvin: f64) -> RGBColor {
+    let (c, h, kb) = (299792458., 6.62606957e-34, 1.3806488e-23);
+    let mut sum = RGBColor::new(0., 0., 0.);
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let l = lambda * 1e-9;
+        let planck = 2. * h * c * c / (l.powi(5) * ((h * c / (l * kb * kelvin)).exp() - 1.));
+        let (x, y, z) = cie_xyz(lambda);
+        sum += xyz_to_rgb(x, y, z) * planck;
+        lambda += 5.;
+    }
+    let rgb = RGBColor::new(
+        (sum.x / WHITE_RGB[0]).max(0.),
+        (sum.y / WHITE_RGB[1]).max(0.),
+        (sum.z / WHITE_RGB[2]).max(0.),
+    );
+    rgb / (0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z)
+}
+//常数1的光谱在380~720nm上积分再转成RGB的结果，除掉之后白色还是白色
+const WHITE_RGB: [f64; 3] = [128.359, 101.528, 97.066];
+
+//Smits 1999，380~720nm分成10段
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+//在每段中心之间线性插值
+fn smits_value(table: &[f64; 10], lambda: f64) -> f64 {
+    let width = (LAMBDA_MAX - LAMBDA_MIN) / 10.;
+    let x = ((lambda - LAMBDA_MIN) / width - 0.5).clamp(0., 9.);
+    let i = (x.floor() as usize).min(8);
+    let t = x - i as f64;
+    table[i] * (1. - t) + table[i + 1] * t
+}
+
+pub fn rgb_to_spectrum(c: RGBColor, lambda: f64) -> f64 {
+    let (r, g, b) = (c.x, c.y, c.z);
+    let s = |table: &[f64; 10]| smits_value(table, lambda);
+    if r <= g && r <= b {
+        r * s(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+            } else {
+                (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * s(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+            } else {
+                (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+            }
+    } else {
+        b * s(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+            } else {
+                (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //hero波长在[0,1)上等间隔取一遍，RGB升成光谱再转回来的平均
+    fn round_trip(c: RGBColor, terminate: bool) -> RGBColor {
+        let n = 2000;
+        let mut sum = RGBColor::new(0., 0., 0.);
+        for i in 0..n {
+            let mut lambdas = SampledWavelengths::sample_visible((i as f64 + 0.5) / n as f64);
+            let s = SampledSpectrum::from_rgb(c, &lambdas);
+            if terminate {
+                lambdas.terminate_secondary();
+            }
+            sum += lambdas.to_rgb(s);
+        }
+        sum / n as f64
+    }
+    fn assert_close(a: RGBColor, b: RGBColor, tol: f64) {
+        for i in 0..3 {
+            assert!(
+                (a[i] - b[i]).abs() < tol,
+                "channel {}: {} vs {}",
+                i,
+                a[i],
+                b[i]
+            );
+        }
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        for c in [
+            RGBColor::new(1., 1., 1.),
+            RGBColor::new(0.5, 0.5, 0.5),
+            RGBColor::new(0.8, 0.3, 0.1),
+            RGBColor::new(0.1, 0.6, 0.3),
+            RGBColor::new(0.2, 0.3, 0.9),
+        ]
+        .iter()
+        {
+            assert_close(round_trip(*c, false), *c, 0.05);
+        }
+    }
+
+    //只留hero波长的时候pdf补过了，平均下来颜色不变
+    #[test]
+    fn terminated_round_trip() {
+        let c = RGBColor::new(0.8, 0.3, 0.1);
+        assert_close(round_trip(c, true), round_trip(c, false), 0.01);
+    }
+
+    #[test]
+    fn hero_reconstructs_wavelengths() {
+        for i in 0..100 {
+            let lambdas = SampledWavelengths::sample_visible(i as f64 / 100.);
+            let again = SampledWavelengths::from_hero(lambdas.lambda[0]);
+            for k in 0..SPECTRAL_SAMPLES {
+                assert!((lambdas.lambda[k] - again.lambda[k]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn blackbody_has_unit_luminance() {
+        for k in [2700., 5000., 6500., 10000.].iter() {
+            let c = blackbody_rgb(*k);
+            let y = 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z;
+            assert!((y - 1.).abs() < 1e-9);
+        }
+        //色温低的偏红，高的偏蓝
+        assert!(blackbody_rgb(2700.).x > blackbody_rgb(2700.).z);
+        assert!(blackbody_rgb(10000.).z > blackbody_rgb(10000.).x);
+    }
+}
@@ -1,10 +1,12 @@
 use super::aabb::AABB;
+use crate::material::{emitted_power, is_emissive};
 use crate::onb::Onb;
 use crate::vec3::{Point3, Vec3};
 use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
 use std::f64::consts::PI;
 use std::f64::INFINITY;
 use std::sync::Arc;
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
@@ -26,7 +28,27 @@ impl Sphere {
         return (u, v);
     }
 }
+//p是单位球面上的点，按get_sphere_uv的参数化求dp/du、dp/dv，两极上dp/dv取不到就用个很小的数
+pub fn sphere_derivatives(p: Point3, radius: f64) -> (Vec3, Vec3) {
+    let sin_theta = (p.x * p.x + p.z * p.z).sqrt().max(1e-8);
+    let dpdu = Vec3::new(p.z, 0., -p.x) * (2. * PI * radius);
+    let dpdv = Vec3::new(-p.x * p.y / sin_theta, sin_theta, -p.z * p.y / sin_theta) * (PI * radius);
+    (dpdu, dpdv)
+}
 impl Hittable for Sphere {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mat_ptr) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mat_ptr, self.area())
+    }
+    fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
     fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
         if let Some(rec) = self.hit(Ray::new(o, v, 0.), 0.001, INFINITY) {
             let cos_theta_max =
@@ -74,6 +96,8 @@ impl Hittable for Sphere {
             );
 
             rec.set_face_normal(r, outward_normal);
+            let (dpdu, dpdv) = sphere_derivatives(outward_normal, self.radius);
+            rec.set_derivatives(dpdu, dpdv, dpdu / self.radius, dpdv / self.radius);
 
             return Some(rec);
         }
@@ -91,6 +115,7 @@ impl Hittable for Sphere {
     }
 }
 
+#[derive(Clone)]
 pub struct MovingSphere {
     pub center0: Point3,
     pub center1: Point3,
@@ -132,7 +157,52 @@ impl MovingSphere {
     }
 }
 
+impl MovingSphere {
+    //整个运动过程的包围球，光源采样不知道时间，只能对着它采
+    fn swept_sphere(&self) -> (Point3, f64) {
+        let center = (self.center0 + self.center1) / 2.;
+        let radius = self.radius + (self.center1 - self.center0).length() / 2.;
+        (center, radius)
+    }
+}
+
 impl Hittable for MovingSphere {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mat_ptr) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mat_ptr, self.area())
+    }
+    fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        let (center, radius) = self.swept_sphere();
+        let distance_squared = (center - o).length_squared();
+        if distance_squared <= radius * radius {
+            return 1. / (4. * PI);
+        }
+        let cos_theta_max = (1. - radius * radius / distance_squared).sqrt();
+        let cos_theta = Vec3::dot(Vec3::unit_vector(v), Vec3::unit_vector(center - o));
+        if cos_theta < cos_theta_max {
+            return 0.;
+        }
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let (center, radius) = self.swept_sphere();
+        let direction = center - o;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= radius * radius {
+            return Vec3::random_unit_vector();
+        }
+        let uvw = Onb::build_from_w(direction);
+        uvw.local_vec(Vec3::random_to_sphere(radius, distance_squared))
+    }
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let oc = r.orig - self.center(r.tm);
         let a = r.dir.length_squared();
@@ -166,6 +236,8 @@ impl Hittable for MovingSphere {
         );
 
         rec.set_face_normal(r, outward_normal);
+        let (dpdu, dpdv) = sphere_derivatives(outward_normal, self.radius);
+        rec.set_derivatives(dpdu, dpdv, dpdu / self.radius, dpdv / self.radius);
         return Some(rec);
     }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
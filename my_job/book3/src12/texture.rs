@@ -1,11 +1,16 @@
-use super::vec3::{Point3, RGBColor};
-use crate::perlin::Perlin;
+use super::vec3::{Point3, RGBColor, Vec3};
+use crate::hittable::HitRecord;
+use crate::perlin::{NoiseKind, Perlin};
+use crate::worley::Worley;
+use image::codecs::hdr::HdrDecoder;
 use image::math::utils::clamp;
-use image::RgbImage;
+use std::f64::consts::PI;
 use std::str;
 use std::sync::Arc;
+//纹理只有这一个入口，要的东西都在rec里：位置用物体坐标系里的p_object，物体移动、旋转的时候图案跟着走；
+//图片纹理按rec.uv_width()挑mipmap，随时间变的纹理用rec.tm，Mapped_Texture还要用法向量和别的uv
 pub trait Texture: Send + Sync {
-    fn value(&self, u: f64, v: f64, p: Point3) -> RGBColor;
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor;
 }
 //Solid_Color
 #[derive(Clone, Copy)]
@@ -22,7 +27,7 @@ impl Solid_Color {
 }
 
 impl Texture for Solid_Color {
-    fn value(&self, u: f64, v: f64, p: Point3) -> RGBColor {
+    fn evaluate(&self, _rec: &HitRecord) -> RGBColor {
         return self.color_value;
     }
 }
@@ -40,12 +45,13 @@ impl Checker_Texture {
     }
 }
 impl Texture for Checker_Texture {
-    fn value(&self, u: f64, v: f64, p: Point3) -> RGBColor {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
         let sines = (10.0 * p.x).sin() * (p.y * 10.0).sin() * (p.z * 10.0).sin();
         if sines < 0.0 {
-            return self.odd.value(u, v, p);
+            return self.odd.evaluate(rec);
         } else {
-            return self.even.value(u, v, p);
+            return self.even.evaluate(rec);
         }
     }
 }
@@ -53,20 +59,36 @@ impl Texture for Checker_Texture {
 pub struct Noise_Texture {
     pub noise: Perlin,
     pub sc: f64,
+    pub speed: f64, //不是0的话噪声在时间上也变，用四维噪声，每单位时间走speed
 }
 impl Noise_Texture {
     pub fn new(sc: f64) -> Self {
+        Self::with_seed(sc, 0)
+    }
+    pub fn with_seed(sc: f64, seed: u64) -> Self {
         Self {
-            noise: Perlin::new(),
+            noise: Perlin::with_seed(seed),
             sc: sc,
+            speed: 0.,
         }
     }
+    pub fn set_kind(&mut self, kind: NoiseKind) {
+        self.noise.set_kind(kind);
+    }
+    //四维噪声只有改进的Perlin噪声一种，speed不是0的时候set_kind设的种类不起作用
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
 }
 impl Texture for Noise_Texture {
-    fn value(&self, _u: f64, _v: f64, p: Point3) -> RGBColor {
-        return RGBColor::new(1., 1., 1.)
-            * 0.5
-            * (1.0 + (self.sc * p.z + 10.0 * self.noise.turb(p)).sin());
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let turb = if self.speed == 0. {
+            self.noise.turb(p)
+        } else {
+            self.noise.turb4(p, rec.tm * self.speed)
+        };
+        return RGBColor::new(1., 1., 1.) * 0.5 * (1.0 + (self.sc * p.z + 10.0 * turb).sin());
     }
 }
 //Image_Texture
@@ -91,41 +113,749 @@ impl Texture for Image_Texture  {
     }
 }*/
 
+#[derive(Copy, Clone)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+#[derive(Copy, Clone)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    Trilinear, //在mipmap的两层之间再插值，要知道滤波宽度
+}
+
+//一层mipmap，texel是线性的颜色，第0行是图片最上面
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<RGBColor>,
+}
+impl MipLevel {
+    //2x2取平均缩小一半，奇数的边多出来的那一列（行）并进最后一个texel里，变成3个取平均
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        //新的第i个texel盖住原来[2i, 2i+2)，最后一个一直盖到边
+        let span = |i: usize, n: usize, full: usize| {
+            let end = if i + 1 == n { full } else { 2 * i + 2 };
+            (2 * i).min(full - 1)..end
+        };
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let ys = span(y, height, self.height);
+            for x in 0..width {
+                let xs = span(x, width, self.width);
+                let mut c = RGBColor::new(0., 0., 0.);
+                for sy in ys.clone() {
+                    for sx in xs.clone() {
+                        c += self.texels[sy * self.width + sx];
+                    }
+                }
+                texels.push(c / (ys.len() * xs.len()) as f64);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 pub struct Image_Texture {
     pub width: i32,
     pub height: i32,
-    pub bytes_per_scanline: i32,
-    pub img: RgbImage,
+    levels: Vec<MipLevel>,
+    pub wrap: WrapMode,
+    pub filter: TextureFilter,
+    pub scale: (f64, f64), //uv先乘scale再加offset
+    pub offset: (f64, f64),
 }
 
 impl Image_Texture {
+    //颜色贴图，8位的图按sRGB解码成线性的，.hdr本来就是线性的
     pub fn new(filename: &str) -> Self {
+        Self::load(filename, true)
+    }
+    //法线、粗糙度这类数据贴图，不做sRGB解码
+    pub fn new_linear(filename: &str) -> Self {
+        Self::load(filename, false)
+    }
+    fn load(filename: &str, srgb: bool) -> Self {
+        let level = if filename.to_lowercase().ends_with(".hdr") {
+            let file = std::io::BufReader::new(std::fs::File::open(filename).expect("failed"));
+            let decoder = HdrDecoder::new(file).expect("failed");
+            let meta = decoder.metadata();
+            let pixels = decoder.read_image_hdr().expect("failed");
+            MipLevel {
+                width: meta.width as usize,
+                height: meta.height as usize,
+                texels: pixels
+                    .iter()
+                    .map(|p| RGBColor::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                    .collect(),
+            }
+        } else {
+            let img = image::open(filename).expect("failed").to_rgb8();
+            let decode = |c: u8| {
+                let c = c as f64 / 255.;
+                if srgb {
+                    srgb_to_linear(c)
+                } else {
+                    c
+                }
+            };
+            MipLevel {
+                width: img.width() as usize,
+                height: img.height() as usize,
+                texels: img
+                    .pixels()
+                    .map(|p| RGBColor::new(decode(p[0]), decode(p[1]), decode(p[2])))
+                    .collect(),
+            }
+        };
+        Self::from_level(level)
+    }
+    //从第0层一直缩到1x1
+    fn from_level(level: MipLevel) -> Self {
+        let (width, height) = (level.width as i32, level.height as i32);
+        let mut levels = vec![level];
+        while {
+            let last = levels.last().unwrap();
+            last.width > 1 || last.height > 1
+        } {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
         Self {
-            width: 0,
-            height: 0,
-            bytes_per_scanline: 0,
-            img: image::open(filename).expect("failed").to_rgb8(),
+            width,
+            height,
+            levels,
+            wrap: WrapMode::Repeat,
+            filter: TextureFilter::Bilinear,
+            scale: (1., 1.),
+            offset: (0., 0.),
+        }
+    }
+    fn wrap_index(&self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self.wrap {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+            WrapMode::Clamp => i.clamp(0, n - 1),
+        };
+        i as usize
+    }
+    fn texel(&self, level: usize, x: i64, y: i64) -> RGBColor {
+        let l = &self.levels[level];
+        let x = self.wrap_index(x, l.width);
+        let y = self.wrap_index(y, l.height);
+        l.texels[y * l.width + x]
+    }
+    //s、t是以texel为单位的坐标，t从图片上方往下
+    fn bilinear(&self, level: usize, s: f64, t: f64) -> RGBColor {
+        let (s, t) = (s - 0.5, t - 0.5);
+        let (x0, y0) = (s.floor(), t.floor());
+        let (fx, fy) = (s - x0, t - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        self.texel(level, x0, y0) * ((1. - fx) * (1. - fy))
+            + self.texel(level, x0 + 1, y0) * (fx * (1. - fy))
+            + self.texel(level, x0, y0 + 1) * ((1. - fx) * fy)
+            + self.texel(level, x0 + 1, y0 + 1) * (fx * fy)
+    }
+    fn lookup(&self, level: usize, u: f64, v: f64, bilinear: bool) -> RGBColor {
+        let l = &self.levels[level];
+        let s = u * l.width as f64;
+        let t = (1. - v) * l.height as f64;
+        if bilinear {
+            self.bilinear(level, s, t)
+        } else {
+            self.texel(level, s.floor() as i64, t.floor() as i64)
         }
     }
 }
 impl Texture for Image_Texture {
-    fn value(&self, u: f64, v: f64, p: Point3) -> RGBColor {
-        let u = f64::clamp(u, 0.0, 1.0);
-        let v = 1.0 - f64::clamp(v, 0.0, 1.0);
-        let mut i = (u * ((self.img.width()) as f64)) as i32;
-        let mut j = (v * ((self.img.height()) as f64)) as i32;
-        if i >= self.img.width() as i32 {
-            i = self.img.width() as i32 - 1;
-        }
-        if j >= self.img.height() as i32 {
-            j = self.img.height() as i32 - 1;
-        }
-        let color_scale = 1.0 / 255.0;
-        let pixel = self.img.get_pixel(i as u32, j as u32);
-        return RGBColor::new(
-            color_scale * (pixel[0] as f64),
-            color_scale * (pixel[1] as f64),
-            color_scale * (pixel[2] as f64),
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let u = rec.u * self.scale.0 + self.offset.0;
+        let v = rec.v * self.scale.1 + self.offset.1;
+        let width = rec.uv_width();
+        match self.filter {
+            TextureFilter::Nearest => self.lookup(0, u, v, false),
+            TextureFilter::Bilinear => self.lookup(0, u, v, true),
+            TextureFilter::Trilinear => {
+                //滤波宽度换算成uv缩放之后的texel数，取对数就是mipmap的层
+                let texels = width
+                    * self.scale.0.abs().max(self.scale.1.abs())
+                    * self.width.max(self.height) as f64;
+                let top = (self.levels.len() - 1) as f64;
+                let level = if texels > 1. {
+                    texels.log2().min(top)
+                } else {
+                    0.
+                };
+                let l0 = level.floor();
+                let f = level - l0;
+                let c0 = self.lookup(l0 as usize, u, v, true);
+                if f <= 0. {
+                    return c0;
+                }
+                c0 * (1. - f) + self.lookup(l0 as usize + 1, u, v, true) * f
+            }
+        }
+    }
+}
+
+//三个通道的平均，把颜色纹理当标量用
+pub fn average(c: RGBColor) -> f64 {
+    (c.x + c.y + c.z) / 3.
+}
+
+//颜色渐变：t在几个色标之间线性插值，两头外面取端点的颜色
+#[derive(Clone)]
+pub struct ColorRamp {
+    pub stops: Vec<(f64, RGBColor)>,
+}
+impl ColorRamp {
+    //色标按位置排好
+    pub fn new(mut stops: Vec<(f64, RGBColor)>) -> Self {
+        if stops.is_empty() {
+            panic!("color ramp needs at least one stop");
+        }
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Self { stops }
+    }
+    pub fn two(a: RGBColor, b: RGBColor) -> Self {
+        Self::new(vec![(0., a), (1., b)])
+    }
+    pub fn eval(&self, t: f64) -> RGBColor {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for w in self.stops.windows(2) {
+            let (t0, c0) = w[0];
+            let (t1, c1) = w[1];
+            if t <= t1 {
+                let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1. };
+                return c0 * (1. - f) + c1 * f;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+//Worley_Texture
+#[derive(Copy, Clone)]
+pub enum WorleyFeature {
+    F1,        //到最近特征点的距离，一个个圆斑
+    F2,        //到第二近的
+    F2MinusF1, //两个之差，格子的边界是黑的，像细胞、石板路
+}
+pub struct Worley_Texture {
+    pub noise: Worley,
+    pub sc: f64,
+    pub feature: WorleyFeature,
+}
+impl Worley_Texture {
+    pub fn new(sc: f64, feature: WorleyFeature) -> Self {
+        Self::with_seed(sc, feature, 0)
+    }
+    pub fn with_seed(sc: f64, feature: WorleyFeature, seed: u64) -> Self {
+        Self {
+            noise: Worley::with_seed(seed),
+            sc,
+            feature,
+        }
+    }
+}
+impl Texture for Worley_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let (f1, f2) = self.noise.noise(p * self.sc);
+        let d = match self.feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::F2MinusF1 => f2 - f1,
+        };
+        RGBColor::new(1., 1., 1.) * d.clamp(0., 1.)
+    }
+}
+
+//Fbm_Texture，灰度在0.5附近上下
+pub struct Fbm_Texture {
+    pub noise: Perlin,
+    pub sc: f64,
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+impl Fbm_Texture {
+    pub fn new(sc: f64, octaves: u32) -> Self {
+        Self::with_seed(sc, octaves, 0)
+    }
+    pub fn with_seed(sc: f64, octaves: u32, seed: u64) -> Self {
+        Self {
+            noise: Perlin::with_seed(seed),
+            sc,
+            octaves,
+            lacunarity: 2.,
+            gain: 0.5,
+        }
+    }
+}
+impl Texture for Fbm_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let n = self
+            .noise
+            .fbm(p * self.sc, self.octaves, self.lacunarity, self.gain);
+        RGBColor::new(1., 1., 1.) * (0.5 + 0.5 * n).clamp(0., 1.)
+    }
+}
+
+//Ridged_Texture，山脊、闪电一类的纹路
+pub struct Ridged_Texture {
+    pub noise: Perlin,
+    pub sc: f64,
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+    pub offset: f64,
+}
+impl Ridged_Texture {
+    pub fn new(sc: f64, octaves: u32) -> Self {
+        Self::with_seed(sc, octaves, 0)
+    }
+    pub fn with_seed(sc: f64, octaves: u32, seed: u64) -> Self {
+        Self {
+            noise: Perlin::with_seed(seed),
+            sc,
+            octaves,
+            lacunarity: 2.,
+            gain: 0.5,
+            offset: 1.,
+        }
+    }
+}
+impl Texture for Ridged_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let n = self.noise.ridged(
+            p * self.sc,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+            self.offset,
+        );
+        RGBColor::new(1., 1., 1.) * n.clamp(0., 1.)
+    }
+}
+
+//Wood_Texture：绕y轴的年轮，用噪声把圈扰乱一点
+pub struct Wood_Texture {
+    pub noise: Perlin,
+    pub rings: f64,      //每单位半径几圈
+    pub distortion: f64, //噪声扰动的强度，单位是圈
+    pub ramp: ColorRamp,
+}
+impl Wood_Texture {
+    pub fn new(light: RGBColor, dark: RGBColor, rings: f64) -> Self {
+        Self::new_ramp(ColorRamp::two(light, dark), rings)
+    }
+    pub fn new_ramp(ramp: ColorRamp, rings: f64) -> Self {
+        Self::with_seed(ramp, rings, 0)
+    }
+    //一屋子家具的木纹不想一模一样就每件换个种子
+    pub fn with_seed(ramp: ColorRamp, rings: f64, seed: u64) -> Self {
+        Self {
+            noise: Perlin::with_seed(seed),
+            rings,
+            distortion: 0.5,
+            ramp,
+        }
+    }
+}
+impl Texture for Wood_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let r =
+            (p.x * p.x + p.z * p.z).sqrt() * self.rings + self.distortion * self.noise.turb(p * 2.);
+        //锯齿波换成中间深两边浅，年轮之间不会有硬边
+        let t = r - r.floor();
+        self.ramp.eval((t * 2. - 1.).abs())
+    }
+}
+
+//Marble_Texture：和Noise_Texture一样的sin加湍流，结果过一个颜色渐变
+pub struct Marble_Texture {
+    pub noise: Perlin,
+    pub sc: f64,
+    pub ramp: ColorRamp,
+}
+impl Marble_Texture {
+    pub fn new(ramp: ColorRamp, sc: f64) -> Self {
+        Self::with_seed(ramp, sc, 0)
+    }
+    pub fn with_seed(ramp: ColorRamp, sc: f64, seed: u64) -> Self {
+        Self {
+            noise: Perlin::with_seed(seed),
+            sc,
+            ramp,
+        }
+    }
+}
+impl Texture for Marble_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let t = 0.5 * (1.0 + (self.sc * p.z + 10.0 * self.noise.turb(p)).sin());
+        self.ramp.eval(t)
+    }
+}
+
+//Brick_Texture：uv上的砖墙，隔一行错开offset；offset是0就是方砖（瓷砖）
+pub struct Brick_Texture {
+    pub brick: Arc<dyn Texture>,
+    pub mortar: Arc<dyn Texture>,
+    pub rows: f64,         //v方向几行
+    pub columns: f64,      //u方向几列
+    pub mortar_width: f64, //灰缝占一块砖的比例
+    pub offset: f64,       //每隔一行错开多少块
+}
+impl Brick_Texture {
+    pub fn new(brick: Arc<dyn Texture>, mortar: Arc<dyn Texture>) -> Self {
+        Self {
+            brick,
+            mortar,
+            rows: 8.,
+            columns: 4.,
+            mortar_width: 0.05,
+            offset: 0.5,
+        }
+    }
+    //n×n的方砖
+    pub fn tiles(tile: Arc<dyn Texture>, grout: Arc<dyn Texture>, n: f64) -> Self {
+        Self {
+            brick: tile,
+            mortar: grout,
+            rows: n,
+            columns: n,
+            mortar_width: 0.05,
+            offset: 0.,
+        }
+    }
+    fn is_mortar(&self, u: f64, v: f64) -> bool {
+        let y = v * self.rows;
+        let row = y.floor();
+        let mut x = u * self.columns;
+        if (row as i64).rem_euclid(2) == 1 {
+            x += self.offset;
+        }
+        let fx = x - x.floor();
+        let fy = y - row;
+        let half = self.mortar_width / 2.;
+        fx < half || fx > 1. - half || fy < half || fy > 1. - half
+    }
+}
+impl Texture for Brick_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        if self.is_mortar(rec.u, rec.v) {
+            self.mortar.evaluate(rec)
+        } else {
+            self.brick.evaluate(rec)
+        }
+    }
+}
+
+//Gradient_Texture：按uv或者位置算一个0到1的t，再过颜色渐变
+#[derive(Copy, Clone)]
+pub enum Gradient {
+    U,
+    V,
+    Linear(Point3, Point3), //从第一个点到第二个点
+    Radial(Point3, f64),    //球心和半径
+}
+pub struct Gradient_Texture {
+    pub kind: Gradient,
+    pub ramp: ColorRamp,
+}
+impl Gradient_Texture {
+    pub fn new(kind: Gradient, ramp: ColorRamp) -> Self {
+        Self { kind, ramp }
+    }
+}
+impl Texture for Gradient_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let p = rec.p_object;
+        let t = match self.kind {
+            Gradient::U => rec.u,
+            Gradient::V => rec.v,
+            Gradient::Linear(a, b) => {
+                let d = b - a;
+                Vec3::dot(p - a, d) / d.length_squared()
+            }
+            Gradient::Radial(c, r) => (p - c).length() / r,
+        };
+        self.ramp.eval(t)
+    }
+}
+
+//下面是组合纹理的节点，输入都是任意的纹理，rec原样传下去
+
+//Ramp_Texture：输入当标量，过颜色渐变
+pub struct Ramp_Texture {
+    pub input: Arc<dyn Texture>,
+    pub ramp: ColorRamp,
+}
+impl Ramp_Texture {
+    pub fn new(input: Arc<dyn Texture>, ramp: ColorRamp) -> Self {
+        Self { input, ramp }
+    }
+}
+impl Texture for Ramp_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        self.ramp.eval(average(self.input.evaluate(rec)))
+    }
+}
+
+//Mix_Texture：按factor逐通道在a、b之间插值，factor是b的比例
+pub struct Mix_Texture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+    pub factor: Arc<dyn Texture>,
+}
+impl Mix_Texture {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>, factor: Arc<dyn Texture>) -> Self {
+        Self { a, b, factor }
+    }
+}
+impl Texture for Mix_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let f = self.factor.evaluate(rec);
+        self.a.evaluate(rec) * (RGBColor::new(1., 1., 1.) - f) + self.b.evaluate(rec) * f
+    }
+}
+
+//Multiply_Texture：逐通道相乘，比如颜色乘一张AO
+pub struct Multiply_Texture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+impl Multiply_Texture {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Self { a, b }
+    }
+}
+impl Texture for Multiply_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        self.a.evaluate(rec) * self.b.evaluate(rec)
+    }
+}
+
+//Remap_Texture：逐通道把[from.0, from.1]线性映射到[to.0, to.1]，超出去的截断
+pub struct Remap_Texture {
+    pub input: Arc<dyn Texture>,
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+}
+impl Remap_Texture {
+    pub fn new(input: Arc<dyn Texture>, from: (f64, f64), to: (f64, f64)) -> Self {
+        Self { input, from, to }
+    }
+    fn remap(&self, x: f64) -> f64 {
+        let t = ((x - self.from.0) / (self.from.1 - self.from.0)).clamp(0., 1.);
+        self.to.0 + (self.to.1 - self.to.0) * t
+    }
+}
+impl Texture for Remap_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let c = self.input.evaluate(rec);
+        RGBColor::new(self.remap(c.x), self.remap(c.y), self.remap(c.z))
+    }
+}
+
+//纹理坐标怎么来
+#[derive(Copy, Clone)]
+pub enum TextureMapping {
+    Uv,                  //形状自己的(u,v)
+    UvSet(usize),        //网格上的第几套uv，0就是(u,v)
+    World,               //位置用世界坐标，物体动了图案不跟着动
+    Planar(Vec3, Vec3),  //物体坐标投影到两个轴上，轴的长度就是缩放
+    Spherical(Point3),   //绕center的经纬度，和Sphere的uv一样
+    Cylindrical(Point3), //绕过center的y轴，u是角度，v是高度
+    Triplanar(f64),      //沿x、y、z三个方向平面投影，按法向量混合，数越大过渡越硬
+}
+
+//Mapped_Texture：按mapping重新算uv（或者换成世界坐标）再交给里面的纹理
+pub struct Mapped_Texture {
+    pub input: Arc<dyn Texture>,
+    pub mapping: TextureMapping,
+}
+impl Mapped_Texture {
+    pub fn new(input: Arc<dyn Texture>, mapping: TextureMapping) -> Self {
+        Self { input, mapping }
+    }
+    //一组(u, v, 权重)，只有三平面投影是三组
+    fn project(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3,
+        n: Vec3,
+        extra: &[(f64, f64)],
+    ) -> Vec<(f64, f64, f64)> {
+        match self.mapping {
+            TextureMapping::Uv | TextureMapping::World => vec![(u, v, 1.)],
+            TextureMapping::UvSet(i) => {
+                let (u, v) = if i == 0 {
+                    (u, v)
+                } else {
+                    extra.get(i - 1).copied().unwrap_or((u, v))
+                };
+                vec![(u, v, 1.)]
+            }
+            TextureMapping::Planar(su, sv) => vec![(Vec3::dot(p, su), Vec3::dot(p, sv), 1.)],
+            TextureMapping::Spherical(center) => {
+                let d = Vec3::unit_vector(p - center);
+                let theta = (-d.y).acos();
+                let phi = (-d.z).atan2(d.x) + PI;
+                vec![(phi / (2. * PI), theta / PI, 1.)]
+            }
+            TextureMapping::Cylindrical(center) => {
+                let d = p - center;
+                let phi = (-d.z).atan2(d.x) + PI;
+                vec![(phi / (2. * PI), d.y, 1.)]
+            }
+            TextureMapping::Triplanar(sharpness) => {
+                let w = Vec3::new(
+                    n.x.abs().powf(sharpness),
+                    n.y.abs().powf(sharpness),
+                    n.z.abs().powf(sharpness),
+                );
+                let sum = w.x + w.y + w.z;
+                if sum <= 0. {
+                    return vec![(p.x, p.y, 1.)];
+                }
+                vec![
+                    (p.z, p.y, w.x / sum),
+                    (p.x, p.z, w.y / sum),
+                    (p.x, p.y, w.z / sum),
+                ]
+            }
+        }
+    }
+}
+impl Texture for Mapped_Texture {
+    fn evaluate(&self, rec: &HitRecord) -> RGBColor {
+        let mut mapped = rec.clone();
+        if let TextureMapping::World = self.mapping {
+            mapped.p_object = rec.p;
+        }
+        let mut c = RGBColor::new(0., 0., 0.);
+        for (u, v, w) in self.project(rec.u, rec.v, rec.p_object, rec.n_object, &rec.extra_uv) {
+            if w <= 0. {
+                continue;
+            }
+            mapped.u = u;
+            mapped.v = v;
+            c += self.input.evaluate(&mapped) * w;
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //一行texel，颜色的r就是下标，方便看取到的是哪一个
+    fn row(n: usize) -> Image_Texture {
+        Image_Texture::from_level(MipLevel {
+            width: n,
+            height: 1,
+            texels: (0..n).map(|i| RGBColor::new(i as f64, 0., 0.)).collect(),
+        })
+    }
+
+    fn fetch(tex: &Image_Texture, x: i64) -> f64 {
+        tex.texel(0, x, 0)[0]
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let mut tex = row(4);
+        let xs = [-5, -1, 0, 3, 4, 7, 9];
+        tex.wrap = WrapMode::Repeat;
+        let got: Vec<f64> = xs.iter().map(|&x| fetch(&tex, x)).collect();
+        assert_eq!(got, vec![3., 3., 0., 3., 0., 3., 1.]);
+        tex.wrap = WrapMode::Mirror;
+        let got: Vec<f64> = xs.iter().map(|&x| fetch(&tex, x)).collect();
+        assert_eq!(got, vec![3., 0., 0., 3., 3., 0., 1.]);
+        tex.wrap = WrapMode::Clamp;
+        let got: Vec<f64> = xs.iter().map(|&x| fetch(&tex, x)).collect();
+        assert_eq!(got, vec![0., 0., 0., 3., 3., 3., 3.]);
+    }
+
+    #[test]
+    fn lookup_wraps_uv() {
+        let mut tex = row(4);
+        //u=1.1落在第0.4个texel里
+        tex.wrap = WrapMode::Repeat;
+        assert_eq!(tex.lookup(0, 1.1, 0.5, false)[0], 0.);
+        tex.wrap = WrapMode::Clamp;
+        assert_eq!(tex.lookup(0, 1.1, 0.5, false)[0], 3.);
+        tex.wrap = WrapMode::Mirror;
+        assert_eq!(tex.lookup(0, 1.1, 0.5, false)[0], 3.);
+        //两个texel中心的正中间，双线性插值取平均
+        tex.wrap = WrapMode::Clamp;
+        let c = tex.lookup(0, 0.5, 0.5, true)[0];
+        assert!((c - 1.5).abs() < 1e-9, "{}", c);
+    }
+
+    #[test]
+    fn odd_levels_keep_every_texel() {
+        //3x1缩一次就是1x1，三个texel都要算进去
+        let tex = row(3);
+        assert_eq!(tex.levels.len(), 2);
+        assert!((tex.levels[1].texels[0][0] - 1.).abs() < 1e-9);
+        //5x3：每一层的平均值都不变
+        let level = MipLevel {
+            width: 5,
+            height: 3,
+            texels: (0..15).map(|i| RGBColor::new(i as f64, 1., 0.)).collect(),
+        };
+        let next = level.downsample();
+        assert_eq!((next.width, next.height), (2, 1));
+        //第0个盖住x在[0,2)，第1个盖住[2,5)，y全部
+        assert!(
+            (next.texels[0][0] - 5.5).abs() < 1e-9,
+            "{}",
+            next.texels[0][0]
+        );
+        assert!(
+            (next.texels[1][0] - 8.).abs() < 1e-9,
+            "{}",
+            next.texels[1][0]
         );
+        let tex = Image_Texture::from_level(level);
+        let last = tex.levels.last().unwrap();
+        assert_eq!((last.width, last.height), (1, 1));
+        assert_eq!(last.texels[0][1], 1.);
     }
 }
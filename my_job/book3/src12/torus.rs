@@ -0,0 +1,253 @@
+use super::aabb::AABB;
+use crate::hittable::area_pdf_value;
+use crate::material::{emitted_power, is_emissive};
+use crate::quadric::{Frame, LocalHit};
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+//圆环：center是中心，axis是穿过环中间的轴，major是管中心到轴的距离，minor是管的半径
+#[derive(Clone)]
+pub struct Torus {
+    pub frame: Frame,
+    pub major: f64,
+    pub minor: f64,
+    pub mp: Arc<dyn Material>,
+}
+impl Torus {
+    pub fn new(center: Point3, axis: Vec3, major: f64, minor: f64, mp: Arc<dyn Material>) -> Self {
+        Self {
+            frame: Frame::new(center, axis),
+            major,
+            minor,
+            mp,
+        }
+    }
+}
+
+//c[i]是t^i的系数
+fn eval(c: &[f64], t: f64) -> f64 {
+    c.iter().rev().fold(0., |acc, x| acc * t + x)
+}
+
+//三次方程的实根（Cardano，三个实根的时候用三角函数），c是从常数项开始的系数
+fn cubic_roots(c: [f64; 4]) -> Vec<f64> {
+    if c[3].abs() < 1e-14 {
+        //退化成二次
+        let (a, b, k) = (c[2], c[1], c[0]);
+        if a.abs() < 1e-14 {
+            return if b.abs() < 1e-14 {
+                vec![]
+            } else {
+                vec![-k / b]
+            };
+        }
+        let disc = b * b - 4. * a * k;
+        if disc < 0. {
+            return vec![];
+        }
+        let s = disc.sqrt();
+        return vec![(-b - s) / (2. * a), (-b + s) / (2. * a)];
+    }
+    let (a, b, k) = (c[2] / c[3], c[1] / c[3], c[0] / c[3]);
+    //t = x - a/3消掉二次项：x³ + px + q = 0
+    let p = b - a * a / 3.;
+    let q = 2. * a * a * a / 27. - a * b / 3. + k;
+    let shift = -a / 3.;
+    let disc = q * q / 4. + p * p * p / 27.;
+    if disc > 0. {
+        let s = disc.sqrt();
+        vec![(-q / 2. + s).cbrt() + (-q / 2. - s).cbrt() + shift]
+    } else {
+        let m = 2. * (-p / 3.).max(0.).sqrt();
+        if m == 0. {
+            return vec![shift];
+        }
+        let theta = ((3. * q / (p * m)).clamp(-1., 1.)).acos() / 3.;
+        (0..3)
+            .map(|i| m * (theta - 2. * PI * i as f64 / 3.).cos() + shift)
+            .collect()
+    }
+}
+
+//四次方程在[lo, hi]里最小的根：导数的根把区间分成单调的几段，每段里变号就二分
+fn smallest_quartic_root(c: [f64; 5], lo: f64, hi: f64) -> Option<f64> {
+    let mut stops: Vec<f64> = cubic_roots([c[1], 2. * c[2], 3. * c[3], 4. * c[4]])
+        .into_iter()
+        .filter(|t| *t > lo && *t < hi)
+        .collect();
+    stops.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    stops.insert(0, lo);
+    stops.push(hi);
+    for w in stops.windows(2) {
+        let (mut a, mut b) = (w[0], w[1]);
+        let (fa, fb) = (eval(&c, a), eval(&c, b));
+        if fa == 0. {
+            return Some(a);
+        }
+        if fa * fb > 0. {
+            continue;
+        }
+        for _ in 0..64 {
+            let m = 0.5 * (a + b);
+            if eval(&c, m) * fa > 0. {
+                a = m;
+            } else {
+                b = m;
+            }
+        }
+        return Some(0.5 * (a + b));
+    }
+    None
+}
+
+impl Hittable for Torus {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        4. * PI * PI * self.major * self.minor
+    }
+    //(|p|² + R² - r²)² = 4R²(x² + y²)，展开成t的四次方程
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (o, d) = self.frame.to_local(&r);
+        let (big, small) = (self.major, self.minor);
+        //先和外接球求交，四次方程只在这一段里找根，也顺便剔掉大部分光线
+        let bound = big + small;
+        let a = d.length_squared();
+        let half_b = Vec3::dot(o, d);
+        let disc = half_b * half_b - a * (o.length_squared() - bound * bound);
+        if disc < 0. {
+            return None;
+        }
+        let lo = ((-half_b - disc.sqrt()) / a).max(t_min);
+        let hi = ((-half_b + disc.sqrt()) / a).min(t_max);
+        if lo > hi {
+            return None;
+        }
+        let m = a;
+        let n = half_b;
+        let k = o.length_squared() + big * big - small * small;
+        let four_r2 = 4. * big * big;
+        let c = [
+            k * k - four_r2 * (o.x * o.x + o.y * o.y),
+            4. * n * k - 2. * four_r2 * (o.x * d.x + o.y * d.y),
+            4. * n * n + 2. * m * k - four_r2 * (d.x * d.x + d.y * d.y),
+            4. * m * n,
+            m * m,
+        ];
+        let t = smallest_quartic_root(c, lo, hi)?;
+        let p = o + d * t;
+        //phi绕轴，theta绕管子
+        let phi = p.y.atan2(p.x);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let theta = p.z.atan2(rho - big);
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let ring = big + small * cos_theta;
+        let hit = LocalHit {
+            t,
+            n: Vec3::new(cos_theta * cos_phi, cos_theta * sin_phi, sin_theta),
+            u: (phi + PI) / (2. * PI),
+            v: (theta + PI) / (2. * PI),
+            dpdu: Vec3::new(-ring * sin_phi, ring * cos_phi, 0.) * (2. * PI),
+            dpdv: Vec3::new(
+                -small * sin_theta * cos_phi,
+                -small * sin_theta * sin_phi,
+                small * cos_theta,
+            ) * (2. * PI),
+            dndu: Vec3::new(-cos_theta * sin_phi, cos_theta * cos_phi, 0.) * (2. * PI),
+            dndv: Vec3::new(-sin_theta * cos_phi, -sin_theta * sin_phi, cos_theta) * (2. * PI),
+        };
+        Some(self.frame.record(r, hit, self.mp.clone()))
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = self.major + self.minor;
+        Some(self.frame.bounding_box(
+            Point3::new(-r, -r, -self.minor),
+            Point3::new(r, r, self.minor),
+        ))
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    //外圈的面积大，theta按(R + r cosθ)/(R + r)的概率接受
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let theta = loop {
+            let theta = 2. * PI * rng.gen::<f64>();
+            let ring = self.major + self.minor * theta.cos();
+            if rng.gen::<f64>() * (self.major + self.minor) <= ring {
+                break theta;
+            }
+        };
+        let phi = 2. * PI * rng.gen::<f64>();
+        let ring = self.major + self.minor * theta.cos();
+        let p = Point3::new(ring * phi.cos(), ring * phi.sin(), self.minor * theta.sin());
+        self.frame.to_world(p) - o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::tests::{assert_close, check_sampling, gray};
+
+    #[test]
+    fn torus_hit() {
+        let torus = Torus::new(
+            Point3::new(0., 0., 0.),
+            Vec3::new(0., 0., 1.),
+            2.,
+            0.5,
+            gray(),
+        );
+        let r = Ray::new(Point3::new(-5., 0., 0.), Vec3::new(1., 0., 0.), 0.);
+        //沿x轴依次穿过管子的外壁、内壁、中间的洞、另一边
+        let ts = [2.5, 3.5, 6.5, 7.5];
+        let normals = [-1., 1., -1., 1.];
+        let mut t_min = 0.001;
+        for (&t, &nx) in ts.iter().zip(normals.iter()) {
+            let rec = torus.hit(r, t_min, f64::INFINITY).unwrap();
+            assert!((rec.t - t).abs() < 1e-6, "{} != {}", rec.t, t);
+            let outward = if rec.front_face {
+                rec.normal
+            } else {
+                -rec.normal
+            };
+            assert_close(outward, Vec3::new(nx, 0., 0.));
+            t_min = rec.t + 0.001;
+        }
+        assert!(torus.hit(r, t_min, f64::INFINITY).is_none());
+        assert!(torus.hit(r, 0.001, 2.4).is_none());
+        //从洞里沿轴穿过去
+        let through = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., 1.), 0.);
+        assert!(torus.hit(through, 0.001, f64::INFINITY).is_none());
+        //正上方打到管子顶
+        let top = Ray::new(Point3::new(2., 0., 3.), Vec3::new(0., 0., -1.), 0.);
+        let rec = torus.hit(top, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.5).abs() < 1e-6);
+        assert_close(rec.normal, Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn torus_pdf() {
+        let torus = Torus::new(
+            Point3::new(0., 0.3, 2.5),
+            Vec3::new(0.2, 1., 0.4),
+            1.,
+            0.5,
+            gray(),
+        );
+        check_sampling(&torus, Point3::new(0., 0., 0.));
+    }
+}
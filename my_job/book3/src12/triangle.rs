@@ -0,0 +1,369 @@
+use super::aabb::AABB;
+use crate::bvh::BvhNode;
+use crate::hittable::{area_pdf_value, EXTRA_UV_SETS};
+use crate::material::{emitted_power, is_emissive};
+use crate::onb::Onb;
+use crate::texture::Texture;
+use crate::vec3::{Point3, Vec3};
+use crate::{hittable::HitRecord, hittable::Hittable, material::Material, ray::Ray};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub p: [Point3; 3],
+    pub n: Option<[Vec3; 3]>, //顶点法向量，没有就用面法向量
+    pub uv: [(f64, f64); 3],
+    pub extra_uv: [[(f64, f64); 3]; EXTRA_UV_SETS], //网格上别的几套uv，没有就是0
+    pub mp: Arc<dyn Material>,
+}
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, mp: Arc<dyn Material>) -> Self {
+        Self {
+            p: [p0, p1, p2],
+            n: None,
+            uv: [(0., 0.), (1., 0.), (0., 1.)],
+            extra_uv: [[(0., 0.); 3]; EXTRA_UV_SETS],
+            mp,
+        }
+    }
+    //由三个顶点的uv解出dp/du、dp/dv（顶点法向量也一样解），uv退化的时候随便取两个切向
+    fn derivatives(&self) -> (Vec3, Vec3, Vec3, Vec3) {
+        let du02 = self.uv[0].0 - self.uv[2].0;
+        let dv02 = self.uv[0].1 - self.uv[2].1;
+        let du12 = self.uv[1].0 - self.uv[2].0;
+        let dv12 = self.uv[1].1 - self.uv[2].1;
+        let det = du02 * dv12 - dv02 * du12;
+        let zero = Vec3::new(0., 0., 0.);
+        if det.abs() < 1e-12 {
+            let n = Vec3::cross(self.p[1] - self.p[0], self.p[2] - self.p[0]);
+            let uvw = Onb::build_from_w(n);
+            return (uvw.u(), uvw.v(), zero, zero);
+        }
+        let solve = |a: [Vec3; 3]| {
+            let d02 = a[0] - a[2];
+            let d12 = a[1] - a[2];
+            (
+                (d02 * dv12 - d12 * dv02) / det,
+                (d12 * du02 - d02 * du12) / det,
+            )
+        };
+        let (dpdu, dpdv) = solve(self.p);
+        let (dndu, dndv) = match self.n {
+            Some(n) => solve(n),
+            None => (zero, zero),
+        };
+        (dpdu, dpdv, dndu, dndv)
+    }
+}
+impl Hittable for Triangle {
+    fn normal_bounds(&self) -> (Vec3, f64) {
+        let n = Vec3::cross(self.p[1] - self.p[0], self.p[2] - self.p[0]);
+        (Vec3::unit_vector(n), 1.)
+    }
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area())
+    }
+    fn area(&self) -> f64 {
+        Vec3::cross(self.p[1] - self.p[0], self.p[2] - self.p[0]).length() / 2.
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        //Möller–Trumbore
+        let e1 = self.p[1] - self.p[0];
+        let e2 = self.p[2] - self.p[0];
+        let pvec = Vec3::cross(r.dir, e2);
+        let det = Vec3::dot(e1, pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+        let tvec = r.orig - self.p[0];
+        let b1 = Vec3::dot(tvec, pvec) * inv_det;
+        if b1 < 0. || b1 > 1. {
+            return None;
+        }
+        let qvec = Vec3::cross(tvec, e1);
+        let b2 = Vec3::dot(r.dir, qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return None;
+        }
+        let t = Vec3::dot(e2, qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let b0 = 1. - b1 - b2;
+        let u = b0 * self.uv[0].0 + b1 * self.uv[1].0 + b2 * self.uv[2].0;
+        let v = b0 * self.uv[0].1 + b1 * self.uv[1].1 + b2 * self.uv[2].1;
+        //几何法向量用面法向量，顶点法向量插值出来的只用来着色，免得光从面底下漏过来
+        let mut outward_normal = Vec3::unit_vector(Vec3::cross(e1, e2));
+        let shading_normal = self
+            .n
+            .map(|n| Vec3::unit_vector(n[0] * b0 + n[1] * b1 + n[2] * b2));
+        if let Some(ns) = shading_normal {
+            //顶点顺序和顶点法向量对不上的时候以顶点法向量为准
+            if Vec3::dot(ns, outward_normal) < 0. {
+                outward_normal = -outward_normal;
+            }
+        }
+        let mut rec = HitRecord::new(r.at(t), outward_normal, t, u, v, true, self.mp.clone());
+        rec.set_face_normal(r, outward_normal);
+        if let Some(ns) = shading_normal {
+            rec.set_shading_normal(ns);
+        }
+        for (dst, uv) in rec.extra_uv.iter_mut().zip(self.extra_uv.iter()) {
+            *dst = (
+                b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+                b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+            );
+        }
+        let (dpdu, dpdv, dndu, dndv) = self.derivatives();
+        rec.set_derivatives(dpdu, dpdv, dndu, dndv);
+        Some(rec)
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let mut minimum = self.p[0];
+        let mut maximum = self.p[0];
+        for q in self.p.iter() {
+            for c in 0..3 {
+                minimum[c] = minimum[c].min(q[c] - 0.0001);
+                maximum[c] = maximum[c].max(q[c] + 0.0001);
+            }
+        }
+        Some(AABB::new(minimum, maximum))
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area())
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let su = r1.sqrt();
+        let random_point =
+            self.p[0] * (1. - su) + self.p[1] * (su * (1. - r2)) + self.p[2] * (su * r2);
+        random_point - o
+    }
+}
+
+//三角网格，求交走BVH，采样按面积选三角形
+#[derive(Clone)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub uv_sets: Vec<Vec<(f64, f64)>>, //除了uvs以外的几套uv，纹理用Mapped_Texture的UvSet挑
+    pub indices: Vec<[usize; 3]>,
+    pub mp: Arc<dyn Material>,
+    triangles: Vec<Arc<Triangle>>,
+    bvh: BvhNode,
+    area_cdf: Vec<f64>,
+    area: f64,
+}
+impl Mesh {
+    //normals和uvs可以为空，非空时要和positions一样长
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mp: Arc<dyn Material>,
+    ) -> Self {
+        Self::new_uv_sets(positions, normals, uvs, Vec::new(), indices, mp)
+    }
+    //uv_sets里每一套都要和positions一样长，最多EXTRA_UV_SETS套
+    pub fn new_uv_sets(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        uv_sets: Vec<Vec<(f64, f64)>>,
+        indices: Vec<[usize; 3]>,
+        mp: Arc<dyn Material>,
+    ) -> Self {
+        if indices.is_empty() {
+            panic!("Mesh::new: a mesh needs at least one triangle");
+        }
+        if uv_sets.len() > EXTRA_UV_SETS {
+            panic!("a mesh can have at most {} extra uv sets", EXTRA_UV_SETS);
+        }
+        let mut triangles = Vec::new();
+        let mut area_cdf = Vec::new();
+        let mut area = 0.;
+        for idx in indices.iter() {
+            let mut tri = Triangle::new(
+                positions[idx[0]],
+                positions[idx[1]],
+                positions[idx[2]],
+                mp.clone(),
+            );
+            if !normals.is_empty() {
+                tri.n = Some([normals[idx[0]], normals[idx[1]], normals[idx[2]]]);
+            }
+            if !uvs.is_empty() {
+                tri.uv = [uvs[idx[0]], uvs[idx[1]], uvs[idx[2]]];
+            }
+            for (dst, set) in tri.extra_uv.iter_mut().zip(uv_sets.iter()) {
+                *dst = [set[idx[0]], set[idx[1]], set[idx[2]]];
+            }
+            area += tri.area();
+            area_cdf.push(area);
+            triangles.push(Arc::new(tri));
+        }
+        let objects: Vec<Arc<dyn Hittable>> = triangles
+            .iter()
+            .map(|t| t.clone() as Arc<dyn Hittable>)
+            .collect();
+        let bvh = BvhNode::new(objects, 0., 1.);
+        Self {
+            positions,
+            normals,
+            uvs,
+            uv_sets,
+            indices,
+            mp,
+            triangles,
+            bvh,
+            area_cdf,
+            area,
+        }
+    }
+    //每个三角形按边的中点拆成四个，共用的边只加一个点，顶点法向量和uv跟着插值
+    pub fn subdivide(&self) -> Self {
+        let mut positions = self.positions.clone();
+        let mut normals = self.normals.clone();
+        let mut uvs = self.uvs.clone();
+        let mut uv_sets = self.uv_sets.clone();
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            let key = (a.min(b), a.max(b));
+            *midpoints.entry(key).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]) * 0.5);
+                if !normals.is_empty() {
+                    normals.push(Vec3::unit_vector(normals[a] + normals[b]));
+                }
+                if !uvs.is_empty() {
+                    uvs.push(((uvs[a].0 + uvs[b].0) * 0.5, (uvs[a].1 + uvs[b].1) * 0.5));
+                }
+                for set in uv_sets.iter_mut() {
+                    set.push(((set[a].0 + set[b].0) * 0.5, (set[a].1 + set[b].1) * 0.5));
+                }
+                positions.len() - 1
+            })
+        };
+        let mut indices = Vec::new();
+        for &[a, b, c] in self.indices.iter() {
+            let ab = midpoint(a, b);
+            let bc = midpoint(b, c);
+            let ca = midpoint(c, a);
+            indices.push([a, ab, ca]);
+            indices.push([ab, b, bc]);
+            indices.push([ca, bc, c]);
+            indices.push([ab, bc, ca]);
+        }
+        Self::new_uv_sets(positions, normals, uvs, uv_sets, indices, self.mp.clone())
+    }
+    //置换贴图：先细分levels次，再把每个顶点沿法向量推出去 高度×scale，最后重新算顶点法向量
+    //高度是纹理三个通道的平均，没有uv的网格只能用按位置算的纹理
+    pub fn displace(&self, height: &dyn Texture, scale: f64, levels: u32) -> Self {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = mesh.subdivide();
+        }
+        let normals = if mesh.normals.is_empty() {
+            vertex_normals(&mesh.positions, &mesh.indices)
+        } else {
+            mesh.normals.clone()
+        };
+        let positions: Vec<Point3> = mesh
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let (u, v) = mesh.uvs.get(i).copied().unwrap_or((0., 0.));
+                let mut rec = HitRecord::new(*p, normals[i], 0., u, v, true, mesh.mp.clone());
+                for (dst, set) in rec.extra_uv.iter_mut().zip(mesh.uv_sets.iter()) {
+                    *dst = set[i];
+                }
+                let h = height.evaluate(&rec);
+                *p + normals[i] * ((h.x + h.y + h.z) / 3. * scale)
+            })
+            .collect();
+        let normals = vertex_normals(&positions, &mesh.indices);
+        Self::new_uv_sets(
+            positions,
+            normals,
+            mesh.uvs,
+            mesh.uv_sets,
+            mesh.indices,
+            mesh.mp,
+        )
+    }
+}
+//按面积加权平均相邻三角形的面法向量
+pub fn vertex_normals(positions: &[Point3], indices: &[[usize; 3]]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::new(0., 0., 0.); positions.len()];
+    for idx in indices.iter() {
+        //叉乘的长度是面积的两倍，不用再乘面积
+        let n = Vec3::cross(
+            positions[idx[1]] - positions[idx[0]],
+            positions[idx[2]] - positions[idx[0]],
+        );
+        for &i in idx.iter() {
+            normals[i] += n;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|n| {
+            if n.length_squared() > 0. {
+                Vec3::unit_vector(n)
+            } else {
+                Vec3::new(0., 1., 0.)
+            }
+        })
+        .collect()
+}
+impl Hittable for Mesh {
+    fn lights(&self) -> Vec<Arc<dyn Hittable>> {
+        if is_emissive(&self.mp) {
+            vec![Arc::new(self.clone())]
+        } else {
+            Vec::new()
+        }
+    }
+    fn power(&self) -> f64 {
+        emitted_power(&self.mp, self.area)
+    }
+    fn area(&self) -> f64 {
+        self.area
+    }
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.bvh.bounding_box(time0, time1)
+    }
+    fn pdf_value(&self, o: Point3, v: Vec3) -> f64 {
+        area_pdf_value(self, o, v, self.area)
+    }
+    fn random(&self, o: Vec3) -> Vec3 {
+        //三角形全都退化的时候面积是0，随便挑一个
+        if self.area <= 0. {
+            let i = rand::thread_rng().gen_range(0..self.triangles.len());
+            return self.triangles[i].random(o);
+        }
+        let pick = rand::thread_rng().gen_range(0.0..self.area);
+        let i = self
+            .area_cdf
+            .partition_point(|a| *a <= pick)
+            .min(self.triangles.len() - 1);
+        self.triangles[i].random(o)
+    }
+}
@@ -0,0 +1,102 @@
+use super::vec3::{Point3, Vec3};
+use crate::perlin::Perlin;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+const POINT_COUNT: usize = 256;
+
+//Worley（cellular）噪声：空间分成单位格子，每格里随机放一个特征点，
+//值是到最近（F1）和第二近（F2）特征点的距离
+pub struct Worley {
+    pub offset: [Vec3; POINT_COUNT], //特征点在格子里的位置
+    pub perm_x: [usize; POINT_COUNT],
+    pub perm_y: [usize; POINT_COUNT],
+    pub perm_z: [usize; POINT_COUNT],
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Worley {
+    //和Perlin一样默认种子是0
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut offset = [Vec3::new(0., 0., 0.); POINT_COUNT];
+        for o in offset.iter_mut() {
+            *o = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+        }
+        let mut perm_x = [0; POINT_COUNT];
+        let mut perm_y = [0; POINT_COUNT];
+        let mut perm_z = [0; POINT_COUNT];
+        Perlin::perline_generate_perm(&mut perm_x, &mut rng);
+        Perlin::perline_generate_perm(&mut perm_y, &mut rng);
+        Perlin::perline_generate_perm(&mut perm_z, &mut rng);
+        Self {
+            offset,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+    fn feature_point(&self, i: i32, j: i32, k: i32) -> Point3 {
+        let h = self.perm_x[(i & 255) as usize]
+            ^ self.perm_y[(j & 255) as usize]
+            ^ self.perm_z[(k & 255) as usize];
+        Point3::new(i as f64, j as f64, k as f64) + self.offset[h]
+    }
+    //(F1, F2)，特征点只会在周围3x3x3个格子里
+    pub fn noise(&self, p: Point3) -> (f64, f64) {
+        let i = p.x.floor() as i32;
+        let j = p.y.floor() as i32;
+        let k = p.z.floor() as i32;
+        let mut f1 = f64::INFINITY;
+        let mut f2 = f64::INFINITY;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let d = (self.feature_point(i + di, j + dj, k + dk) - p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_noise() {
+        let (a, b, c) = (
+            Worley::with_seed(7),
+            Worley::with_seed(7),
+            Worley::with_seed(8),
+        );
+        let zero = (Worley::new(), Worley::with_seed(0));
+        let mut differ = 0;
+        for i in 0..64 {
+            let i = i as f64;
+            let p = Point3::new(0.37 * i - 5.1, 1.13 * i + 0.21, -0.71 * i + 2.9);
+            let (f1, f2) = a.noise(p);
+            assert!(f1 <= f2);
+            assert!((f1, f2) == b.noise(p));
+            assert!(zero.0.noise(p) == zero.1.noise(p));
+            if (f1, f2) != c.noise(p) {
+                differ += 1;
+            }
+        }
+        assert!(differ > 60, "{}", differ);
+    }
+}